@@ -1,9 +1,10 @@
-use std::collections::HashMap;
-use time::{Date, Month};
+use time::{Date, Month, OffsetDateTime};
 
 use h_bank::contracts::health_data_contract::*;
 use h_bank::contracts::structs_enums::*;
-use h_bank::persons::{Individual, Corporation};
+use h_bank::contracts::signatures::SigningKey;
+use h_bank::persons::{Individual, Corporation, Consent, PseudonymService};
+use h_bank::finance::money::{Currency, Money};
 
 fn main() {
     // Create individuals
@@ -14,6 +15,11 @@ fn main() {
     );
     individual_originator.add_hla_profile(vec!["A02:01", "B07:02", "C01:02"]);
     individual_originator.add_blood_type("A+");
+    individual_originator.set_consent(Consent::new(
+        vec![DataCategory::Hla, DataCategory::Labs],
+        vec![DataUsePurpose::Storage, DataUsePurpose::Research],
+        vec![PartyType::DataRecipient],
+    ));
 
     let mut individual_donor = Individual::new(
         "Jane Smith".to_string(),
@@ -62,91 +68,102 @@ fn main() {
         EntityId("C-HB123".to_string())
     );
 
+    // Contracts are public, so the originator takes part under a pseudonym issued by HBank instead of their person_id.
+    // Demo secret only; HBank keeps its real secret private, along with the key that resolves pseudonyms to person_ids.
+    let (mut pseudonyms, _resolution_key) = PseudonymService::new(corp_hbank.get_person_id().clone(), [7; 32]);
+    if let Err(e) = pseudonyms.issue(&individual_originator, OffsetDateTime::now_utc()) {
+        eprintln!("{}", e);
+        return;
+    }
+
     // Create contract parties
-    let originator = Party::DataOriginator(PartyInfo { name: individual_originator.get_name().to_string(), entity_id: individual_originator.get_person_id().clone() });
-    let donor = Party::Donor(PartyInfo { name: individual_donor.get_name().to_string(), entity_id: individual_donor.get_person_id().clone() });
-    let funder = Party::Funder(PartyInfo { name: individual_funder.get_name().to_string(), entity_id: individual_funder.get_person_id().clone() });
-    let custodian = Party::DataCustodian(PartyInfo { name: corp_custodian.get_name().to_string(), entity_id: corp_custodian.get_person_id().clone() });
-    let recipient = Party::DataRecipient(PartyInfo { name: corp_recipient.get_name().to_string(), entity_id: corp_recipient.get_person_id().clone() });
-    let consultant = Party::DataConsultant(PartyInfo { name: corp_consultant.get_name().to_string(), entity_id: corp_consultant.get_person_id().clone() });
-    let generator = Party::DataGenerator(PartyInfo { name: corp_generator.get_name().to_string(), entity_id: corp_generator.get_person_id().clone() });
-    let advertiser = Party::Advertiser(PartyInfo { name: corp_advertiser.get_name().to_string(), entity_id: corp_advertiser.get_person_id().clone() });
+    let originator = match pseudonyms.party(individual_originator.get_person_id(), PartyType::DataOriginator) {
+        Ok(party) => party,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
     let hbank = Party::HBank(PartyInfo { name: corp_hbank.get_name().to_string(), entity_id: corp_hbank.get_person_id().clone() });
 
     // Define contract details
     let contract_category = ContractCategory::TwoParty(TwoPartyLegalStructure::Storage_or_Exchange(
         StorageExchangeLegalStructure::AgentStorageAgreement { 
-            agent: custodian.clone(), 
+            agent: originator.clone(), 
             h_bank: hbank.clone() 
         }
     ));
-    let legal_framework = ContractLegalFramework::CommonLaw;
-    let generator_rate_spec = GeneratorRateSpecification::KnowledgeRate(0.05);
-    let individual_contribution_level = IndividualContributionLevel::DataOnly;
-    let contract_id = "C-001".to_string();
-    let cohort_id = Some("CH-001".to_string());
-    let privacy_level = DataPrivacyLevel::HIPPA_deidentified;
-
-    // let parties_to_add = vec![
-    //     originator.clone(), donor.clone(), funder.clone(), custodian.clone(),
-    //     recipient.clone(), consultant.clone(), generator.clone(), advertiser.clone(), hbank.clone(),
-    // ];
+
     let parties_to_add = vec![
         originator.clone(), hbank.clone(),
     ];
 
-    // Create HashMaps for individuals and corporations
-    let mut individuals_map: HashMap<EntityId, Individual> = HashMap::new();
-    individuals_map.insert(individual_originator.get_person_id().clone(), individual_originator);
-    individuals_map.insert(individual_donor.get_person_id().clone(), individual_donor);
-    individuals_map.insert(individual_funder.get_person_id().clone(), individual_funder);
-
-    let mut corporations_map: HashMap<EntityId, Corporation> = HashMap::new();
-    corporations_map.insert(corp_custodian.get_person_id().clone(), corp_custodian);
-    corporations_map.insert(corp_recipient.get_person_id().clone(), corp_recipient);
-    corporations_map.insert(corp_consultant.get_person_id().clone(), corp_consultant);
-    corporations_map.insert(corp_generator.get_person_id().clone(), corp_generator);
-    corporations_map.insert(corp_advertiser.get_person_id().clone(), corp_advertiser);
-    corporations_map.insert(corp_hbank.get_person_id().clone(), corp_hbank);
-
-    // Create default Terms of the contract
-    let contract_terms = Terms::default();
-
     let residual_payees = Residuals {
         Beneficiaries: vec![
             originator.clone(),
         ],
-        DisbursementSchedule: "Annually".to_string(),
+        DisbursementSchedule: DisbursementSchedule {
+            frequency: DisbursementFrequency::Annually,
+            start_date: Date::from_calendar_date(2025, Month::January, 1).unwrap(),
+            end_date: None,
+        },
+        Amount: ResidualAmount::FixedPerDisbursement(Money::new(10_000, Currency::usd())),
     };
 
-    // Create the contract
-    let mut contract = HealthDataContract::new(
-        vec![],
-        contract_category,
-        legal_framework,
-        contract_terms,
-        None, //Some(generator_rate_spec),
-        Some(residual_payees),
-        Some(individual_contribution_level),
-        true,
-        Some(true),
-        contract_id,
-        cohort_id,
-        privacy_level,
-        individuals_map,
-        corporations_map,
-    );
+    // Demo keys only; in practice each party signs with its own Ed25519 key and registers the public half.
+    let signing_keys: Vec<(EntityId, SigningKey)> = parties_to_add.iter().enumerate()
+        .map(|(i, party)| (party.entity_id().clone(), SigningKey::from_bytes(&[i as u8 + 1; 32])))
+        .collect();
+
+    // Create the contract. Terms only name the data categories stored; generator_rate is left unset since no generator is a party.
+    let mut builder = HealthDataContract::builder("C-001", contract_category);
+    for (entity_id, signing_key) in &signing_keys {
+        builder = builder.party_key(entity_id.clone(), &signing_key.verifying_key());
+    }
+    let built = builder
+        .parties(parties_to_add)
+        .legal_framework(ContractLegalFramework::CommonLaw)
+        .terms(Terms { data_categories: vec![DataCategory::Hla, DataCategory::Labs], ..Terms::default() })
+        .residual_payments(residual_payees)
+        .individual_contribution_level(IndividualContributionLevel::DataOnly)
+        .irb_required(true)
+        .irb_approved(true)
+        .cohort_id("CH-001")
+        .privacy_level(DataPrivacyLevel::SafeHarbor)
+        .individual_as(originator.entity_id().clone(), individual_originator)
+        .individuals([individual_donor, individual_funder])
+        .corporations([corp_custodian, corp_recipient, corp_consultant, corp_generator, corp_advertiser, corp_hbank])
+        .build();
+
+    let mut contract = match built {
+        Ok(contract) => contract,
+        Err(report) => {
+            eprintln!("Contract could not be built: {}", report);
+            return;
+        }
+    };
 
-    // Add parties
-    if let Err(e) = contract.add_parties(parties_to_add) {
-        eprintln!("Error adding parties: {}", e);
+    // Move the contract through its lifecycle: propose, collect every party's signature, sign, then validate and execute
+    if let Err(e) = contract.propose() {
+        eprintln!("Contract could not be proposed: {}", e);
         return;
     }
 
-    // Validate and execute contract
+    for (entity_id, signing_key) in &signing_keys {
+        if let Err(e) = contract.sign_as(entity_id, signing_key) {
+            eprintln!("{}", e);
+            return;
+        }
+    }
+    println!("Contract {} content hash: {}", contract.get_contract_id(), contract.content_hash());
+
+    if let Err(e) = contract.sign() {
+        eprintln!("Contract could not be signed: {}", e);
+        return;
+    }
     if let Err(e) = contract.validate_and_execute_contract() {
         eprintln!("Contract validation failed: {}", e);
     } else {
-        println!("Contract validated and executed successfully.");
+        println!("Contract validated and executed successfully. Status: {}", contract.get_status());
     }
-}
\ No newline at end of file
+}
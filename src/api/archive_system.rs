@@ -4,6 +4,7 @@ use std::sync::RwLock;
 
 use super::shared_models::CodeSubmission;
 
+// Code submissions archived by HBankInterface once their analysis results are in.
 pub struct ArchiveSystem {
     archives: RwLock<HashMap<String, CodeSubmission>>,
 }
@@ -1,10 +1,16 @@
 use std::collections::HashMap;
 use std::sync::RwLock;
+use time::OffsetDateTime;
 use uuid::Uuid;
 use crate::api::shared_models::CodeSubmission;
 
+struct StoredSubmission {
+    submission: CodeSubmission,
+    submitted_at: OffsetDateTime,
+}
+
 pub struct CodeStorage {
-    submissions: RwLock<HashMap<String, CodeSubmission>>,
+    submissions: RwLock<HashMap<String, StoredSubmission>>,
 }
 
 impl CodeStorage {
@@ -17,12 +23,26 @@ impl CodeStorage {
     pub fn store_submission(&self, submission: CodeSubmission) -> String {
         let job_id = Uuid::new_v4().to_string();
         let mut submissions = self.submissions.write().unwrap();
-        submissions.insert(job_id.clone(), submission);
+        submissions.insert(job_id.clone(), StoredSubmission { submission, submitted_at: OffsetDateTime::now_utc() });
         job_id
     }
 
     pub fn get_submission(&self, job_id: &str) -> Option<CodeSubmission> {
         let submissions = self.submissions.read().unwrap();
-        submissions.get(job_id).cloned()
+        submissions.get(job_id).map(|stored| stored.submission.clone())
+    }
+
+    pub fn get_submitted_at(&self, job_id: &str) -> Option<OffsetDateTime> {
+        let submissions = self.submissions.read().unwrap();
+        submissions.get(job_id).map(|stored| stored.submitted_at)
+    }
+
+    // (job_id, submission) for every job run against the given cohort.
+    pub fn jobs_for_cohort(&self, cohort_id: &str) -> Vec<(String, CodeSubmission)> {
+        let submissions = self.submissions.read().unwrap();
+        submissions.iter()
+            .filter(|(_, stored)| stored.submission.cohort_id == cohort_id)
+            .map(|(job_id, stored)| (job_id.clone(), stored.submission.clone()))
+            .collect()
     }
 }
\ No newline at end of file
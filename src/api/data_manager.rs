@@ -1,9 +1,11 @@
 use std::sync::RwLock;
 use std::collections::{HashMap, HashSet};
-use crate::api::shared_models::AnalysisResult;
+use crate::api::shared_models::{AnalysisResult, RevocationFlag};
 
 pub struct DataManager {
     analysis_results: RwLock<HashMap<String, AnalysisResult>>,
+    // Not checked yet; the server app will register and validate its id here.
+    #[allow(dead_code)]
     valid_server_app_ids: RwLock<HashSet<String>>,
 }
 
@@ -26,12 +28,28 @@ impl DataManager {
         results.get(job_id).cloned()
     }
 
+    // Flags a stored result whose inputs included data that has since been withdrawn. Returns whether a result was flagged.
+    pub fn flag_analysis_result(&self, job_id: &str, flag: RevocationFlag) -> Result<bool, String> {
+        let mut results = self.analysis_results.write().map_err(|e| e.to_string())?;
+        match results.get_mut(job_id) {
+            Some(result) => {
+                if !result.revocation_flags.contains(&flag) {
+                    result.revocation_flags.push(flag);
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    #[allow(dead_code)]
     pub fn validate_server_app_id(&self, server_app_id: &str) -> bool {
         let valid_ids = self.valid_server_app_ids.read().unwrap();
         valid_ids.contains(server_app_id)
     }
 
     // Method to add valid server app IDs (you might want to make this more secure in a real-world scenario)
+    #[allow(dead_code)]
     pub fn add_valid_server_app_id(&self, server_app_id: String) {
         let mut valid_ids = self.valid_server_app_ids.write().unwrap();
         valid_ids.insert(server_app_id);
@@ -1,4 +1,6 @@
 use std::path::PathBuf;
+use std::sync::RwLock;
+use time::{Date, OffsetDateTime};
 use super::data_manager::DataManager;
 use super::archive_system::ArchiveSystem;
 use super::code_storage::CodeStorage;
@@ -6,6 +8,13 @@ use crate::data_management::cohort_manager::CohortManager;
 use crate::data_management::synthetic_data_generator::SyntheticDataGenerator;
 use super::shared_models::*;
 use crate::api_prelude::CohortSummary;
+use crate::contracts::serialization::contract_json_schema;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::contracts::structs_enums::{AgreementKind, DataPrivacyLevel, EntityId};
+use crate::data_management::cohort_manager::RevocationOutcome;
+use crate::data_management::deidentification::SafeHarborDeidentifier;
+use crate::data_management::anonymity::AnonymityPolicy;
+use crate::finance::subscriptions::{AccessChange, BillingEvent, Subscriptions, UsageKind};
 
 /*
 The HBankInterface is meant to be imported and used by HBroker-ServerApp
@@ -13,10 +22,12 @@ The HBankInterface is meant to be imported and used by HBroker-ServerApp
 
 pub struct HBankInterface {
     data_manager: DataManager,
+    // Submissions of jobs whose results are in.
     archive_system: ArchiveSystem,
     code_storage: CodeStorage,
     cohort_manager: CohortManager,
     synthetic_data_generator: SyntheticDataGenerator,
+    subscriptions: RwLock<Subscriptions>,
 }
 
 impl HBankInterface {
@@ -27,11 +38,149 @@ impl HBankInterface {
             code_storage: CodeStorage::new(),
             cohort_manager: CohortManager::new(),
             synthetic_data_generator: SyntheticDataGenerator::new(base_data_path),
+            subscriptions: RwLock::new(Subscriptions::new()),
         }
     }
 
-    pub fn submit_code(&self, submission: CodeSubmission) -> Result<String, String> {
-        Ok(self.code_storage.store_submission(submission))
+    pub fn create_cohort(&mut self, cohort_id: String, privacy_level: DataPrivacyLevel) -> Result<(), String> {
+        self.cohort_manager.create_cohort(cohort_id, privacy_level)
+    }
+
+    pub fn add_contract_to_cohort(&mut self, cohort_id: &str, contract: HealthDataContract) -> Result<(), String> {
+        self.cohort_manager.add_contract_to_cohort(cohort_id, contract)
+    }
+
+    // Sets the k-anonymity and l-diversity targets for releases under one of a cohort's contracts.
+    pub fn set_anonymity_policy(&mut self, cohort_id: &str, contract_id: &str, policy: AnonymityPolicy) -> Result<(), String> {
+        self.cohort_manager.set_anonymity_policy(cohort_id, contract_id, policy)
+    }
+
+    /*
+    A job may only read data from originators who still consent. Jobs naming a revoked (or unknown) participant are rejected;
+    jobs that name nobody are pinned to the cohort's consenting participants at submission time.
+    A job may only release its results at the cohort's release level (the strictest of the cohort's level and the levels
+    of its contracts) or a stricter one.
+    A job run under a contract needs that contract to be in force; under a SubscriptionAgreement it is also metered as
+    a job run, and refused while the subscription is suspended for an overdue bill.
+    */
+    pub fn submit_code(&self, mut submission: CodeSubmission) -> Result<String, String> {
+        let today = OffsetDateTime::now_utc().date();
+        let contract = match &submission.contract_id {
+            Some(contract_id) => Some(self.cohort_manager.get_contract(&submission.cohort_id, contract_id)?),
+            None => None,
+        };
+        let subscription = contract.filter(|c| c.get_agreement_type().kind() == AgreementKind::SubscriptionAgreement);
+        match (contract, subscription) {
+            (_, Some(subscription)) => self.subscriptions.read().map_err(|e| e.to_string())?.check_access(subscription, today).map_err(|e| e.to_string())?,
+            (Some(contract), None) if !contract.is_active() || !contract.is_in_force(today) => {
+                return Err(format!("Contract {} is not in force; no jobs can run under it", contract.get_contract_id()));
+            }
+            _ => {}
+        }
+
+        let cohort_level = self.cohort_manager.get_release_level(&submission.cohort_id)?;
+        let release_level = *submission.privacy_level.get_or_insert(cohort_level);
+        if !cohort_level.permits(release_level) {
+            return Err(format!("Cohort {} releases data at {:?}, which cannot be released at {:?}", submission.cohort_id, cohort_level, release_level));
+        }
+
+        let active = self.cohort_manager.active_participants(&submission.cohort_id)?;
+        let revoked = self.cohort_manager.revoked_participants(&submission.cohort_id)?;
+
+        for entity_id in &submission.entity_ids {
+            if let Some(revocation) = revoked.iter().find(|r| r.entity_id == *entity_id) {
+                return Err(format!("Participant {} revoked consent under contract {}; their data cannot be used", entity_id.0, revocation.contract_id));
+            }
+            if !active.contains(entity_id) {
+                return Err(format!("Participant {} is not covered by a contract in force in cohort {}", entity_id.0, submission.cohort_id));
+            }
+        }
+        if submission.entity_ids.is_empty() {
+            let mut participants: Vec<EntityId> = active.into_iter().collect();
+            participants.sort_by(|a, b| a.0.cmp(&b.0));
+            submission.entity_ids = participants;
+        }
+
+        let job_id = self.code_storage.store_submission(submission);
+        if let Some(subscription) = subscription {
+            let mut subscriptions = self.subscriptions.write().map_err(|e| e.to_string())?;
+            subscriptions.record_usage(subscription, UsageKind::JobRun, today, Some(job_id.clone())).map_err(|e| e.to_string())?;
+        }
+        Ok(job_id)
+    }
+
+    /*
+    Bills a cohort's SubscriptionAgreement for every period ended before on, then suspends it while a bill is overdue
+    and reinstates it once they are all paid. Meant to be run daily for every subscription.
+    */
+    pub fn run_billing_cycle(&mut self, cohort_id: &str, contract_id: &str, on: Date) -> Result<(Vec<BillingEvent>, AccessChange), String> {
+        let subscriptions = self.subscriptions.get_mut().map_err(|e| e.to_string())?;
+        self.cohort_manager.update_contract(cohort_id, contract_id, |contract| subscriptions.run_billing_cycle(contract, on))?
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn record_subscription_payment(&self, event_id: &str, paid_on: Date) -> Result<BillingEvent, String> {
+        let mut subscriptions = self.subscriptions.write().map_err(|e| e.to_string())?;
+        subscriptions.mark_paid(event_id, paid_on).cloned().map_err(|e| e.to_string())
+    }
+
+    pub fn get_subscription_bills(&self, contract_id: &str) -> Result<Vec<BillingEvent>, String> {
+        let subscriptions = self.subscriptions.read().map_err(|e| e.to_string())?;
+        Ok(subscriptions.for_contract(contract_id).cloned().collect())
+    }
+
+    /*
+    Releases patient records from a cohort, under one of its contracts in force, at the cohort's release level (the
+    strictest of the cohort's level and the levels of its contracts). SafeHarbor releases only ever return the output
+    of the SafeHarborDeidentifier, with its report; a mapping that names no identifier columns is refused rather than
+    taken to mean the table holds none. Synthetic-only and aggregate-only releases never return records.
+    Ages are de-identified as of the year of the release.
+    Under the agreement types in RELEASE_CHECKED_AGREEMENTS the released table must also meet the anonymity policy
+    HBank set for the contract; a release under a contract without one, or whose table misses k or l, is blocked.
+    */
+    pub fn release_dataset(&self, release: DatasetRelease) -> Result<ReleasedDataset, String> {
+        let privacy_level = self.cohort_manager.get_release_level(&release.cohort_id)?;
+        let contract = self.cohort_manager.get_contract(&release.cohort_id, &release.contract_id)?;
+        let today = OffsetDateTime::now_utc().date();
+        if !contract.is_in_force(today) {
+            return Err(format!("Contract {} is not in force; no data can be released under it", release.contract_id));
+        }
+
+        let (table, deidentification) = match privacy_level {
+            DataPrivacyLevel::SafeHarbor => {
+                if release.safe_harbor_mapping.is_empty() {
+                    return Err(format!("Cohort {} releases at SafeHarbor; the columns holding identifiers must be mapped", release.cohort_id));
+                }
+                let deidentifier = SafeHarborDeidentifier::new(release.safe_harbor_mapping, today.year());
+                let (table, report) = deidentifier.deidentify(&release.table).map_err(|e| e.to_string())?;
+                (table, Some(report))
+            }
+            DataPrivacyLevel::SyntheticOnly | DataPrivacyLevel::AggregateOnly => {
+                return Err(format!("Cohort {} releases at {:?}; patient records cannot be released from it", release.cohort_id, privacy_level));
+            }
+            DataPrivacyLevel::Identified | DataPrivacyLevel::LimitedDataSet | DataPrivacyLevel::ExpertDetermination => (release.table, None),
+        };
+
+        let kind = contract.get_agreement_type().kind();
+        let anonymity = match self.cohort_manager.get_anonymity_policy(&release.cohort_id, &release.contract_id)? {
+            Some(policy) => Some(policy.check_release(contract, &table).map_err(|e| e.to_string())?),
+            None if AnonymityPolicy::requires_release_check(kind) => {
+                return Err(format!(
+                    "Datasets released under a {} must meet k-anonymity and l-diversity targets; no anonymity policy is set for contract {}",
+                    kind, release.contract_id
+                ));
+            }
+            None => None,
+        };
+
+        Ok(ReleasedDataset {
+            cohort_id: release.cohort_id,
+            contract_id: release.contract_id,
+            privacy_level,
+            table,
+            deidentification,
+            anonymity,
+        })
     }
 
     pub fn get_code_submission(&self, job_id: &str) -> Result<CodeSubmission, String> {
@@ -48,9 +197,281 @@ impl HBankInterface {
             .map_err(|e| e.to_string())
     }
 
-    pub fn submit_analysis_result(&self, result: AnalysisResult) -> Result<(), String> {
+    /*
+    Results of jobs submitted before one of their participants revoked consent are stored flagged.
+    The job's submission is archived with its result.
+    */
+    pub fn submit_analysis_result(&self, mut result: AnalysisResult) -> Result<(), String> {
+        if let (Some(submission), Some(submitted_at)) = (self.code_storage.get_submission(&result.job_id), self.code_storage.get_submitted_at(&result.job_id)) {
+            let revoked = self.cohort_manager.revoked_participants(&submission.cohort_id).unwrap_or_default();
+            for revocation in revoked {
+                let flag = RevocationFlag { entity_id: revocation.entity_id.clone(), contract_id: revocation.contract_id.clone(), revoked_at: revocation.revoked_at };
+                if submission.entity_ids.contains(&revocation.entity_id) && revocation.revoked_at >= submitted_at && !result.revocation_flags.contains(&flag) {
+                    result.revocation_flags.push(flag);
+                }
+            }
+            self.archive_system.store_submission(&result.job_id, &submission).map_err(|e| e.to_string())?;
+        }
         self.data_manager.store_analysis_result(&result)
     }
 
+    pub fn get_archived_submission(&self, job_id: &str) -> Result<CodeSubmission, String> {
+        self.archive_system.retrieve_submission(job_id)
+            .ok_or_else(|| "Archived code submission not found".to_string())
+    }
+
+    pub fn get_analysis_result(&self, job_id: &str) -> Result<AnalysisResult, String> {
+        self.data_manager.get_analysis_result(job_id)
+            .ok_or_else(|| "Analysis result not found".to_string())
+    }
+
+    /*
+    Withdraws a DataOriginator from a contract: the contract records the revocation date, the cohort stops counting them,
+    new jobs can no longer use their data, and results already computed from their data are flagged.
+    */
+    pub fn revoke_consent(&mut self, contract_id: &str, entity_id: &EntityId) -> Result<RevocationOutcome, String> {
+        let outcome = self.cohort_manager.revoke_consent(contract_id, entity_id)?;
+        if self.cohort_manager.active_participants(&outcome.cohort_id)?.contains(entity_id) {
+            // Still a participant of the cohort through another contract, so earlier results remain covered by consent.
+            return Ok(outcome);
+        }
+        let revocation = &outcome.revocation;
+        let flag = RevocationFlag { entity_id: revocation.entity_id.clone(), contract_id: revocation.contract_id.clone(), revoked_at: revocation.revoked_at };
+
+        for (job_id, submission) in self.code_storage.jobs_for_cohort(&outcome.cohort_id) {
+            if submission.entity_ids.contains(entity_id) {
+                self.data_manager.flag_analysis_result(&job_id, flag.clone())?;
+            }
+        }
+        Ok(outcome)
+    }
+
+    // The published JSON Schema for contracts exchanged with HBroker.
+    pub fn get_contract_schema(&self) -> serde_json::Value {
+        contract_json_schema()
+    }
+
     // Add other methods as needed...
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use time::Duration;
+
+    use super::*;
+    use crate::contracts::*;
+    use crate::data_management::anonymity::{Generalization, QuasiIdentifier};
+    use crate::data_management::deidentification::{SafeHarborIdentifier, Table};
+    use crate::finance::money::{Currency, Money};
+    use crate::persons::consent::Consent;
+    use crate::test_support::sale;
+
+    fn submission(contract_id: &str) -> CodeSubmission {
+        CodeSubmission {
+            cohort_id: "CH-SUB".to_string(),
+            wasm_code: Vec::new(),
+            entry_point: "main".to_string(),
+            data_dir: PathBuf::from("data"),
+            execution_mode: ExecutionMode::Remote,
+            entity_ids: Vec::new(),
+            privacy_level: None,
+            contract_id: Some(contract_id.to_string()),
+        }
+    }
+
+    // A SafeHarbor subscription on a daily plan whose bills are due the day they are issued, so a bill is overdue two
+    // days after the contract starts.
+    fn subscription_contract() -> HealthDataContract {
+        let usd = |amount_minor| Money::new(amount_minor, Currency::usd());
+        let plan = SubscriptionPlan {
+            tier: "daily".to_string(),
+            price: usd(1_000),
+            period: TermLength::Days(1),
+            included_job_runs: 10,
+            included_data_refreshes: 0,
+            overage_per_job_run: usd(100),
+            overage_per_data_refresh: usd(0),
+            payment_terms_days: 0,
+        };
+        sale()
+            .kind(AgreementKind::SubscriptionAgreement)
+            .contract_id("C-SUB-001")
+            .terms(Terms { subscription: Some(plan), ..Terms::default() })
+            .executed()
+    }
+
+    fn subscribed_interface() -> HBankInterface {
+        let mut interface = HBankInterface::new(PathBuf::from("data"));
+        interface.create_cohort("CH-SUB".to_string(), DataPrivacyLevel::SafeHarbor).unwrap();
+        interface.add_contract_to_cohort("CH-SUB", subscription_contract()).unwrap();
+        interface
+    }
+
+    #[test]
+    fn jobs_under_a_subscription_are_metered() {
+        let interface = subscribed_interface();
+        let job_id = interface.submit_code(submission("C-SUB-001")).unwrap();
+        let subscriptions = interface.subscriptions.read().unwrap();
+        let today = OffsetDateTime::now_utc().date();
+        assert_eq!(subscriptions.usage_between("C-SUB-001", UsageKind::JobRun, today, today), 1);
+        assert_eq!(interface.get_code_submission(&job_id).unwrap().contract_id.as_deref(), Some("C-SUB-001"));
+    }
+
+    #[test]
+    fn overdue_bill_blocks_jobs_until_paid() {
+        let mut interface = subscribed_interface();
+        let today = OffsetDateTime::now_utc().date();
+
+        let (billed, access) = interface.run_billing_cycle("CH-SUB", "C-SUB-001", today + Duration::days(2)).unwrap();
+        assert_eq!(billed.len(), 2);
+        assert!(matches!(access, AccessChange::Suspended { .. }));
+        let err = interface.submit_code(submission("C-SUB-001")).unwrap_err();
+        assert!(err.contains("suspended"), "{}", err);
+
+        for bill in &billed {
+            interface.record_subscription_payment(&bill.event_id, today + Duration::days(2)).unwrap();
+        }
+        let (billed, access) = interface.run_billing_cycle("CH-SUB", "C-SUB-001", today + Duration::days(3)).unwrap();
+        assert_eq!(access, AccessChange::Reinstated);
+        // The day the contract spent suspended is not billed.
+        assert!(billed.is_empty());
+        assert_eq!(interface.get_subscription_bills("C-SUB-001").unwrap().len(), 2);
+        assert!(interface.submit_code(submission("C-SUB-001")).is_ok());
+    }
+
+    #[test]
+    fn jobs_need_the_named_contract_in_the_cohort() {
+        let interface = subscribed_interface();
+        assert!(interface.submit_code(submission("C-OTHER")).is_err());
+    }
+
+    #[test]
+    fn jobs_release_at_the_cohort_level_or_stricter() {
+        let mut interface = HBankInterface::new(PathBuf::from("data"));
+        interface.create_cohort("CH-LDS".to_string(), DataPrivacyLevel::LimitedDataSet).unwrap();
+        let release_at = |privacy_level| CodeSubmission { cohort_id: "CH-LDS".to_string(), privacy_level: Some(privacy_level), contract_id: None, ..submission("") };
+
+        assert!(interface.submit_code(release_at(DataPrivacyLevel::LimitedDataSet)).is_ok());
+        assert!(interface.submit_code(release_at(DataPrivacyLevel::SafeHarbor)).is_ok());
+        assert!(interface.submit_code(release_at(DataPrivacyLevel::Identified)).is_err());
+
+        interface.create_cohort("CH-SH".to_string(), DataPrivacyLevel::SafeHarbor).unwrap();
+        let expert = CodeSubmission { cohort_id: "CH-SH".to_string(), ..release_at(DataPrivacyLevel::ExpertDetermination) };
+        assert!(interface.submit_code(expert).is_err());
+    }
+
+    #[test]
+    fn safe_harbor_contract_in_a_limited_data_set_cohort_is_released_at_safe_harbor() {
+        let mut interface = HBankInterface::new(PathBuf::from("data"));
+        interface.create_cohort("CH-LDS".to_string(), DataPrivacyLevel::LimitedDataSet).unwrap();
+        interface.add_contract_to_cohort("CH-LDS", subscription_contract()).unwrap();
+
+        let table = Table::new(vec!["name".to_string(), "zip".to_string(), "hba1c".to_string()], vec![vec!["Ada Lovelace".to_string(), "02139".to_string(), "6.1".to_string()]]);
+        let release = |safe_harbor_mapping| DatasetRelease {
+            cohort_id: "CH-LDS".to_string(),
+            contract_id: "C-SUB-001".to_string(),
+            table: table.clone(),
+            safe_harbor_mapping,
+        };
+        let err = interface.release_dataset(release(HashMap::new())).unwrap_err();
+        assert!(err.contains("SafeHarbor"), "{}", err);
+
+        let mapping = HashMap::from([("name".to_string(), SafeHarborIdentifier::Name), ("zip".to_string(), SafeHarborIdentifier::ZipCode)]);
+        let released = interface.release_dataset(release(mapping)).unwrap();
+        assert_eq!(released.privacy_level, DataPrivacyLevel::SafeHarbor);
+        assert_eq!(released.table, Table::new(vec!["zip".to_string(), "hba1c".to_string()], vec![vec!["021".to_string(), "6.1".to_string()]]));
+        assert!(released.deidentification.is_some());
+
+        // Jobs release at the contract's level by default and cannot ask for the cohort's looser one.
+        let job = CodeSubmission { cohort_id: "CH-LDS".to_string(), ..submission("C-SUB-001") };
+        let job_id = interface.submit_code(job.clone()).unwrap();
+        assert_eq!(interface.get_code_submission(&job_id).unwrap().privacy_level, Some(DataPrivacyLevel::SafeHarbor));
+        assert!(interface.submit_code(CodeSubmission { privacy_level: Some(DataPrivacyLevel::LimitedDataSet), ..job }).is_err());
+    }
+
+    // An executed DirectSale of lab results by the DataOriginator behind the pseudonym, who consents to it.
+    fn originator_sale(contract_id: &str, pseudonym: &str) -> HealthDataContract {
+        sale()
+            .contract_id(contract_id)
+            .originator(pseudonym, Consent::new(vec![DataCategory::Labs], vec![DataUsePurpose::Commercial], vec![PartyType::DataRecipient]))
+            .terms(Terms { data_categories: vec![DataCategory::Labs], ..Terms::default() })
+            .executed()
+    }
+
+    #[test]
+    fn direct_sale_releases_are_checked_against_the_policy_hbank_set() {
+        let mut interface = HBankInterface::new(PathBuf::from("data"));
+        interface.create_cohort("CH-LAB".to_string(), DataPrivacyLevel::SafeHarbor).unwrap();
+        interface.add_contract_to_cohort("CH-LAB", originator_sale("C-DS-001", "PSN-00000000000000000000000000000001")).unwrap();
+        let table = |ages: [&str; 4]| Table::new(
+            vec!["name".to_string(), "age".to_string(), "diagnosis".to_string()],
+            ages.iter().zip(["asthma", "copd", "asthma", "copd"]).map(|(age, diagnosis)| vec!["Ada Lovelace".to_string(), age.to_string(), diagnosis.to_string()]).collect(),
+        );
+        let release = |table| DatasetRelease {
+            cohort_id: "CH-LAB".to_string(),
+            contract_id: "C-DS-001".to_string(),
+            table,
+            safe_harbor_mapping: HashMap::from([("name".to_string(), SafeHarborIdentifier::Name)]),
+        };
+        let err = interface.release_dataset(release(table(["31", "34", "36", "38"]))).unwrap_err();
+        assert!(err.contains("no anonymity policy is set"), "{}", err);
+
+        let policy = |k, l| AnonymityPolicy {
+            k,
+            l,
+            quasi_identifiers: vec![QuasiIdentifier { column: "age".to_string(), generalization: Generalization::NumericBands(vec![10]) }],
+            sensitive_attributes: vec!["diagnosis".to_string()],
+            max_suppressed_rows: 0,
+        };
+        assert!(interface.set_anonymity_policy("CH-LAB", "C-DS-001", policy(1, 1)).is_err());
+        assert!(interface.set_anonymity_policy("CH-LAB", "C-OTHER", policy(2, 2)).is_err());
+        interface.set_anonymity_policy("CH-LAB", "C-DS-001", policy(2, 2)).unwrap();
+
+        let err = interface.release_dataset(release(table(["31", "34", "36", "38"]))).unwrap_err();
+        assert!(err.contains("cannot be released under contract C-DS-001"), "{}", err);
+        let released = interface.release_dataset(release(table(["30-39"; 4]))).unwrap();
+        assert!(released.anonymity.unwrap().is_satisfied());
+    }
+
+    fn analysis_result(job_id: &str) -> AnalysisResult {
+        AnalysisResult { job_id: job_id.to_string(), status: "done".to_string(), result: None, error: None, revocation_flags: Vec::new() }
+    }
+
+    #[test]
+    fn revoking_consent_cascades_through_the_cohort_and_its_jobs() {
+        let withdrawing = EntityId("PSN-00000000000000000000000000000001".to_string());
+        let staying = EntityId("PSN-00000000000000000000000000000002".to_string());
+        let mut interface = HBankInterface::new(PathBuf::from("data"));
+        interface.create_cohort("CH-LAB".to_string(), DataPrivacyLevel::SafeHarbor).unwrap();
+        interface.add_contract_to_cohort("CH-LAB", originator_sale("C-DS-001", &withdrawing.0)).unwrap();
+        interface.add_contract_to_cohort("CH-LAB", originator_sale("C-DS-002", &staying.0)).unwrap();
+        let job = || CodeSubmission { cohort_id: "CH-LAB".to_string(), contract_id: None, ..submission("") };
+
+        let finished = interface.submit_code(job()).unwrap();
+        interface.submit_analysis_result(analysis_result(&finished)).unwrap();
+        let running = interface.submit_code(job()).unwrap();
+        assert_eq!(interface.get_code_submission(&finished).unwrap().entity_ids, vec![withdrawing.clone(), staying.clone()]);
+        // Only jobs whose results are in are archived.
+        assert_eq!(interface.get_archived_submission(&finished).unwrap().entity_ids, vec![withdrawing.clone(), staying.clone()]);
+        assert!(interface.get_archived_submission(&running).is_err());
+
+        let outcome = interface.revoke_consent("C-DS-001", &withdrawing).unwrap();
+        assert_eq!(outcome.total_participants, 1);
+        assert_eq!(interface.get_cohort_summary("CH-LAB").unwrap().total_participants, 1);
+
+        // Results already in are flagged now; results still to come are flagged when they arrive.
+        let flags = interface.get_analysis_result(&finished).unwrap().revocation_flags;
+        assert_eq!(flags.len(), 1);
+        assert_eq!((&flags[0].entity_id, flags[0].contract_id.as_str(), flags[0].revoked_at), (&withdrawing, "C-DS-001", outcome.revocation.revoked_at));
+        interface.submit_analysis_result(analysis_result(&running)).unwrap();
+        assert_eq!(interface.get_analysis_result(&running).unwrap().revocation_flags, flags);
+
+        let later = interface.submit_code(job()).unwrap();
+        assert_eq!(interface.get_code_submission(&later).unwrap().entity_ids, vec![staying.clone()]);
+        interface.submit_analysis_result(analysis_result(&later)).unwrap();
+        assert!(interface.get_analysis_result(&later).unwrap().revocation_flags.is_empty());
+        let err = interface.submit_code(CodeSubmission { entity_ids: vec![withdrawing.clone()], ..job() }).unwrap_err();
+        assert!(err.contains("revoked consent"), "{}", err);
+    }
+}
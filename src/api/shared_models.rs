@@ -1,5 +1,10 @@
 use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use time::OffsetDateTime;
+use crate::contracts::structs_enums::{DataPrivacyLevel, EntityId};
+use crate::data_management::deidentification::{DeidentificationReport, SafeHarborIdentifier, Table};
+use crate::data_management::anonymity::AnonymityReport;
 
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -16,6 +21,15 @@ pub struct CodeSubmission {
     pub entry_point: String,
     pub data_dir: PathBuf,
     pub execution_mode: ExecutionMode,
+    // The DataOriginators whose data the job reads. Left empty, HBank scopes the job to the cohort's consenting participants.
+    #[serde(default)]
+    pub entity_ids: Vec<EntityId>,
+    // The level the job's results are released at. Left empty, they are released at the cohort's release level.
+    #[serde(default)]
+    pub privacy_level: Option<DataPrivacyLevel>,
+    // The cohort contract the job runs under. Jobs run under a SubscriptionAgreement must name it so they are metered.
+    #[serde(default)]
+    pub contract_id: Option<String>,
 }
 
 
@@ -25,6 +39,40 @@ pub struct AnalysisResult {
     pub status: String,
     pub result: Option<String>,
     pub error: Option<String>,
+    // Set when an originator whose data the job used has since revoked consent.
+    #[serde(default)]
+    pub revocation_flags: Vec<RevocationFlag>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RevocationFlag {
+    pub entity_id: EntityId,
+    pub contract_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub revoked_at: OffsetDateTime,
+}
+
+// Patient records to be released from a cohort under one of its contracts.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DatasetRelease {
+    pub cohort_id: String,
+    pub contract_id: String,
+    pub table: Table,
+    // Which columns hold Safe Harbor identifiers. Required when the cohort releases at SafeHarbor.
+    #[serde(default)]
+    pub safe_harbor_mapping: HashMap<String, SafeHarborIdentifier>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReleasedDataset {
+    pub cohort_id: String,
+    pub contract_id: String,
+    pub privacy_level: DataPrivacyLevel,
+    pub table: Table,
+    // What was done to the table to release it at SafeHarbor.
+    pub deidentification: Option<DeidentificationReport>,
+    // How the released table measured up to the contract's anonymity policy, when it has one.
+    pub anonymity: Option<AnonymityReport>,
 }
 
 // Add any other shared structures here
@@ -4,6 +4,16 @@ Declare the submodules within the contracts module.
 
 pub mod health_data_contract;
 pub mod structs_enums;
+pub mod lifecycle;
+pub mod validation;
+pub mod serialization;
+pub mod builder;
+pub mod role_rules;
+pub mod signatures;
+pub mod amendments;
+pub mod revocation;
+pub mod duration;
+pub mod advertising;
 
 
 /*
@@ -12,4 +22,14 @@ This allows you to expose the entire module at a higher level, making it easier
 of your crate to access all items within that submodule through a single import.
 */
 pub use health_data_contract::*;  // Re-export all public items from health_data_contract
-pub use structs_enums::*;  // Re-export all public items from structs_enums
\ No newline at end of file
+pub use structs_enums::*;  // Re-export all public items from structs_enums
+pub use lifecycle::*;
+pub use validation::*;
+pub use serialization::*;
+pub use builder::*;
+pub use role_rules::*;
+pub use signatures::*;
+pub use amendments::*;
+pub use revocation::*;
+pub use duration::*;
+pub use advertising::*;
\ No newline at end of file
@@ -0,0 +1,419 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::Date;
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::validation::*;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::finance::money::Money;
+use crate::persons::Individual;
+
+/*
+Advertising campaigns run by an Advertiser under an AdvertiserAgreement with HBank.
+
+A campaign has a budget, runs from start_date to end_date (inclusive, within the agreement's term) and targets
+audience segments. A segment is a combination of attributes describing a group of people, never a person:
+    - attributes must be aggregate and free of PHI: postal areas no finer than the 3-digit ZIP prefix, and age bands
+      at least MIN_AGE_BAND_YEARS wide with everyone over 89 grouped as 90 or older,
+    - health attributes (conditions, medications, lab results, blood type, HLA alleles) may only be used when the
+      agreement's data is de-identified (DataPrivacyLevel::is_deidentified); at Identified or LimitedDataSet they are rejected.
+HBank matches segments to people itself. Advertisers only ever see how many opted-in individuals a segment reaches,
+and a segment reaching fewer than MIN_AUDIENCE_SIZE of them is not served at all.
+*/
+pub const MIN_AUDIENCE_SIZE: usize = 50;
+pub const MIN_AGE_BAND_YEARS: u32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AudienceAttribute {
+    // max_age None means "min_age or older".
+    AgeBand { min_age: u32, max_age: Option<u32> },
+    Region(String),
+    PostalPrefix(String),
+    Language(String),
+    Interest(String),
+    Condition(String),
+    Medication(String),
+    LabResult(String),
+    BloodType(String),
+    HlaAllele(String),
+}
+
+impl AudienceAttribute {
+    pub fn is_health_attribute(&self) -> bool {
+        matches!(
+            self,
+            AudienceAttribute::Condition(_) |
+            AudienceAttribute::Medication(_) |
+            AudienceAttribute::LabResult(_) |
+            AudienceAttribute::BloodType(_) |
+            AudienceAttribute::HlaAllele(_)
+        )
+    }
+
+    // Why the attribute would single out individuals, if it would.
+    pub fn identifying_reason(&self) -> Option<String> {
+        match self {
+            AudienceAttribute::PostalPrefix(prefix) if prefix.len() != 3 || !prefix.chars().all(|c| c.is_ascii_digit()) => {
+                Some(format!("postal area {} is not a 3-digit ZIP prefix", prefix))
+            }
+            AudienceAttribute::AgeBand { min_age, max_age: Some(max_age) } => {
+                if max_age < min_age {
+                    Some(format!("age band {}-{} is empty", min_age, max_age))
+                } else if *max_age > 89 {
+                    Some(format!("age band {}-{} singles out ages over 89; use 90 or older", min_age, max_age))
+                } else if max_age - min_age + 1 < MIN_AGE_BAND_YEARS {
+                    Some(format!("age band {}-{} is narrower than {} years", min_age, max_age, MIN_AGE_BAND_YEARS))
+                } else {
+                    None
+                }
+            }
+            AudienceAttribute::AgeBand { min_age, max_age: None } if *min_age > 90 => {
+                Some(format!("age band {} or older singles out ages over 89; use 90 or older", min_age))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudienceSegment {
+    pub name: String,
+    pub attributes: Vec<AudienceAttribute>,
+}
+
+impl AudienceSegment {
+    pub fn uses_health_attributes(&self) -> bool {
+        self.attributes.iter().any(AudienceAttribute::is_health_attribute)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CampaignRequest {
+    pub name: String,
+    pub budget: Money,
+    pub start_date: Date,
+    pub end_date: Date,
+    pub segments: Vec<AudienceSegment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CampaignStatus {
+    Running,
+    Paused,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Campaign {
+    pub campaign_id: String,
+    pub contract_id: String,
+    pub advertiser: EntityId,
+    pub name: String,
+    pub budget: Money,
+    pub spent: Money,
+    pub start_date: Date,
+    pub end_date: Date,
+    pub segments: Vec<AudienceSegment>,
+    pub status: CampaignStatus,
+}
+
+impl Campaign {
+    pub fn remaining_budget(&self) -> Money {
+        Money::new(self.budget.amount_minor - self.spent.amount_minor, self.budget.currency.clone())
+    }
+
+    pub fn is_running_on(&self, on: Date) -> bool {
+        self.status == CampaignStatus::Running && on >= self.start_date && on <= self.end_date
+    }
+}
+
+// The only audience figure an advertiser is given: how many opted-in individuals a segment reaches.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudienceReach {
+    pub campaign_id: String,
+    pub segment: String,
+    pub reach: usize,
+}
+
+#[derive(Debug)]
+pub enum CampaignError {
+    NotAdvertiserAgreement(AgreementKind),
+    Invalid(ValidationReport),
+    UnknownCampaign(String),
+    UnknownSegment { campaign_id: String, segment: String },
+    NotRunning { campaign_id: String, on: Date },
+    BudgetExceeded { campaign_id: String, remaining: Money, requested: Money },
+    InvalidSpend(Money),
+    AudienceTooSmall { campaign_id: String, segment: String },
+}
+
+impl fmt::Display for CampaignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CampaignError::NotAdvertiserAgreement(kind) => write!(f, "Campaign Error: campaigns run under an AdvertiserAgreement, not a {}", kind),
+            CampaignError::Invalid(report) => write!(f, "Campaign Error: {}", report),
+            CampaignError::UnknownCampaign(campaign_id) => write!(f, "Campaign Error: campaign {} not found", campaign_id),
+            CampaignError::UnknownSegment { campaign_id, segment } => write!(f, "Campaign Error: campaign {} has no segment {}", campaign_id, segment),
+            CampaignError::NotRunning { campaign_id, on } => write!(f, "Campaign Error: campaign {} is not running on {}", campaign_id, on),
+            CampaignError::BudgetExceeded { campaign_id, remaining, requested } => {
+                write!(f, "Campaign Error: spending {} would exceed the remaining budget of {} for campaign {}", requested, remaining, campaign_id)
+            }
+            CampaignError::InvalidSpend(amount) => write!(f, "Campaign Error: spend must be a positive amount in the budget's currency (found {})", amount),
+            CampaignError::AudienceTooSmall { campaign_id, segment } => {
+                write!(f, "Campaign Error: segment {} of campaign {} reaches fewer than {} opted-in individuals", segment, campaign_id, MIN_AUDIENCE_SIZE)
+            }
+        }
+    }
+}
+
+// Checks a campaign request against the AdvertiserAgreement it would run under.
+pub fn campaign_report(contract: &HealthDataContract, request: &CampaignRequest) -> ValidationReport {
+    let mut report = ValidationReport::new();
+
+    if request.budget.is_negative() || request.budget.is_zero() {
+        report.error(ValidationCode::CampaignBudgetInvalid, ValidationSubject::Field("campaign.budget".into()), format!("The campaign budget must be positive (found {}).", request.budget));
+    }
+
+    let duration = || ValidationSubject::Field("campaign.end_date".into());
+    if request.end_date < request.start_date {
+        report.error(ValidationCode::CampaignEndsBeforeStart, duration(), format!("The campaign ends on {} but only starts on {}.", request.end_date, request.start_date));
+    } else if !contract.is_in_force(request.start_date) {
+        report.error(ValidationCode::CampaignOutsideContractTerm, duration(), format!("Contract {} is not in force on {}, when the campaign starts.", contract.get_contract_id(), request.start_date));
+    } else if let Some(last_day) = contract.last_day_in_force().filter(|last_day| *last_day < request.end_date) {
+        report.error(ValidationCode::CampaignOutsideContractTerm, duration(), format!("The campaign runs until {} but contract {} ends on {}.", request.end_date, contract.get_contract_id(), last_day));
+    }
+
+    if request.segments.is_empty() {
+        report.error(ValidationCode::RequiredFieldMissing, ValidationSubject::Field("campaign.segments".into()), "A campaign must target at least one audience segment.");
+    }
+    let health_targeting_allowed = contract.get_privacy_level().is_deidentified();
+    for segment in &request.segments {
+        let field = || ValidationSubject::Field(format!("campaign.segments.{}", segment.name));
+        for reason in segment.attributes.iter().filter_map(AudienceAttribute::identifying_reason) {
+            report.error(ValidationCode::TargetingIdentifiesIndividuals, field(), format!("Segment {} is not an aggregate audience: {}.", segment.name, reason));
+        }
+        if segment.uses_health_attributes() && !health_targeting_allowed {
+            report.error(
+                ValidationCode::HealthTargetingNotAllowed,
+                field(),
+                format!("Segment {} targets health attributes, which is not allowed for data at {:?}.", segment.name, contract.get_privacy_level()),
+            );
+        }
+    }
+    report
+}
+
+#[derive(Debug, Default)]
+pub struct Campaigns {
+    campaigns: Vec<Campaign>,
+}
+
+impl Campaigns {
+    pub fn new() -> Self {
+        Campaigns {
+            campaigns: Vec::new(),
+        }
+    }
+
+    pub fn launch(&mut self, contract: &HealthDataContract, request: CampaignRequest) -> Result<&Campaign, CampaignError> {
+        let kind = contract.get_agreement_type().kind();
+        let ContractCategory::TwoParty(TwoPartyLegalStructure::Advertisement(AdLegalStructure::AdvertiserAgreement { advertiser, .. })) = contract.get_agreement_type() else {
+            return Err(CampaignError::NotAdvertiserAgreement(kind));
+        };
+        campaign_report(contract, &request).into_result().map_err(CampaignError::Invalid)?;
+
+        let campaign = Campaign {
+            campaign_id: format!("CMP-{:05}", self.campaigns.len() + 1),
+            contract_id: contract.get_contract_id().to_string(),
+            advertiser: advertiser.entity_id().clone(),
+            name: request.name,
+            spent: Money::zero(request.budget.currency.clone()),
+            budget: request.budget,
+            start_date: request.start_date,
+            end_date: request.end_date,
+            segments: request.segments,
+            status: CampaignStatus::Running,
+        };
+        self.campaigns.push(campaign);
+        Ok(self.campaigns.last().expect("campaign was just launched"))
+    }
+
+    fn get_mut(&mut self, campaign_id: &str) -> Result<&mut Campaign, CampaignError> {
+        self.campaigns.iter_mut().find(|c| c.campaign_id == campaign_id).ok_or_else(|| CampaignError::UnknownCampaign(campaign_id.to_string()))
+    }
+
+    pub fn pause(&mut self, campaign_id: &str) -> Result<(), CampaignError> {
+        self.get_mut(campaign_id)?.status = CampaignStatus::Paused;
+        Ok(())
+    }
+
+    pub fn resume(&mut self, campaign_id: &str) -> Result<(), CampaignError> {
+        self.get_mut(campaign_id)?.status = CampaignStatus::Running;
+        Ok(())
+    }
+
+    // Charges an amount to a running campaign, which may never spend more than its budget.
+    pub fn record_spend(&mut self, campaign_id: &str, amount: Money, on: Date) -> Result<&Campaign, CampaignError> {
+        let campaign = self.get_mut(campaign_id)?;
+        if !campaign.is_running_on(on) {
+            return Err(CampaignError::NotRunning { campaign_id: campaign_id.to_string(), on });
+        }
+        if amount.is_negative() || amount.is_zero() || amount.currency != campaign.budget.currency {
+            return Err(CampaignError::InvalidSpend(amount));
+        }
+        let remaining = campaign.remaining_budget();
+        if amount.amount_minor > remaining.amount_minor {
+            return Err(CampaignError::BudgetExceeded { campaign_id: campaign_id.to_string(), remaining, requested: amount });
+        }
+        campaign.spent.amount_minor += amount.amount_minor;
+        Ok(campaign)
+    }
+
+    /*
+    The reach of a segment among the individuals HBank matched to it. Only those who opted in to advertising are
+    counted, as recorded in their consent, and segments reaching fewer than MIN_AUDIENCE_SIZE of them are withheld.
+    */
+    pub fn reach<'a>(&self, campaign_id: &str, segment: &str, matched: impl IntoIterator<Item = &'a Individual>) -> Result<AudienceReach, CampaignError> {
+        let campaign = self.get(campaign_id).ok_or_else(|| CampaignError::UnknownCampaign(campaign_id.to_string()))?;
+        if !campaign.segments.iter().any(|s| s.name == segment) {
+            return Err(CampaignError::UnknownSegment { campaign_id: campaign_id.to_string(), segment: segment.to_string() });
+        }
+        let reach = matched.into_iter().filter(|individual| individual.has_opted_in_to_advertising()).count();
+        if reach < MIN_AUDIENCE_SIZE {
+            return Err(CampaignError::AudienceTooSmall { campaign_id: campaign_id.to_string(), segment: segment.to_string() });
+        }
+        Ok(AudienceReach { campaign_id: campaign_id.to_string(), segment: segment.to_string(), reach })
+    }
+
+    pub fn get(&self, campaign_id: &str) -> Option<&Campaign> {
+        self.campaigns.iter().find(|c| c.campaign_id == campaign_id)
+    }
+
+    pub fn all(&self) -> &[Campaign] {
+        &self.campaigns
+    }
+
+    pub fn for_advertiser<'a>(&'a self, advertiser: &'a EntityId) -> impl Iterator<Item = &'a Campaign> {
+        self.campaigns.iter().filter(move |c| c.advertiser == *advertiser)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Duration, Month, OffsetDateTime};
+
+    use super::*;
+    use crate::finance::money::Currency;
+    use crate::test_support::{corporation, execute, party, register_keys, signing_keys};
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    // An executed AdvertiserAgreement between advertiser C-A001 and HBank over data at the given privacy level.
+    fn advertiser_agreement(privacy_level: DataPrivacyLevel) -> HealthDataContract {
+        let advertiser = party(PartyType::Advertiser, "C-A001");
+        let hbank = party(PartyType::HBank, "C-HB001");
+        let keys = signing_keys(&[advertiser.clone(), hbank.clone()]);
+        let category = ContractCategory::TwoParty(TwoPartyLegalStructure::Advertisement(AdLegalStructure::AdvertiserAgreement {
+            advertiser: advertiser.clone(),
+            h_bank: hbank.clone(),
+        }));
+        let builder = HealthDataContract::builder("C-AD-001", category)
+            .parties([advertiser.clone(), hbank.clone()])
+            .legal_framework(ContractLegalFramework::CommonLaw)
+            .privacy_level(privacy_level)
+            .corporations([corporation(&advertiser), corporation(&hbank)]);
+        let mut contract = register_keys(builder, &keys).build().unwrap();
+        execute(&mut contract, &keys);
+        contract
+    }
+
+    fn segment(name: &str, attributes: Vec<AudienceAttribute>) -> AudienceSegment {
+        AudienceSegment { name: name.to_string(), attributes }
+    }
+
+    fn request(segments: Vec<AudienceSegment>) -> CampaignRequest {
+        let today = OffsetDateTime::now_utc().date();
+        CampaignRequest { name: "Flu season".to_string(), budget: usd(10_000), start_date: today, end_date: today + Duration::days(30), segments }
+    }
+
+    fn people(count: usize, opted_in: bool) -> Vec<Individual> {
+        (0..count)
+            .map(|i| {
+                let mut individual = Individual::new(format!("Person {}", i), EntityId(format!("I-P{:03}", i)), Date::from_calendar_date(1980, Month::January, 1).unwrap());
+                if opted_in {
+                    individual.opt_in_to_advertising();
+                }
+                individual
+            })
+            .collect()
+    }
+
+    fn codes(report: &ValidationReport) -> Vec<ValidationCode> {
+        report.errors().map(|issue| issue.code).collect()
+    }
+
+    #[test]
+    fn segments_must_describe_groups_not_people() {
+        let contract = advertiser_agreement(DataPrivacyLevel::SafeHarbor);
+        let aggregate = segment("aggregate", vec![
+            AudienceAttribute::PostalPrefix("021".to_string()),
+            AudienceAttribute::AgeBand { min_age: 30, max_age: Some(34) },
+            AudienceAttribute::AgeBand { min_age: 90, max_age: None },
+        ]);
+        assert!(codes(&campaign_report(&contract, &request(vec![aggregate]))).is_empty());
+
+        for attribute in [
+            AudienceAttribute::PostalPrefix("02139".to_string()),
+            AudienceAttribute::AgeBand { min_age: 30, max_age: Some(33) },
+            AudienceAttribute::AgeBand { min_age: 85, max_age: Some(95) },
+            AudienceAttribute::AgeBand { min_age: 91, max_age: None },
+            AudienceAttribute::AgeBand { min_age: 40, max_age: Some(30) },
+        ] {
+            let report = campaign_report(&contract, &request(vec![segment("narrow", vec![attribute.clone()])]));
+            assert_eq!(codes(&report), vec![ValidationCode::TargetingIdentifiesIndividuals], "{:?}", attribute);
+        }
+    }
+
+    #[test]
+    fn health_targeting_needs_deidentified_data() {
+        let health = || request(vec![segment("diabetics", vec![AudienceAttribute::Condition("Type 2 diabetes".to_string())])]);
+
+        assert!(codes(&campaign_report(&advertiser_agreement(DataPrivacyLevel::SafeHarbor), &health())).is_empty());
+        assert_eq!(
+            codes(&campaign_report(&advertiser_agreement(DataPrivacyLevel::LimitedDataSet), &health())),
+            vec![ValidationCode::HealthTargetingNotAllowed],
+        );
+    }
+
+    #[test]
+    fn reach_counts_only_opted_in_individuals() {
+        let contract = advertiser_agreement(DataPrivacyLevel::SafeHarbor);
+        let mut campaigns = Campaigns::new();
+        let campaign_id = campaigns.launch(&contract, request(vec![segment("boston", vec![AudienceAttribute::PostalPrefix("021".to_string())])]))
+            .unwrap().campaign_id.clone();
+
+        let mut matched = people(MIN_AUDIENCE_SIZE, true);
+        matched.extend(people(200, false));
+        let reach = campaigns.reach(&campaign_id, "boston", &matched).unwrap();
+        assert_eq!(reach.reach, MIN_AUDIENCE_SIZE);
+
+        matched[0].opt_out_of_advertising();
+        assert!(matches!(campaigns.reach(&campaign_id, "boston", &matched), Err(CampaignError::AudienceTooSmall { .. })));
+        assert!(matches!(campaigns.reach(&campaign_id, "cambridge", &matched), Err(CampaignError::UnknownSegment { .. })));
+    }
+
+    #[test]
+    fn spend_stays_within_a_running_campaigns_budget() {
+        let contract = advertiser_agreement(DataPrivacyLevel::SafeHarbor);
+        let mut campaigns = Campaigns::new();
+        let campaign = campaigns.launch(&contract, request(vec![segment("boston", vec![AudienceAttribute::PostalPrefix("021".to_string())])])).unwrap();
+        let (campaign_id, start_date) = (campaign.campaign_id.clone(), campaign.start_date);
+
+        campaigns.record_spend(&campaign_id, usd(9_000), start_date).unwrap();
+        assert!(matches!(campaigns.record_spend(&campaign_id, usd(1_001), start_date), Err(CampaignError::BudgetExceeded { .. })));
+        campaigns.pause(&campaign_id).unwrap();
+        assert!(matches!(campaigns.record_spend(&campaign_id, usd(1), start_date), Err(CampaignError::NotRunning { .. })));
+        assert_eq!(campaigns.get(&campaign_id).unwrap().remaining_budget(), usd(1_000));
+    }
+}
@@ -0,0 +1,320 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+use time::OffsetDateTime;
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::lifecycle::ContractStatus;
+use crate::contracts::signatures::{ContentHash, ContractSignature};
+use crate::contracts::validation::ValidationReport;
+use crate::finance::settlement::payers_and_payees;
+
+/*
+Amendments let the amendable content of a signed contract (terms, generator rate, residuals) change over its life
+without drawing up a new contract.
+
+Any party may propose an Amendment. It names the parties it affects, and each of them must re-consent by signing
+the content hash of the amended contract. Once every affected party has approved, the current content is archived
+as a ContractRevision and the amended content becomes the next numbered revision. Parties that were not affected keep
+their earlier signature, which remains valid for the last revision they consented to.
+*/
+
+// The parts of a contract that can change through an amendment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RevisionContent {
+    pub terms: Terms,
+    pub generator_rate: Option<GeneratorRateSpecification>,
+    pub residual_payments: Option<Residuals>,
+}
+
+// The proposed changes; None leaves a field as it is.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AmendmentChanges {
+    pub terms: Option<Terms>,
+    pub generator_rate: Option<Option<GeneratorRateSpecification>>,
+    pub residual_payments: Option<Option<Residuals>>,
+}
+
+impl AmendmentChanges {
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_none() && self.generator_rate.is_none() && self.residual_payments.is_none()
+    }
+
+    pub fn apply_to(&self, content: &RevisionContent) -> RevisionContent {
+        RevisionContent {
+            terms: self.terms.clone().unwrap_or_else(|| content.terms.clone()),
+            generator_rate: self.generator_rate.clone().unwrap_or_else(|| content.generator_rate.clone()),
+            residual_payments: self.residual_payments.clone().unwrap_or_else(|| content.residual_payments.clone()),
+        }
+    }
+
+    /*
+    Which parties must re-consent, with payers and payees as settlement names them for the agreement type:
+      - terms: every party.
+      - generator_rate: the generators it pays, the payees whose remainder it comes out of, and HBank.
+        The payers pay the same gross amount either way.
+      - residual_payments: the old and new beneficiaries, the payers who owe the residuals, and HBank.
+    */
+    pub fn affected_parties(&self, parties: &[Party], agreement_type: &ContractCategory, current: &RevisionContent) -> Vec<EntityId> {
+        let mut affected: Vec<EntityId> = Vec::new();
+        let mut add = |entity_id: &EntityId| {
+            if !affected.contains(entity_id) {
+                affected.push(entity_id.clone());
+            }
+        };
+        let (payers, payees) = payers_and_payees(agreement_type).unwrap_or_default();
+        let hbank = parties.iter().filter(|p| matches!(p, Party::HBank(_)));
+
+        if self.terms.is_some() {
+            parties.iter().for_each(|p| add(p.entity_id()));
+        }
+        if self.generator_rate.is_some() {
+            parties.iter().filter(|p| matches!(p, Party::DataGenerator(_))).for_each(|p| add(p.entity_id()));
+            payees.iter().for_each(|p| add(p.entity_id()));
+            hbank.clone().for_each(|p| add(p.entity_id()));
+        }
+        if let Some(new_residuals) = &self.residual_payments {
+            let old_beneficiaries = current.residual_payments.iter().flat_map(|r| r.Beneficiaries.iter());
+            let new_beneficiaries = new_residuals.iter().flat_map(|r| r.Beneficiaries.iter());
+            old_beneficiaries.chain(new_beneficiaries).for_each(|p| add(p.entity_id()));
+            payers.iter().for_each(|p| add(p.entity_id()));
+            hbank.for_each(|p| add(p.entity_id()));
+        }
+        affected
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Amendment {
+    pub target_revision: u32,
+    pub proposed_by: EntityId,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub proposed_at: OffsetDateTime,
+    pub changes: AmendmentChanges,
+    pub affected_parties: Vec<EntityId>,
+    pub amended_content_hash: ContentHash,
+    pub approvals: Vec<ContractSignature>,
+}
+
+impl Amendment {
+    pub fn affects(&self, entity_id: &EntityId) -> bool {
+        self.affected_parties.contains(entity_id)
+    }
+
+    pub fn approved_by(&self) -> Vec<&EntityId> {
+        self.approvals.iter().map(|a| &a.entity_id).collect()
+    }
+
+    pub fn awaiting_approval(&self) -> Vec<&EntityId> {
+        self.affected_parties.iter().filter(|e| !self.approvals.iter().any(|a| a.entity_id == **e)).collect()
+    }
+
+    pub fn is_fully_approved(&self) -> bool {
+        self.awaiting_approval().is_empty()
+    }
+}
+
+// An amendment that was withdrawn before every affected party approved it, and who withdrew it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct WithdrawnAmendment {
+    pub amendment: Amendment,
+    pub withdrawn_by: EntityId,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub withdrawn_at: OffsetDateTime,
+}
+
+// A superseded revision of a contract, together with the amendment that produced it (None for revision 1).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ContractRevision {
+    pub number: u32,
+    pub content: RevisionContent,
+    pub content_hash: ContentHash,
+    pub amendment: Option<Amendment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: serde_json::Value,
+    pub after: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RevisionDiff {
+    pub from_revision: u32,
+    pub to_revision: u32,
+    pub changes: Vec<FieldChange>,
+}
+
+impl RevisionDiff {
+    // Compares two revisions field by field on their JSON representation.
+    pub fn between(from_revision: u32, from: &RevisionContent, to_revision: u32, to: &RevisionContent) -> Self {
+        let before = serde_json::to_value(from).expect("revision content is always serializable");
+        let after = serde_json::to_value(to).expect("revision content is always serializable");
+        let mut changes = Vec::new();
+        if let (serde_json::Value::Object(before), serde_json::Value::Object(after)) = (before, after) {
+            for (field, before_value) in before {
+                let after_value = after.get(&field).cloned().unwrap_or(serde_json::Value::Null);
+                if before_value != after_value {
+                    changes.push(FieldChange { field, before: before_value, after: after_value });
+                }
+            }
+        }
+        RevisionDiff { from_revision, to_revision, changes }
+    }
+}
+
+#[derive(Debug)]
+pub enum AmendmentError {
+    NotAmendable(ContractStatus),
+    NotAParty(EntityId),
+    NoChanges,
+    AmendmentPending,
+    NoPendingAmendment,
+    NotAffected(EntityId),
+    NotAllowedToWithdraw(EntityId),
+    InvalidApproval(EntityId),
+    KeyNotRegistered(EntityId),
+    UnknownRevision(u32),
+    Invalid(ValidationReport),
+}
+
+impl fmt::Display for AmendmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmendmentError::NotAmendable(status) => write!(f, "Amendment Error: a {} contract cannot be amended", status),
+            AmendmentError::NotAParty(entity_id) => write!(f, "Amendment Error: {} is not a party to the contract", entity_id.0),
+            AmendmentError::NoChanges => write!(f, "Amendment Error: the amendment does not change anything"),
+            AmendmentError::AmendmentPending => write!(f, "Amendment Error: another amendment is already pending"),
+            AmendmentError::NoPendingAmendment => write!(f, "Amendment Error: there is no pending amendment"),
+            AmendmentError::NotAffected(entity_id) => write!(f, "Amendment Error: {} is not affected by the pending amendment", entity_id.0),
+            AmendmentError::NotAllowedToWithdraw(entity_id) => write!(f, "Amendment Error: only the proposer or HBank may withdraw the pending amendment, not {}", entity_id.0),
+            AmendmentError::InvalidApproval(entity_id) => write!(f, "Amendment Error: approval from {} is not a valid signature of the amended contract under its registered key", entity_id.0),
+            AmendmentError::KeyNotRegistered(entity_id) => write!(f, "Amendment Error: no public key is registered for {}", entity_id.0),
+            AmendmentError::UnknownRevision(number) => write!(f, "Amendment Error: revision {} does not exist", number),
+            AmendmentError::Invalid(report) => write!(f, "Amendment Error: {}", report),
+        }
+    }
+}
+
+impl From<ValidationReport> for AmendmentError {
+    fn from(report: ValidationReport) -> Self {
+        AmendmentError::Invalid(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::contracts::HealthDataContract;
+    use crate::finance::money::{Currency, Money, Rate};
+    use crate::test_support::{sale, SaleFixture};
+
+    // An executed DirectSale from an individual custodian to an individual recipient, with one generator.
+    fn executed_sale() -> (SaleFixture, HealthDataContract) {
+        let sale = sale().generator_rate(GeneratorRateSpecification::KnowledgeRate(Rate::percent(5).unwrap()));
+        let contract = sale.executed();
+        (sale, contract)
+    }
+
+    fn rate_change() -> AmendmentChanges {
+        AmendmentChanges { generator_rate: Some(Some(GeneratorRateSpecification::KnowledgeRate(Rate::percent(8).unwrap()))), ..AmendmentChanges::default() }
+    }
+
+    fn residuals(beneficiary: &Party) -> Residuals {
+        Residuals {
+            Beneficiaries: vec![beneficiary.clone()],
+            DisbursementSchedule: DisbursementSchedule {
+                frequency: DisbursementFrequency::Annually,
+                start_date: Date::from_calendar_date(2030, Month::January, 1).unwrap(),
+                end_date: None,
+            },
+            Amount: ResidualAmount::FixedPerDisbursement(Money::new(10_000, Currency::usd())),
+        }
+    }
+
+    fn sorted(mut entity_ids: Vec<EntityId>) -> Vec<EntityId> {
+        entity_ids.sort();
+        entity_ids
+    }
+
+    #[test]
+    fn generator_rate_change_affects_the_payee_not_the_payer() {
+        let (sale, contract) = executed_sale();
+        let affected = rate_change().affected_parties(contract.get_parties(), contract.get_agreement_type(), &RevisionContent {
+            terms: Terms::default(),
+            generator_rate: contract.get_generator_rate().cloned(),
+            residual_payments: None,
+        });
+        // The custodian (agent_a) is paid what the generators leave; the recipient (agent_b) pays the same either way.
+        assert_eq!(sorted(affected), sorted(vec![sale.generators[0].entity_id().clone(), sale.agent_a.entity_id().clone(), sale.hbank.entity_id().clone()]));
+    }
+
+    #[test]
+    fn residual_change_affects_beneficiaries_and_payers() {
+        let (sale, mut contract) = executed_sale();
+        let changes = AmendmentChanges { residual_payments: Some(Some(residuals(&sale.generators[0]))), ..AmendmentChanges::default() };
+
+        contract.propose_amendment(sale.agent_a.entity_id(), changes).unwrap();
+        let pending = contract.get_pending_amendment().unwrap();
+        let expected = [&sale.generators[0], &sale.agent_b, &sale.hbank].map(|p| p.entity_id().clone()).to_vec();
+        assert_eq!(sorted(pending.affected_parties.clone()), sorted(expected));
+        assert!(!pending.affects(sale.agent_a.entity_id()));
+    }
+
+    #[test]
+    fn amendment_applies_once_every_affected_party_approves() {
+        let (sale, mut contract) = executed_sale();
+        contract.propose_amendment(sale.agent_a.entity_id(), rate_change()).unwrap();
+
+        assert!(matches!(contract.approve_amendment_as(sale.agent_b.entity_id(), &sale.key_of(&sale.agent_b)), Err(AmendmentError::NotAffected(_))));
+        assert!(!contract.approve_amendment_as(sale.generators[0].entity_id(), &sale.key_of(&sale.generators[0])).unwrap());
+        assert!(!contract.approve_amendment_as(sale.agent_a.entity_id(), &sale.key_of(&sale.agent_a)).unwrap());
+        assert!(contract.approve_amendment_as(sale.hbank.entity_id(), &sale.key_of(&sale.hbank)).unwrap());
+
+        assert_eq!(contract.get_revision(), 2);
+        assert_eq!(contract.get_generator_rate(), Some(&GeneratorRateSpecification::KnowledgeRate(Rate::percent(8).unwrap())));
+        // The recipient's signature on revision 1 still counts; everyone else signed revision 2.
+        assert!(contract.signature_report().is_valid());
+    }
+
+    #[test]
+    fn terminating_the_contract_drops_the_pending_amendment() {
+        let (sale, mut contract) = executed_sale();
+        let amended_content_hash = contract.propose_amendment(sale.agent_a.entity_id(), rate_change()).unwrap();
+        contract.approve_amendment_as(sale.generators[0].entity_id(), &sale.key_of(&sale.generators[0])).unwrap();
+        contract.approve_amendment_as(sale.agent_a.entity_id(), &sale.key_of(&sale.agent_a)).unwrap();
+        contract.terminate().unwrap();
+        assert!(contract.get_pending_amendment().is_none());
+
+        // The last approval, signed before termination, can no longer complete the amendment.
+        let approval = ContractSignature::create(sale.hbank.entity_id().clone(), amended_content_hash, &sale.key_of(&sale.hbank));
+        let err = contract.approve_amendment(approval).unwrap_err();
+        assert!(matches!(err, AmendmentError::NotAmendable(ContractStatus::Terminated)));
+        assert_eq!(contract.get_revision(), 1);
+        assert!(contract.get_revisions().is_empty());
+    }
+
+    #[test]
+    fn only_the_proposer_or_hbank_can_withdraw() {
+        let (sale, mut contract) = executed_sale();
+        contract.propose_amendment(sale.agent_a.entity_id(), rate_change()).unwrap();
+
+        let err = contract.withdraw_amendment(sale.generators[0].entity_id()).unwrap_err();
+        assert!(matches!(err, AmendmentError::NotAllowedToWithdraw(entity_id) if entity_id == *sale.generators[0].entity_id()));
+        assert!(contract.get_pending_amendment().is_some());
+
+        let withdrawn = contract.withdraw_amendment(sale.hbank.entity_id()).unwrap();
+        assert_eq!(withdrawn.withdrawn_by, *sale.hbank.entity_id());
+        assert_eq!(withdrawn.amendment.proposed_by, *sale.agent_a.entity_id());
+        assert!(contract.get_pending_amendment().is_none());
+
+        contract.propose_amendment(sale.agent_a.entity_id(), rate_change()).unwrap();
+        contract.withdraw_amendment(sale.agent_a.entity_id()).unwrap();
+        assert_eq!(contract.get_withdrawn_amendments().len(), 2);
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::validation::*;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::contracts::signatures::{encode_public_key, VerifyingKey};
+use crate::persons::Individual;
+use crate::persons::Corporation;
+
+/*
+Named-setter construction of a HealthDataContract, as an alternative to the positional HealthDataContract::new().
+
+Only the contract_id and agreement_type are needed up front. The legal framework and privacy level must be set
+explicitly before build(); everything else defaults to "not specified": no parties, empty Terms, no generator rate,
+no residuals, no individual contribution level, no IRB requirement, no cohort, no party keys and empty person maps.
+Every party needs a registered key (party_key()) before the contract can be signed.
+
+build() reports a missing legal framework or privacy level as a RequiredFieldMissing error, without substituting a
+placeholder for it. Once both are set it runs the full validation suite (role rules for the agreement type plus every
+other contract rule) and returns either the valid contract or the complete ValidationReport.
+*/
+pub struct HealthDataContractBuilder {
+    contract_id: String,
+    agreement_type: ContractCategory,
+    parties: Vec<Party>,
+    party_keys: BTreeMap<EntityId, String>,
+    legal_framework: Option<ContractLegalFramework>,
+    terms: Terms,
+    generator_rate: Option<GeneratorRateSpecification>,
+    residual_payments: Option<Residuals>,
+    individual_contribution_level: Option<IndividualContributionLevel>,
+    irb_required: bool,
+    irb_approved: Option<bool>,
+    cohort_id: Option<String>,
+    privacy_level: Option<DataPrivacyLevel>,
+    individuals_map: HashMap<EntityId, Individual>,
+    corporations_map: HashMap<EntityId, Corporation>,
+}
+
+impl HealthDataContractBuilder {
+    pub fn new(contract_id: impl Into<String>, agreement_type: ContractCategory) -> Self {
+        HealthDataContractBuilder {
+            contract_id: contract_id.into(),
+            agreement_type,
+            parties: Vec::new(),
+            party_keys: BTreeMap::new(),
+            legal_framework: None,
+            terms: Terms::default(),
+            generator_rate: None,
+            residual_payments: None,
+            individual_contribution_level: None,
+            irb_required: false,
+            irb_approved: None,
+            cohort_id: None,
+            privacy_level: None,
+            individuals_map: HashMap::new(),
+            corporations_map: HashMap::new(),
+        }
+    }
+
+    pub fn party(mut self, party: Party) -> Self {
+        self.parties.push(party);
+        self
+    }
+
+    pub fn parties(mut self, parties: impl IntoIterator<Item = Party>) -> Self {
+        self.parties.extend(parties);
+        self
+    }
+
+    // The public key the party will sign with. Signatures made with any other key are rejected.
+    pub fn party_key(mut self, entity_id: EntityId, key: &VerifyingKey) -> Self {
+        self.party_keys.insert(entity_id, encode_public_key(key));
+        self
+    }
+
+    pub fn legal_framework(mut self, legal_framework: ContractLegalFramework) -> Self {
+        self.legal_framework = Some(legal_framework);
+        self
+    }
+
+    pub fn terms(mut self, terms: Terms) -> Self {
+        self.terms = terms;
+        self
+    }
+
+    pub fn generator_rate(mut self, generator_rate: GeneratorRateSpecification) -> Self {
+        self.generator_rate = Some(generator_rate);
+        self
+    }
+
+    pub fn residual_payments(mut self, residual_payments: Residuals) -> Self {
+        self.residual_payments = Some(residual_payments);
+        self
+    }
+
+    pub fn individual_contribution_level(mut self, level: IndividualContributionLevel) -> Self {
+        self.individual_contribution_level = Some(level);
+        self
+    }
+
+    pub fn irb_required(mut self, irb_required: bool) -> Self {
+        self.irb_required = irb_required;
+        self
+    }
+
+    pub fn irb_approved(mut self, irb_approved: bool) -> Self {
+        self.irb_approved = Some(irb_approved);
+        self
+    }
+
+    pub fn cohort_id(mut self, cohort_id: impl Into<String>) -> Self {
+        self.cohort_id = Some(cohort_id.into());
+        self
+    }
+
+    pub fn privacy_level(mut self, privacy_level: DataPrivacyLevel) -> Self {
+        self.privacy_level = Some(privacy_level);
+        self
+    }
+
+    pub fn individual(mut self, individual: Individual) -> Self {
+        self.individuals_map.insert(individual.get_person_id().clone(), individual);
+        self
+    }
+
+    // An Individual who appears in the contract under a pseudonymous entity_id rather than their person_id.
+    pub fn individual_as(mut self, entity_id: EntityId, individual: Individual) -> Self {
+        self.individuals_map.insert(entity_id, individual);
+        self
+    }
+
+    pub fn individuals(mut self, individuals: impl IntoIterator<Item = Individual>) -> Self {
+        for individual in individuals {
+            self.individuals_map.insert(individual.get_person_id().clone(), individual);
+        }
+        self
+    }
+
+    pub fn corporation(mut self, corporation: Corporation) -> Self {
+        self.corporations_map.insert(corporation.get_person_id().clone(), corporation);
+        self
+    }
+
+    pub fn corporations(mut self, corporations: impl IntoIterator<Item = Corporation>) -> Self {
+        for corporation in corporations {
+            self.corporations_map.insert(corporation.get_person_id().clone(), corporation);
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<HealthDataContract, ValidationReport> {
+        let mut report = ValidationReport::new();
+        if self.legal_framework.is_none() {
+            report.error(ValidationCode::RequiredFieldMissing, ValidationSubject::Field("legal_framework".into()), "A legal framework must be specified.");
+        }
+        if self.privacy_level.is_none() {
+            report.error(ValidationCode::RequiredFieldMissing, ValidationSubject::Field("privacy_level".into()), "A data privacy level must be specified.");
+        }
+
+        let (Some(legal_framework), Some(privacy_level)) = (self.legal_framework, self.privacy_level) else {
+            return Err(report);
+        };
+
+        let contract = HealthDataContract::new(
+            self.parties,
+            self.agreement_type,
+            legal_framework,
+            self.terms,
+            self.generator_rate,
+            self.residual_payments,
+            self.individual_contribution_level,
+            self.irb_required,
+            self.irb_approved,
+            self.contract_id,
+            self.cohort_id,
+            privacy_level,
+            self.individuals_map,
+            self.corporations_map,
+        )
+        .with_party_keys(self.party_keys);
+
+        contract.validate_contract()?;
+        Ok(contract)
+    }
+}
+
+impl HealthDataContract {
+    pub fn builder(contract_id: impl Into<String>, agreement_type: ContractCategory) -> HealthDataContractBuilder {
+        HealthDataContractBuilder::new(contract_id, agreement_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sale;
+
+    // A DirectSale without a legal framework or privacy level.
+    fn sale_without_required_fields() -> HealthDataContractBuilder {
+        let sale = sale();
+        HealthDataContract::builder(sale.contract_id.clone(), sale.agreement_type()).parties(sale.parties())
+    }
+
+    fn only_missing(report: &ValidationReport, field: &str) -> bool {
+        report.codes() == vec![ValidationCode::RequiredFieldMissing]
+            && report.errors().all(|issue| issue.subject == ValidationSubject::Field(field.into()))
+    }
+
+    #[test]
+    fn missing_legal_framework_is_an_error() {
+        let report = sale_without_required_fields()
+            .privacy_level(DataPrivacyLevel::SafeHarbor)
+            .build()
+            .unwrap_err();
+        assert!(only_missing(&report, "legal_framework"));
+    }
+
+    #[test]
+    fn missing_privacy_level_is_an_error() {
+        let report = sale_without_required_fields()
+            .legal_framework(ContractLegalFramework::CommonLaw)
+            .build()
+            .unwrap_err();
+        assert!(only_missing(&report, "privacy_level"));
+    }
+
+    #[test]
+    fn build_keeps_the_legal_framework_and_privacy_level_given() {
+        let contract = sale()
+            .builder()
+            .legal_framework(ContractLegalFramework::UCC)
+            .privacy_level(DataPrivacyLevel::ExpertDetermination)
+            .build()
+            .unwrap();
+        let document = contract.to_document();
+        assert_eq!(document.legal_framework, ContractLegalFramework::UCC);
+        assert_eq!(document.privacy_level, DataPrivacyLevel::ExpertDetermination);
+    }
+}
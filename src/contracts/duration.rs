@@ -0,0 +1,262 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+use time::{Date, Duration, Month};
+
+use crate::contracts::structs_enums::EntityId;
+use crate::contracts::lifecycle::ContractStatus;
+
+/*
+How long a contract stays in force once it takes effect.
+
+    FixedEnd            - in force up to and including end_date.
+    AutoRenew           - renews for another term at the end of each term, unless a party gives notice
+                          at least notice_period_days before a term ends; it then ends with that term.
+    PerpetualRevocable  - in force until it is terminated.
+
+The contract takes effect on Terms::effective_date, or on the day it became Active if that is later.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum ContractDuration {
+    FixedEnd {
+        #[schemars(with = "String")]
+        end_date: Date,
+    },
+    AutoRenew { term: TermLength, notice_period_days: u32 },
+    #[default]
+    PerpetualRevocable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum TermLength {
+    Days(u32),
+    Months(u32),
+    Years(u32),
+}
+
+impl TermLength {
+    pub fn is_zero(&self) -> bool {
+        matches!(self, TermLength::Days(0) | TermLength::Months(0) | TermLength::Years(0))
+    }
+
+    // n consecutive terms as one; the (n+1)-th term starts times(n).after(start).
+    pub fn times(&self, n: u32) -> TermLength {
+        match self {
+            TermLength::Days(days) => TermLength::Days(days.saturating_mul(n)),
+            TermLength::Months(months) => TermLength::Months(months.saturating_mul(n)),
+            TermLength::Years(years) => TermLength::Years(years.saturating_mul(n)),
+        }
+    }
+
+    // The date one term after start. Month arithmetic clamps to the end of shorter months (Jan 31 + 1 month = Feb 28/29).
+    pub fn after(&self, start: Date) -> Date {
+        match self {
+            TermLength::Days(days) => start.saturating_add(Duration::days(*days as i64)),
+            TermLength::Months(months) => add_months(start, *months),
+            TermLength::Years(years) => add_months(start, years.saturating_mul(12)),
+        }
+    }
+}
+
+fn add_months(date: Date, months: u32) -> Date {
+    let month_index = date.year() as i64 * 12 + (date.month() as i64 - 1) + months as i64;
+    let year = (month_index.div_euclid(12)) as i32;
+    let month = Month::try_from((month_index.rem_euclid(12) + 1) as u8).expect("month index is always 1..=12");
+    let day = date.day().min(time::util::days_in_year_month(year, month));
+    Date::from_calendar_date(year, month, day).unwrap_or(Date::MAX)
+}
+
+/*
+Back-to-back periods of the given length starting on first_day, as (first day, last day) pairs, up to the last one
+that has ended by through. A period running past last_day is cut short there, and no period starts after it.
+*/
+pub fn consecutive_periods(length: TermLength, first_day: Date, last_day: Option<Date>, through: Date) -> Vec<(Date, Date)> {
+    if length.is_zero() {
+        return Vec::new();
+    }
+    let mut periods = Vec::new();
+    for n in 0.. {
+        let start = length.times(n).after(first_day);
+        if last_day.is_some_and(|last_day| start > last_day) {
+            break;
+        }
+        let end = length.times(n + 1).after(first_day) - Duration::days(1);
+        let end = last_day.map_or(end, |last_day| end.min(last_day));
+        if end > through {
+            break;
+        }
+        periods.push((start, end));
+    }
+    periods
+}
+
+// Notice from a party that an AutoRenew contract should not renew again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NonRenewalNotice {
+    pub given_by: EntityId,
+    #[schemars(with = "String")]
+    pub given_on: Date,
+}
+
+/*
+The last day a contract is in force, or None if it has no end (PerpetualRevocable, or AutoRenew without notice).
+For AutoRenew this is the end of the first term that ends at least notice_period_days after the notice was given.
+*/
+pub fn last_day_in_force(duration: &ContractDuration, start: Date, notice: Option<&NonRenewalNotice>) -> Option<Date> {
+    match duration {
+        ContractDuration::FixedEnd { end_date } => Some(*end_date),
+        ContractDuration::PerpetualRevocable => None,
+        ContractDuration::AutoRenew { term, notice_period_days } => {
+            let notice = notice?;
+            if term.is_zero() {
+                return Some(start);
+            }
+            let mut term_start = start;
+            loop {
+                let next_start = term.after(term_start);
+                let last_day = next_start.previous_day().unwrap_or(next_start);
+                if notice.given_on.saturating_add(Duration::days(*notice_period_days as i64)) <= last_day {
+                    return Some(last_day);
+                }
+                term_start = next_start;
+            }
+        }
+    }
+}
+
+// Where a contract stands on a given date.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ContractStanding {
+    NotExecuted,
+    NotYetEffective { effective_date: Date },
+    InForce { last_day: Option<Date> },
+    Suspended,
+    Expired { last_day: Date },
+    Terminated { on: Date },
+}
+
+impl ContractStanding {
+    pub fn is_in_force(&self) -> bool {
+        matches!(self, ContractStanding::InForce { .. })
+    }
+
+    pub fn has_ended(&self) -> bool {
+        matches!(self, ContractStanding::Expired { .. } | ContractStanding::Terminated { .. })
+    }
+}
+
+#[derive(Debug)]
+pub enum RenewalError {
+    NotAParty(EntityId),
+    NotAutoRenewing,
+    NoticeAlreadyGiven,
+    NotInForce(ContractStatus),
+}
+
+impl fmt::Display for RenewalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenewalError::NotAParty(entity_id) => write!(f, "Renewal Error: {} is not a party to the contract", entity_id.0),
+            RenewalError::NotAutoRenewing => write!(f, "Renewal Error: the contract does not renew automatically"),
+            RenewalError::NoticeAlreadyGiven => write!(f, "Renewal Error: notice of non-renewal has already been given"),
+            RenewalError::NotInForce(status) => write!(f, "Renewal Error: the contract is {} and not in force", status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::contracts::health_data_contract::HealthDataContract;
+    use crate::contracts::structs_enums::Terms;
+    use crate::test_support::sale;
+
+    fn date(year: i32, month: Month, day: u8) -> Date {
+        Date::from_calendar_date(year, month, day).unwrap()
+    }
+
+    fn notice(given_on: Date) -> NonRenewalNotice {
+        NonRenewalNotice { given_by: EntityId("I-C001".to_string()), given_on }
+    }
+
+    // An executed DirectSale that takes effect today under the given duration.
+    fn executed_sale(duration: ContractDuration) -> (HealthDataContract, Date) {
+        let contract = sale().terms(Terms { duration, ..Terms::default() }).executed();
+        let effective_from = contract.effective_from().unwrap();
+        (contract, effective_from)
+    }
+
+    #[test]
+    fn month_terms_clamp_to_the_end_of_shorter_months() {
+        assert_eq!(TermLength::Months(1).after(date(2030, Month::January, 31)), date(2030, Month::February, 28));
+        assert_eq!(TermLength::Years(1).after(date(2028, Month::February, 29)), date(2029, Month::February, 28));
+        assert_eq!(TermLength::Days(3).times(2).after(date(2030, Month::January, 1)), date(2030, Month::January, 7));
+    }
+
+    #[test]
+    fn periods_stop_at_the_last_day_and_at_through() {
+        let start = date(2030, Month::January, 1);
+        let periods = consecutive_periods(TermLength::Days(10), start, Some(date(2030, Month::January, 25)), date(2030, Month::February, 1));
+        assert_eq!(periods, vec![
+            (start, date(2030, Month::January, 10)),
+            (date(2030, Month::January, 11), date(2030, Month::January, 20)),
+            (date(2030, Month::January, 21), date(2030, Month::January, 25)),
+        ]);
+        assert_eq!(consecutive_periods(TermLength::Days(10), start, None, date(2030, Month::January, 19)).len(), 1);
+        assert!(consecutive_periods(TermLength::Days(0), start, None, date(2030, Month::February, 1)).is_empty());
+    }
+
+    #[test]
+    fn each_duration_has_its_own_last_day() {
+        let start = date(2030, Month::January, 1);
+        let fixed = ContractDuration::FixedEnd { end_date: date(2030, Month::June, 30) };
+        let renewing = ContractDuration::AutoRenew { term: TermLength::Days(30), notice_period_days: 10 };
+
+        assert_eq!(last_day_in_force(&fixed, start, None), Some(date(2030, Month::June, 30)));
+        assert_eq!(last_day_in_force(&ContractDuration::PerpetualRevocable, start, Some(&notice(start))), None);
+        assert_eq!(last_day_in_force(&renewing, start, None), None);
+        // Notice in time ends the current term; late notice ends the next one.
+        assert_eq!(last_day_in_force(&renewing, start, Some(&notice(date(2030, Month::January, 20)))), Some(date(2030, Month::January, 30)));
+        assert_eq!(last_day_in_force(&renewing, start, Some(&notice(date(2030, Month::January, 21)))), Some(date(2030, Month::March, 1)));
+    }
+
+    #[test]
+    fn fixed_end_contract_expires_after_its_end_date() {
+        let today = OffsetDateTime::now_utc().date();
+        let end_date = today + Duration::days(10);
+        let (mut contract, _) = executed_sale(ContractDuration::FixedEnd { end_date });
+
+        assert_eq!(contract.as_of(end_date), ContractStanding::InForce { last_day: Some(end_date) });
+        assert_eq!(contract.as_of(end_date + Duration::days(1)), ContractStanding::Expired { last_day: end_date });
+        assert!(!contract.expire_if_due(end_date).unwrap());
+        assert!(contract.expire_if_due(end_date + Duration::days(1)).unwrap());
+        assert_eq!(contract.get_status(), ContractStatus::Expired);
+        assert_eq!(contract.as_of(end_date + Duration::days(30)), ContractStanding::Expired { last_day: end_date });
+        assert!(matches!(contract.give_non_renewal_notice(&EntityId("I-C001".to_string()), end_date), Err(RenewalError::NotInForce(ContractStatus::Expired))));
+    }
+
+    #[test]
+    fn auto_renewing_contract_runs_until_notice_ends_a_term() {
+        let (mut contract, start) = executed_sale(ContractDuration::AutoRenew { term: TermLength::Days(30), notice_period_days: 10 });
+        assert!(contract.is_in_force(start + Duration::days(365)));
+
+        let custodian = EntityId("I-C001".to_string());
+        assert!(matches!(contract.give_non_renewal_notice(&EntityId("I-X001".to_string()), start), Err(RenewalError::NotAParty(_))));
+        // Notice 25 days in is too late for the first term to end on day 29.
+        let last_day = contract.give_non_renewal_notice(&custodian, start + Duration::days(25)).unwrap();
+        assert_eq!(last_day, start + Duration::days(59));
+        assert!(matches!(contract.give_non_renewal_notice(&custodian, start + Duration::days(26)), Err(RenewalError::NoticeAlreadyGiven)));
+        assert!(contract.is_in_force(last_day));
+        assert_eq!(contract.as_of(last_day + Duration::days(1)), ContractStanding::Expired { last_day });
+    }
+
+    #[test]
+    fn perpetual_contract_stays_in_force_and_cannot_be_given_notice() {
+        let (mut contract, start) = executed_sale(ContractDuration::PerpetualRevocable);
+        assert_eq!(contract.as_of(start + Duration::days(3650)), ContractStanding::InForce { last_day: None });
+        assert!(!contract.expire_if_due(start + Duration::days(3650)).unwrap());
+        assert!(matches!(contract.give_non_renewal_notice(&EntityId("I-C001".to_string()), start), Err(RenewalError::NotAutoRenewing)));
+    }
+}
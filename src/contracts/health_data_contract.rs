@@ -1,23 +1,27 @@
-use std::fmt;
-use std::collections::HashMap;
-use time::OffsetDateTime;
+use std::collections::{BTreeMap, HashMap};
+use time::{Date, OffsetDateTime};
+use serde::{Serialize, Serializer};
 
 use crate::contracts::structs_enums::*; 
+use crate::contracts::lifecycle::*;
+use crate::contracts::validation::*;
+use crate::contracts::serialization::*;
+use crate::contracts::signatures::*;
+use crate::contracts::amendments::*;
+use crate::contracts::revocation::*;
+use crate::contracts::duration::*;
+use crate::contracts::role_rules::DATA_RECEIVING_ROLES;
+use crate::finance::money::Rate;
 use crate::persons::Individual;
+use crate::persons::pseudonyms::is_pseudonym_format;
 use crate::persons::Corporation;
 
 
 #[derive(Debug)]
-pub struct ValidationError(String);
-
-impl fmt::Display for ValidationError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Validation Error: {}", self.0)
-    }
-}
-
 pub struct HealthDataContract {
     parties: Vec<Party>,
+    // Hex-encoded Ed25519 public key of each party, against which its signatures are verified.
+    party_keys: BTreeMap<EntityId, String>,
     agreement_type: ContractCategory,
     legal_framework: ContractLegalFramework,
     terms: Terms,
@@ -30,7 +34,19 @@ pub struct HealthDataContract {
     pub cohort_id: Option<String>,
     privacy_level: DataPrivacyLevel,
     individuals_map: HashMap<EntityId, Individual>,
-    corporations_map: HashMap<EntityId, Corporation>, 
+    corporations_map: HashMap<EntityId, Corporation>,
+    status: ContractStatus,
+    status_history: Vec<StatusTransition>,
+    signatures: Vec<ContractSignature>,
+    // The schema version the signed content is encoded under: the current one when the signatures were collected.
+    signed_schema_version: u32,
+    revision: u32,
+    revisions: Vec<ContractRevision>,
+    current_amendment: Option<Amendment>,
+    pending_amendment: Option<Amendment>,
+    withdrawn_amendments: Vec<WithdrawnAmendment>,
+    revocations: Vec<ConsentRevocation>,
+    non_renewal_notice: Option<NonRenewalNotice>,
 }
 
 impl HealthDataContract {
@@ -40,6 +56,10 @@ impl HealthDataContract {
         &self.parties
     }
 
+    pub fn get_party_keys(&self) -> &BTreeMap<EntityId, String> {
+        &self.party_keys
+    }
+
     pub fn get_privacy_level(&self) -> &DataPrivacyLevel {
         &self.privacy_level
     }
@@ -51,10 +71,76 @@ impl HealthDataContract {
     pub fn get_cohort_id(&self) -> Option<&str> {
         self.cohort_id.as_deref()
     }
+
+    pub fn get_agreement_type(&self) -> &ContractCategory {
+        &self.agreement_type
+    }
+
+    pub fn get_terms(&self) -> &Terms {
+        &self.terms
+    }
+
+    pub fn get_generator_rate(&self) -> Option<&GeneratorRateSpecification> {
+        self.generator_rate.as_ref()
+    }
+
+    pub fn get_residual_payments(&self) -> Option<&Residuals> {
+        self.residual_payments.as_ref()
+    }
+
+    pub fn get_status(&self) -> ContractStatus {
+        self.status
+    }
+
+    pub fn get_status_history(&self) -> &[StatusTransition] {
+        &self.status_history
+    }
+
+    // When the status last changed; the earliest time the next transition can be recorded at.
+    pub fn last_transition_at(&self) -> Option<OffsetDateTime> {
+        self.status_history.last().map(|t| t.at)
+    }
+
+    pub fn get_signatures(&self) -> &[ContractSignature] {
+        &self.signatures
+    }
+
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
+
+    pub fn get_revisions(&self) -> &[ContractRevision] {
+        &self.revisions
+    }
+
+    pub fn get_current_amendment(&self) -> Option<&Amendment> {
+        self.current_amendment.as_ref()
+    }
+
+    pub fn get_pending_amendment(&self) -> Option<&Amendment> {
+        self.pending_amendment.as_ref()
+    }
+
+    pub fn get_withdrawn_amendments(&self) -> &[WithdrawnAmendment] {
+        &self.withdrawn_amendments
+    }
+
+    pub fn get_revocations(&self) -> &[ConsentRevocation] {
+        &self.revocations
+    }
+
+    pub fn get_non_renewal_notice(&self) -> Option<&NonRenewalNotice> {
+        self.non_renewal_notice.as_ref()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.status == ContractStatus::Active
+    }
     ///////////////////////////////////////////////////////
     
 
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         parties: Vec<Party>,
         agreement_type: ContractCategory,
@@ -73,6 +159,7 @@ impl HealthDataContract {
     ) -> Self {
         HealthDataContract {
             parties,
+            party_keys: BTreeMap::new(),
             agreement_type,
             legal_framework,
             terms,
@@ -86,117 +173,87 @@ impl HealthDataContract {
             privacy_level,
             individuals_map,
             corporations_map,
+            status: ContractStatus::Draft,
+            status_history: Vec::new(),
+            signatures: Vec::new(),
+            signed_schema_version: CONTRACT_SCHEMA_VERSION,
+            revision: 1,
+            revisions: Vec::new(),
+            current_amendment: None,
+            pending_amendment: None,
+            withdrawn_amendments: Vec::new(),
+            revocations: Vec::new(),
+            non_renewal_notice: None,
         }
     }
 
-    pub fn add_parties(&mut self, new_parties: Vec<Party>) -> Result<(), ValidationError> {
-        match &self.agreement_type {
-            ContractCategory::TwoParty(two_party_type) => self.validate_two_party(two_party_type, &new_parties)?,
-            ContractCategory::ThreePlusParty(three_plus_party_type) => self.validate_three_plus_party(three_plus_party_type, &new_parties)?,
-        }
+    /*
+    New parties are checked together with the parties already on the contract, so a rule cannot be sidestepped
+    by adding parties in several calls. Minimum role counts are left to validation_report(), since a Draft
+    contract may be assembled over several calls.
+    */
+    pub fn add_parties(&mut self, new_parties: Vec<Party>) -> Result<(), ValidationReport> {
+        let combined: Vec<Party> = self.parties.iter().cloned().chain(new_parties.iter().cloned()).collect();
+        let mut report = ValidationReport::new();
+        self.check_editable(&mut report);
+        self.agreement_type.role_rules().check(&combined, false, &mut report);
+        report.into_result()?;
         self.parties.extend(new_parties);
+        self.invalidate_signatures();
         Ok(())
     }
 
-    fn validate_two_party(&self, two_party_type: &TwoPartyLegalStructure, new_parties: &[Party]) -> Result<(), ValidationError> {
-        if new_parties.len() != 2 {
-            return Err(ValidationError("Two-party agreement requires exactly 2 parties.".into()));
-        }
-        match two_party_type {
-            TwoPartyLegalStructure::Storage_or_Exchange(_) => {
-                let agent_or_generator = new_parties.iter().any(|p| matches!(p, Party::DataOriginator(_) | Party::DataGenerator(_)));
-                let h_bank = new_parties.iter().any(|p| matches!(p, Party::HBank(_)));
-                if !agent_or_generator || !h_bank {
-                    return Err(ValidationError("Parties do not match required types for Storage or Exchange Agreement".into()));
-                }
-            },
-            TwoPartyLegalStructure::Donation(_) => {
-                let donor = new_parties.iter().any(|p| matches!(p, Party::Donor(_)));
-                let h_bank = new_parties.iter().any(|p| matches!(p, Party::HBank(_)));
-                if !donor || !h_bank {
-                    return Err(ValidationError("Parties do not match required types for Donation".into()));
-                }
-            },
-            TwoPartyLegalStructure::Advertisement(_) => {
-                let advertiser = new_parties.iter().any(|p| matches!(p, Party::Advertiser(_)));
-                let h_bank = new_parties.iter().any(|p| matches!(p, Party::HBank(_)));
-                if !advertiser || !h_bank {
-                    return Err(ValidationError("Parties do not match required types for Advertisement".into()));
-                }
-            },
-        }
+    // Registers the public key a party signs with. Like any other content change, this drops existing signatures.
+    pub fn register_party_key(&mut self, entity_id: EntityId, key: &VerifyingKey) -> Result<(), ValidationReport> {
+        let mut report = ValidationReport::new();
+        self.check_editable(&mut report);
+        report.into_result()?;
+        self.party_keys.insert(entity_id, encode_public_key(key));
+        self.invalidate_signatures();
         Ok(())
     }
 
-    fn validate_three_plus_party(&self, three_plus_party_type: &TransactionLegalStructure, new_parties: &[Party]) -> Result<(), ValidationError> {
-        if new_parties.len() < 3 {
-            return Err(ValidationError("Three-plus party agreement requires at least 3 parties.".into()));
-        }
-        match three_plus_party_type {
-            TransactionLegalStructure::ConsultAgreement { .. } => {
-                let agent = new_parties.iter().any(|p| matches!(p, Party::DataOriginator(_) | Party::DataCustodian(_) | Party::DataRecipient(_)));
-                let consultant = new_parties.iter().any(|p| matches!(p, Party::DataConsultant(_)));
-                let generator = new_parties.iter().any(|p| matches!(p, Party::DataGenerator(_)));
-                let h_bank = new_parties.iter().any(|p| matches!(p, Party::HBank(_)));
-                if !agent || !consultant || !generator || !h_bank {
-                    return Err(ValidationError("Parties do not match required types for ConsultAgreement".into()));
-                }
-            },
-            TransactionLegalStructure::DirectSale { .. } |
-            TransactionLegalStructure::PurchaseAgreement { .. } |
-            TransactionLegalStructure::LicensingAgreement { .. } |
-            TransactionLegalStructure::AccessAgreement { .. } |
-            TransactionLegalStructure::SubscriptionAgreement { .. } => {
-                let agent_a = new_parties.iter().any(|p| matches!(p, Party::DataOriginator(_) | Party::DataCustodian(_)));
-                let agent_b = new_parties.iter().any(|p| matches!(p, Party::DataRecipient(_)));
-                let generator = new_parties.iter().any(|p| matches!(p, Party::DataGenerator(_)));
-                let h_bank = new_parties.iter().any(|p| matches!(p, Party::HBank(_)));
-                if !agent_a || !agent_b || !generator || !h_bank {
-                    return Err(ValidationError(format!("Parties do not match required types for {:?}", three_plus_party_type)));
-                }
-            },
-            TransactionLegalStructure::ConsortiumAgreement { .. } => {
-                let agents = new_parties.iter().any(|p| matches!(p, Party::DataOriginator(_) | Party::DataCustodian(_) | Party::DataRecipient(_)));
-                let generator = new_parties.iter().any(|p| matches!(p, Party::DataGenerator(_)));
-                let h_bank = new_parties.iter().any(|p| matches!(p, Party::HBank(_)));
-                if !agents || !generator || !h_bank {
-                    return Err(ValidationError("Parties do not match required types for ConsortiumAgreement".into()));
-                }
-            },
-            TransactionLegalStructure::ParticipationAgreement { .. } => {
-                let agents = new_parties.iter().any(|p| matches!(p, Party::DataOriginator(_) | Party::DataCustodian(_) | Party::DataRecipient(_)));
-                let funders = new_parties.iter().any(|p| matches!(p, Party::Funder(_)));
-                let generator = new_parties.iter().any(|p| matches!(p, Party::DataGenerator(_)));
-                let h_bank = new_parties.iter().any(|p| matches!(p, Party::HBank(_)));
-                if !agents || !funders || !generator || !h_bank {
-                    return Err(ValidationError("Parties do not match required types for ParticipationAgreement".into()));
-                }
-            },
-            TransactionLegalStructure::DataExchangeAgreement { .. } => {
-                let agent_a = new_parties.iter().any(|p| matches!(p, Party::DataOriginator(_) | Party::DataCustodian(_)));
-                let agent_b = new_parties.iter().any(|p| matches!(p, Party::DataRecipient(_)));
-                let generator = new_parties.iter().any(|p| matches!(p, Party::DataGenerator(_)));
-                let h_bank = new_parties.iter().any(|p| matches!(p, Party::HBank(_)));
-                if !agent_a || !agent_b || !generator || !h_bank {
-                    return Err(ValidationError("Parties do not match required types for DataExchangeAgreement".into()));
-                }
-            },
-        }
+    pub(crate) fn with_party_keys(mut self, party_keys: BTreeMap<EntityId, String>) -> Self {
+        self.party_keys = party_keys;
+        self
+    }
+
+    pub fn set_terms(&mut self, terms: Terms) -> Result<(), ValidationReport> {
+        let mut report = ValidationReport::new();
+        self.check_editable(&mut report);
+        report.into_result()?;
+        self.terms = terms;
+        self.invalidate_signatures();
         Ok(())
     }
 
+    // Contract content may only change before it is signed.
+    fn check_editable(&self, report: &mut ValidationReport) {
+        if !matches!(self.status, ContractStatus::Draft | ContractStatus::Proposed) {
+            report.error(
+                ValidationCode::ContractNotEditable,
+                ValidationSubject::Contract,
+                format!("Contract {} is {} and can no longer be edited.", self.contract_id, self.status),
+            );
+        }
+    }
+
+    fn validate_party_roles(&self, report: &mut ValidationReport) {
+        self.agreement_type.role_rules().check(&self.parties, true, report);
+    }
+
     fn determine_whether_parties_have_generator(&self) -> bool {
         self.parties.iter().any(|party| matches!(party, Party::DataGenerator(_)))
     }
 
-    fn validate_generator_rate_spec(&self) -> Result<(), ValidationError> {
+    fn validate_generator_rate_spec(&self, report: &mut ValidationReport) {
         let generator_present = self.determine_whether_parties_have_generator();
-    
+        let field = || ValidationSubject::Field("generator_rate".into());
+
         match (generator_present, &self.generator_rate) {
-            (false, None) => Ok(()),
-            (false, Some(_)) => Err(ValidationError("No parties implement IsGenerator, but generator_rate is specified.".into())),
-            (true, None) => Err(ValidationError("At least one party implements IsGenerator, but generator_rate is not specified.".into())),
-            (true, Some(_)) => Ok(()),
+            (false, Some(_)) => report.error(ValidationCode::GeneratorRateUnexpected, field(), "No parties implement IsGenerator, but generator_rate is specified."),
+            (true, None) => report.error(ValidationCode::GeneratorRateMissing, field(), "At least one party implements IsGenerator, but generator_rate is not specified."),
+            (false, None) | (true, Some(_)) => {}
         }
     } 
 
@@ -204,32 +261,31 @@ impl HealthDataContract {
         self.parties.iter().any(|party| matches!(party, Party::DataOriginator(_)))
     }
 
-    fn validate_individual_contribution_level(&self) -> Result<(), ValidationError> {
+    fn validate_individual_contribution_level(&self, report: &mut ValidationReport) {
         let originator_present = self.determine_whether_parties_have_data_originator();
-    
+        let field = || ValidationSubject::Field("individual_contribution_level".into());
+
         match (originator_present, &self.individual_contribution_level) {
-            (false, None) => Ok(()),
-            (false, Some(IndividualContributionLevel::DataOnly)) => Ok(()),
-            (false, Some(IndividualContributionLevel::DataAndParticipation)) => Err(ValidationError("No parties implement IsOriginator, but individual_contribution_level is set to DataAndParticipation.".to_string())),
-            (true, None) => Err(ValidationError("At least one party implements IsOriginator, but individual_contribution_level is not specified.".to_string())),
-            (true, Some(IndividualContributionLevel::DataOnly)) => Ok(()),
-            (true, Some(IndividualContributionLevel::DataAndParticipation)) => Ok(()),
+            (false, Some(IndividualContributionLevel::DataOnly)) => report.warning(ValidationCode::ContributionLevelWithoutOriginator, field(), "No parties implement IsOriginator, so individual_contribution_level DataOnly has no effect."),
+            (false, Some(IndividualContributionLevel::DataAndParticipation)) => report.error(ValidationCode::ContributionLevelWithoutOriginator, field(), "No parties implement IsOriginator, but individual_contribution_level is set to DataAndParticipation."),
+            (true, None) => report.error(ValidationCode::ContributionLevelMissing, field(), "At least one party implements IsOriginator, but individual_contribution_level is not specified."),
+            (false, None) | (true, Some(_)) => {}
         }
     }
 
-    fn validate_irb_requirement(&self) -> Result<(), ValidationError> {
+    fn validate_irb_requirement(&self, report: &mut ValidationReport) {
         if self.irb_required {
+            let field = || ValidationSubject::Field("irb_approved".into());
             match self.irb_approved {
-                Some(true) => Ok(()),
-                Some(false) => Err(ValidationError("IRB approval required but it was not granted.".to_string())),
-                None => Err(ValidationError("IRB approval required but not specified.".to_string())),
+                Some(true) => {}
+                Some(false) => report.error(ValidationCode::IrbNotGranted, field(), "IRB approval required but it was not granted."),
+                None => report.error(ValidationCode::IrbApprovalMissing, field(), "IRB approval required but not specified."),
             }
-        } else {
-            Ok(())
         }
     }
 
-    pub fn validate_individual_age_wrt_agency_privacy(&self) -> Result<(), ValidationError> {
+    // Custodians and recipients may be individuals or corporations; originators are always individuals.
+    pub fn validate_individual_age_wrt_agency_privacy(&self, report: &mut ValidationReport) {
         let current_date = OffsetDateTime::now_utc().date();
     
         for party in &self.parties {
@@ -240,43 +296,916 @@ impl HealthDataContract {
                     let age = current_date.year() - birth_date.year() -
                               if current_date.ordinal() < birth_date.ordinal() { 1 } else { 0 };
                     if age < 17 {
-                        return Err(ValidationError(format!("Individual with ID {} is under 17 years old.", person_id.0)));
+                        report.error(ValidationCode::IndividualUnderage, ValidationSubject::Party(person_id.clone()), format!("Individual with ID {} is under 17 years old.", person_id.0));
                     }
-                } else {
-                    return Err(ValidationError(format!("Person ID {} not found in individuals_map.", person_id.0)));
+                } else if matches!(party, Party::DataOriginator(_)) {
+                    report.error(ValidationCode::IndividualNotFound, ValidationSubject::Party(person_id.clone()), format!("Person ID {} not found in individuals_map.", person_id.0));
+                } else if !self.corporations_map.contains_key(person_id) {
+                    report.error(ValidationCode::IndividualNotFound, ValidationSubject::Party(person_id.clone()), format!("Person ID {} not found in individuals_map or corporations_map.", person_id.0));
                 }
             }
         }
-        
-        Ok(())
     }
 
-    fn validate_residual_payees(&self) -> Result<(), ValidationError> {
+    fn validate_residual_payees(&self, report: &mut ValidationReport) {
         if let Some(residuals) = &self.residual_payments {
+            if residuals.Beneficiaries.is_empty() {
+                report.error(ValidationCode::ResidualBeneficiariesMissing, ValidationSubject::Field("residual_payments.beneficiaries".into()), "Residual payments are specified but name no beneficiaries.");
+            }
             for beneficiary in &residuals.Beneficiaries {
                 if !self.parties.iter().any(|party| party == beneficiary) {
-                    return Err(ValidationError(format!(
-                        "Beneficiary {:?} is not a party to the contract.",
-                        beneficiary
-                    )));
+                    report.error(
+                        ValidationCode::ResidualBeneficiaryNotParty,
+                        ValidationSubject::Party(beneficiary.as_party_info().entity_id.clone()),
+                        format!("Beneficiary {:?} is not a party to the contract.", beneficiary),
+                    );
+                }
+            }
+        }
+    }
+
+    fn validate_residual_schedule(&self, report: &mut ValidationReport) {
+        let Some(residuals) = &self.residual_payments else {
+            return;
+        };
+        let schedule = &residuals.DisbursementSchedule;
+        let field = || ValidationSubject::Field("residual_payments.disbursement_schedule".into());
+        if let Some(end_date) = schedule.end_date {
+            if end_date < schedule.start_date {
+                report.error(ValidationCode::ResidualScheduleEndsBeforeStart, field(), format!("Residual disbursements end on {} but only start on {}.", end_date, schedule.start_date));
+            }
+        }
+        match (schedule.frequency, &residuals.Amount) {
+            (DisbursementFrequency::RevenueTriggered, ResidualAmount::FixedPerDisbursement(_)) => {
+                report.error(ValidationCode::ResidualAmountMismatch, field(), "Revenue-triggered residuals must be a percentage of revenue.");
+            }
+            (DisbursementFrequency::RevenueTriggered, ResidualAmount::PercentOfRevenue(_)) => {}
+            (_, ResidualAmount::PercentOfRevenue(_)) => {
+                report.error(ValidationCode::ResidualAmountMismatch, field(), format!("{:?} residuals must be a fixed amount per disbursement.", schedule.frequency));
+            }
+            (_, ResidualAmount::FixedPerDisbursement(_)) => {}
+        }
+    }
+
+    // The generator rate (the larger rate of a Tiered spec), HBank's fee and the consultant fee are all taken from the same gross amount.
+    fn validate_combined_rates(&self, report: &mut ValidationReport) {
+        let generator_rate = self.generator_rate.as_ref().map_or(Rate::ZERO, |spec| spec.max_rate());
+        let fees = &self.terms.fees;
+        let total_ppm = Rate::total_ppm([generator_rate, fees.hbank_fee, fees.consultant, fees.originator]);
+        if total_ppm > Rate::PPM_PER_WHOLE as u64 {
+            report.error(
+                ValidationCode::CombinedRatesExceedTotal,
+                ValidationSubject::Field("terms.fees".into()),
+                format!("The generator rate ({}%), HBank fee ({}%), consultant fee ({}%) and originator share ({}%) add up to {}%, more than the whole amount.", generator_rate, fees.hbank_fee, fees.consultant, fees.originator, Rate::format_ppm(total_ppm)),
+            );
+        }
+    }
+
+    fn validate_earmark(&self, report: &mut ValidationReport) {
+        let target = match self.agreement_type.earmark() {
+            Some(Earmark::Cohort(cohort_id)) => cohort_id,
+            Some(Earmark::DiseaseArea(disease_area)) => disease_area,
+            Some(Earmark::GeneralOperations) | None => return,
+        };
+        if target.trim().is_empty() {
+            report.error(ValidationCode::EarmarkTargetEmpty, ValidationSubject::Field("agreement_type.earmark".into()), "A donation earmarked for a cohort or disease area must name it.");
+        }
+    }
+
+    fn validate_subscription_plan(&self, report: &mut ValidationReport) {
+        let field = || ValidationSubject::Field("terms.subscription".into());
+        let is_subscription = self.agreement_type.kind() == AgreementKind::SubscriptionAgreement;
+        let plan = match (is_subscription, &self.terms.subscription) {
+            (true, Some(plan)) => plan,
+            (true, None) => {
+                report.error(ValidationCode::SubscriptionPlanMissing, field(), "A SubscriptionAgreement must specify a subscription plan.");
+                return;
+            }
+            (false, Some(_)) => {
+                report.error(ValidationCode::SubscriptionPlanUnexpected, field(), format!("Only a SubscriptionAgreement takes a subscription plan, not a {}.", self.agreement_type.kind()));
+                return;
+            }
+            (false, None) => return,
+        };
+        if plan.period.is_zero() {
+            report.error(ValidationCode::SubscriptionPlanInvalid, field(), "The billing period must be longer than zero.");
+        }
+        let prices = [&plan.price, &plan.overage_per_job_run, &plan.overage_per_data_refresh];
+        if prices.iter().any(|price| price.is_negative()) {
+            report.error(ValidationCode::SubscriptionPlanInvalid, field(), "Subscription prices cannot be negative.");
+        }
+        if prices.iter().any(|price| price.currency != plan.price.currency) {
+            report.error(ValidationCode::SubscriptionPlanInvalid, field(), format!("All subscription prices must be in {}.", plan.price.currency));
+        }
+    }
+
+    fn validate_royalty_terms(&self, report: &mut ValidationReport) {
+        let Some(royalties) = &self.terms.royalties else {
+            return;
+        };
+        let field = || ValidationSubject::Field("terms.royalties".into());
+        let kind = self.agreement_type.kind();
+        if kind != AgreementKind::LicensingAgreement {
+            report.error(ValidationCode::RoyaltyTermsUnexpected, field(), format!("Only a LicensingAgreement takes royalty terms, not a {}.", kind));
+            return;
+        }
+        if royalties.reporting_period.is_zero() {
+            report.error(ValidationCode::RoyaltyTermsInvalid, field(), "The royalty reporting period must be longer than zero.");
+        }
+        let total_ppm = Rate::total_ppm([royalties.licensor_rate, royalties.generator_rate]);
+        if total_ppm > Rate::PPM_PER_WHOLE as u64 {
+            report.error(
+                ValidationCode::CombinedRatesExceedTotal,
+                field(),
+                format!("The licensor ({}%) and generator ({}%) royalty rates add up to {}%, more than the whole revenue.", royalties.licensor_rate, royalties.generator_rate, Rate::format_ppm(total_ppm)),
+            );
+        }
+    }
+
+    /*
+    Every DataOriginator's consent must cover the contract's purpose, the data categories it covers and who receives the
+    data, whatever the agreement type. Storage and exchange agreements need consent to storage by HBank.
+    */
+    fn validate_originator_consent(&self, report: &mut ValidationReport) {
+        let purpose = self.data_use_purpose();
+        let categories = self.data_categories();
+        let recipient_types = self.data_recipient_types();
+        for entity_id in self.consenting_originators() {
+            // Originators missing from individuals_map are already reported by validate_individual_age_wrt_agency_privacy.
+            let Some(individual) = self.individuals_map.get(entity_id) else {
+                continue;
+            };
+            let gaps = individual.get_consent().gaps(purpose, &categories, &recipient_types);
+            if !gaps.is_empty() {
+                let missing: Vec<String> = gaps.iter().map(|gap| gap.to_string()).collect();
+                report.error(
+                    ValidationCode::ConsentNotCovered,
+                    ValidationSubject::Party(entity_id.clone()),
+                    format!("Data originator {} has not consented to {} under this {}.", entity_id.0, missing.join(", "), self.agreement_type.kind()),
+                );
+            }
+        }
+    }
+
+    /*
+    Contracts are public, so a DataOriginator must appear under an HBank pseudonym, never under the person_id of the
+    Individual behind it. The contract cannot ask the PseudonymService, so it checks the pseudonym's form.
+    */
+    fn validate_originator_pseudonyms(&self, report: &mut ValidationReport) {
+        for party in &self.parties {
+            let Party::DataOriginator(info) = party else {
+                continue;
+            };
+            let entity_id = &info.entity_id;
+            let exposes_person_id = self.individuals_map.get(entity_id).is_some_and(|i| i.get_person_id() == entity_id);
+            if exposes_person_id || !is_pseudonym_format(entity_id) {
+                report.error(
+                    ValidationCode::OriginatorNotPseudonymous,
+                    ValidationSubject::Party(entity_id.clone()),
+                    format!("Data originator {} must appear under a pseudonym issued by HBank, not a person_id.", entity_id.0),
+                );
+            }
+        }
+    }
+
+    // Keys may only be registered for parties to the contract. Missing keys are reported with the signatures.
+    fn validate_party_keys(&self, report: &mut ValidationReport) {
+        for entity_id in self.party_keys.keys() {
+            if !self.parties.iter().any(|p| p.entity_id() == entity_id) {
+                report.error(
+                    ValidationCode::PartyKeyNotAParty,
+                    ValidationSubject::Field("party_keys".into()),
+                    format!("A public key is registered for {}, who is not a party to the contract.", entity_id.0),
+                );
+            }
+        }
+    }
+
+    /*
+    A loaded status history must read as the contract's own: starting from Draft, each transition allowed and taking
+    up from the status the previous one ended in, never recorded before the previous one, and ending in the current status.
+    */
+    fn validate_status_history(&self, report: &mut ValidationReport) {
+        let mut issue = |message: String| report.error(ValidationCode::StatusHistoryInconsistent, ValidationSubject::Field("status_history".into()), message);
+        let mut status = ContractStatus::Draft;
+        let mut last_at: Option<OffsetDateTime> = None;
+        for (i, transition) in self.status_history.iter().enumerate() {
+            if transition.from != status {
+                issue(format!("Transition {} starts from {} but the contract was {}.", i + 1, transition.from, status));
+            } else if !transition.from.can_transition_to(transition.to) {
+                issue(format!("Transition {} moves the contract from {} to {}, which is not allowed.", i + 1, transition.from, transition.to));
+            }
+            if let Some(last_at) = last_at.filter(|last_at| transition.at < *last_at) {
+                issue(format!("Transition {} at {} is recorded before the one preceding it at {}.", i + 1, transition.at, last_at));
+            }
+            status = transition.to;
+            last_at = Some(transition.at);
+        }
+        if status != self.status {
+            issue(format!("The status history ends in {} but the contract is {}.", status, self.status));
+        }
+    }
+
+    fn validate_duration(&self, report: &mut ValidationReport) {
+        let field = || ValidationSubject::Field("terms.duration".into());
+        match &self.terms.duration {
+            ContractDuration::FixedEnd { end_date } => {
+                if let Some(effective_date) = self.terms.effective_date {
+                    if *end_date < effective_date {
+                        report.error(ValidationCode::TermEndsBeforeEffectiveDate, field(), format!("The contract ends on {} but only takes effect on {}.", end_date, effective_date));
+                    }
+                }
+            }
+            ContractDuration::AutoRenew { term, notice_period_days } => {
+                if term.is_zero() {
+                    report.error(ValidationCode::RenewalTermEmpty, field(), "An auto-renewing contract needs a renewal term longer than zero.");
+                } else {
+                    let start = self.terms.effective_date.unwrap_or_else(|| OffsetDateTime::now_utc().date());
+                    let term_days = (term.after(start) - start).whole_days();
+                    if *notice_period_days as i64 >= term_days {
+                        report.warning(ValidationCode::RenewalNoticeExceedsTerm, field(), format!("The notice period of {} days is not shorter than the {}-day renewal term, so notice given during a term only takes effect at the end of the next one.", notice_period_days, term_days));
+                    }
                 }
             }
+            ContractDuration::PerpetualRevocable => {}
+        }
+    }
+
+    /*
+    The parties named inside agreement_type (e.g. DirectSale { agent_a, agent_b, generators, h_bank }) and the parties list
+    must describe the same set of parties. An entity that appears in both places under different roles is reported once
+    as a role mismatch rather than as two separate omissions.
+    */
+    fn validate_category_parties(&self, report: &mut ValidationReport) {
+        let embedded = self.agreement_type.embedded_parties();
+
+        for category_party in &embedded {
+            if self.parties.contains(category_party) {
+                continue;
+            }
+            let entity_id = category_party.entity_id();
+            match self.parties.iter().find(|p| p.entity_id() == entity_id) {
+                Some(listed) => report.error(
+                    ValidationCode::CategoryPartyRoleMismatch,
+                    ValidationSubject::Party(entity_id.clone()),
+                    format!("Entity {} is named as {:?} in the agreement type but listed as {:?} in the contract parties.", entity_id.0, category_party.party_type(), listed.party_type()),
+                ),
+                None => report.error(
+                    ValidationCode::CategoryPartyNotInContract,
+                    ValidationSubject::Party(entity_id.clone()),
+                    format!("{:?} {} is named in the agreement type but is not a party to the contract.", category_party.party_type(), entity_id.0),
+                ),
+            }
+        }
+
+        for party in &self.parties {
+            let entity_id = party.entity_id();
+            if embedded.iter().any(|e| e.entity_id() == entity_id) {
+                continue;
+            }
+            report.error(
+                ValidationCode::ContractPartyNotInCategory,
+                ValidationSubject::Party(entity_id.clone()),
+                format!("{:?} {} is a party to the contract but is not named in the agreement type.", party.party_type(), entity_id.0),
+            );
+        }
+    }
+
+    // Runs every validation rule and returns the full report, including warnings.
+    pub fn validation_report(&self) -> ValidationReport {
+        let mut report = ValidationReport::new();
+        self.validate_generator_rate_spec(&mut report);
+        self.validate_individual_contribution_level(&mut report);
+        self.validate_irb_requirement(&mut report);
+        self.validate_individual_age_wrt_agency_privacy(&mut report);
+        self.validate_residual_payees(&mut report);
+        self.validate_residual_schedule(&mut report);
+        self.validate_party_roles(&mut report);
+        self.validate_category_parties(&mut report);
+        self.validate_duration(&mut report);
+        self.validate_combined_rates(&mut report);
+        self.validate_earmark(&mut report);
+        self.validate_subscription_plan(&mut report);
+        self.validate_royalty_terms(&mut report);
+        self.validate_originator_consent(&mut report);
+        self.validate_originator_pseudonyms(&mut report);
+        self.validate_party_keys(&mut report);
+        report
+    }
+
+    pub fn validate_contract(&self) -> Result<(), ValidationReport> {
+        self.validation_report().into_result()
+    }
+
+    ///////////////////////////////////////////////////////
+    //           Versioned JSON representation
+    pub fn to_document(&self) -> ContractDocument {
+        ContractDocument {
+            schema_version: CONTRACT_SCHEMA_VERSION,
+            contract_id: self.contract_id.clone(),
+            cohort_id: self.cohort_id.clone(),
+            parties: self.parties.clone(),
+            party_keys: self.party_keys.clone(),
+            agreement_type: self.agreement_type.clone(),
+            legal_framework: self.legal_framework.clone(),
+            terms: self.terms.clone(),
+            generator_rate: self.generator_rate.clone(),
+            residual_payments: self.residual_payments.clone(),
+            individual_contribution_level: self.individual_contribution_level.clone(),
+            irb_required: self.irb_required,
+            irb_approved: self.irb_approved,
+            privacy_level: self.privacy_level,
+            status: self.status,
+            status_history: self.status_history.clone(),
+            signatures: self.signatures.clone(),
+            signed_schema_version: Some(self.signed_schema_version),
+            revision: self.revision,
+            revisions: self.revisions.clone(),
+            current_amendment: self.current_amendment.clone(),
+            pending_amendment: self.pending_amendment.clone(),
+            withdrawn_amendments: self.withdrawn_amendments.clone(),
+            revocations: self.revocations.clone(),
+            non_renewal_notice: self.non_renewal_notice.clone(),
+        }
+    }
+
+    /*
+    Rebuilds a contract from its document and runs the normal validation against the supplied person records.
+    The status history is checked too, since as_of() and effective_from() rely on it being in order and ending in the status.
+    Documents of an earlier schema version must first be migrated by ContractDocument::from_json().
+    */
+    pub fn from_document(
+        document: ContractDocument,
+        individuals_map: HashMap<EntityId, Individual>,
+        corporations_map: HashMap<EntityId, Corporation>,
+    ) -> Result<Self, ContractJsonError> {
+        if document.schema_version != CONTRACT_SCHEMA_VERSION {
+            return Err(ContractJsonError::UnsupportedVersion { found: document.schema_version as u64, supported: CONTRACT_SCHEMA_VERSION });
+        }
+        let contract = HealthDataContract {
+            parties: document.parties,
+            party_keys: document.party_keys,
+            agreement_type: document.agreement_type,
+            legal_framework: document.legal_framework,
+            terms: document.terms,
+            generator_rate: document.generator_rate,
+            residual_payments: document.residual_payments,
+            individual_contribution_level: document.individual_contribution_level,
+            irb_required: document.irb_required,
+            irb_approved: document.irb_approved,
+            contract_id: document.contract_id,
+            cohort_id: document.cohort_id,
+            privacy_level: document.privacy_level,
+            individuals_map,
+            corporations_map,
+            status: document.status,
+            status_history: document.status_history,
+            signatures: document.signatures,
+            signed_schema_version: document.signed_schema_version.unwrap_or(document.schema_version),
+            revision: document.revision,
+            revisions: document.revisions,
+            current_amendment: document.current_amendment,
+            pending_amendment: document.pending_amendment,
+            withdrawn_amendments: document.withdrawn_amendments,
+            revocations: document.revocations,
+            non_renewal_notice: document.non_renewal_notice,
+        };
+        let mut report = contract.validation_report();
+        contract.validate_status_history(&mut report);
+        if matches!(contract.status, ContractStatus::Signed | ContractStatus::Active | ContractStatus::Suspended) {
+            contract.validate_signatures(&mut report);
+        }
+        report.into_result()?;
+        Ok(contract)
+    }
+
+    pub fn to_json(&self) -> Result<String, ContractJsonError> {
+        Ok(serde_json::to_string_pretty(&self.to_document())?)
+    }
+
+    pub fn from_json(
+        json: &str,
+        individuals_map: HashMap<EntityId, Individual>,
+        corporations_map: HashMap<EntityId, Corporation>,
+    ) -> Result<Self, ContractJsonError> {
+        Self::from_document(ContractDocument::from_json(json)?, individuals_map, corporations_map)
+    }
+    ///////////////////////////////////////////////////////
+
+    ///////////////////////////////////////////////////////
+    //           Signatures
+    pub fn signable_content(&self) -> SignableContent<'_> {
+        SignableContent {
+            schema_version: self.signed_schema_version,
+            contract_id: &self.contract_id,
+            revision: self.revision,
+            cohort_id: self.cohort_id.as_deref(),
+            parties: &self.parties,
+            party_keys: &self.party_keys,
+            agreement_type: &self.agreement_type,
+            legal_framework: &self.legal_framework,
+            terms: &self.terms,
+            generator_rate: self.generator_rate.as_ref(),
+            residual_payments: self.residual_payments.as_ref(),
+            individual_contribution_level: self.individual_contribution_level.as_ref(),
+            irb_required: self.irb_required,
+            irb_approved: self.irb_approved,
+            privacy_level: &self.privacy_level,
+        }
+    }
+
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        self.signable_content().canonical_bytes()
+    }
+
+    pub fn content_hash(&self) -> ContentHash {
+        self.signable_content().content_hash()
+    }
+
+    // Attaches a party's signature, replacing any earlier signature by the same party.
+    pub fn attach_signature(&mut self, signature: ContractSignature) -> Result<(), SignatureError> {
+        if self.status != ContractStatus::Proposed {
+            return Err(SignatureError::NotOpenForSignature(self.status));
+        }
+        if !self.parties.iter().any(|p| *p.entity_id() == signature.entity_id) {
+            return Err(SignatureError::NotAParty(signature.entity_id));
+        }
+        let Some(registered_key) = self.party_keys.get(&signature.entity_id) else {
+            return Err(SignatureError::KeyNotRegistered(signature.entity_id));
+        };
+        if signature.public_key != *registered_key {
+            return Err(SignatureError::KeyMismatch { entity_id: signature.entity_id });
+        }
+        let content_hash = self.content_hash();
+        if signature.content_hash != content_hash {
+            return Err(SignatureError::StaleContentHash { entity_id: signature.entity_id });
+        }
+        if !signature.verify_with(&content_hash, registered_key) {
+            return Err(SignatureError::InvalidSignature { entity_id: signature.entity_id });
+        }
+        self.signatures.retain(|s| s.entity_id != signature.entity_id);
+        self.signatures.push(signature);
+        Ok(())
+    }
+
+    pub fn sign_as(&mut self, entity_id: &EntityId, signing_key: &SigningKey) -> Result<(), SignatureError> {
+        let signature = ContractSignature::create(entity_id.clone(), self.content_hash(), signing_key);
+        self.attach_signature(signature)
+    }
+
+    // Signatures collected from now on are made under the current schema version.
+    fn invalidate_signatures(&mut self) {
+        self.signatures.clear();
+        self.signed_schema_version = CONTRACT_SCHEMA_VERSION;
+    }
+
+    /*
+    The content hash a party's signature must cover: that of the latest revision the party consented to.
+    Revision 1 needs every party; later revisions only the parties affected by the amendment that produced them.
+    */
+    fn consent_hash_for(&self, entity_id: &EntityId) -> ContentHash {
+        if self.current_amendment.as_ref().is_none_or(|a| a.affects(entity_id)) {
+            return self.content_hash();
+        }
+        self.revisions.iter().rev()
+            .find(|revision| revision.amendment.as_ref().is_none_or(|a| a.affects(entity_id)))
+            .map(|revision| revision.content_hash)
+            .unwrap_or_else(|| self.content_hash())
+    }
+
+    /*
+    Every party must have a registered key and a signature that verifies, under that key, against the revision it
+    last consented to.
+    */
+    fn validate_signatures(&self, report: &mut ValidationReport) {
+        for party in &self.parties {
+            let entity_id = party.entity_id();
+            let Some(registered_key) = self.party_keys.get(entity_id) else {
+                report.error(
+                    ValidationCode::PartyKeyMissing,
+                    ValidationSubject::Party(entity_id.clone()),
+                    format!("No public key is registered for {:?} {}.", party.party_type(), entity_id.0),
+                );
+                continue;
+            };
+            let content_hash = self.consent_hash_for(entity_id);
+            match self.signatures.iter().find(|s| s.entity_id == *entity_id) {
+                None => report.error(
+                    ValidationCode::SignatureMissing,
+                    ValidationSubject::Party(entity_id.clone()),
+                    format!("{:?} {} has not signed the contract.", party.party_type(), entity_id.0),
+                ),
+                Some(signature) if !signature.verify_with(&content_hash, registered_key) => report.error(
+                    ValidationCode::SignatureInvalid,
+                    ValidationSubject::Party(entity_id.clone()),
+                    format!("Signature from {} does not verify under its registered key against the current contract content.", entity_id.0),
+                ),
+                Some(_) => {}
+            }
+        }
+    }
+
+    pub fn signature_report(&self) -> ValidationReport {
+        let mut report = ValidationReport::new();
+        self.validate_signatures(&mut report);
+        report
+    }
+    ///////////////////////////////////////////////////////
+
+    ///////////////////////////////////////////////////////
+    //           Amendments and revision history
+    fn revision_content(&self) -> RevisionContent {
+        RevisionContent {
+            terms: self.terms.clone(),
+            generator_rate: self.generator_rate.clone(),
+            residual_payments: self.residual_payments.clone(),
+        }
+    }
+
+    // Swaps in new amendable content, returning the content it replaced.
+    fn replace_revision_content(&mut self, content: RevisionContent) -> RevisionContent {
+        RevisionContent {
+            terms: std::mem::replace(&mut self.terms, content.terms),
+            generator_rate: std::mem::replace(&mut self.generator_rate, content.generator_rate),
+            residual_payments: std::mem::replace(&mut self.residual_payments, content.residual_payments),
+        }
+    }
+
+    // Validates and hashes the contract as it would be with the given content and revision number, then restores it.
+    fn evaluate_revision(&mut self, content: RevisionContent, revision: u32) -> (ValidationReport, ContentHash) {
+        let previous_content = self.replace_revision_content(content);
+        let previous_revision = std::mem::replace(&mut self.revision, revision);
+        let report = self.validation_report();
+        let content_hash = self.content_hash();
+        self.revision = previous_revision;
+        self.replace_revision_content(previous_content);
+        (report, content_hash)
+    }
+
+    // Proposes an amendment and returns the content hash that affected parties must sign to approve it.
+    pub fn propose_amendment(&mut self, proposed_by: &EntityId, changes: AmendmentChanges) -> Result<ContentHash, AmendmentError> {
+        if !matches!(self.status, ContractStatus::Active | ContractStatus::Suspended) {
+            return Err(AmendmentError::NotAmendable(self.status));
+        }
+        if self.pending_amendment.is_some() {
+            return Err(AmendmentError::AmendmentPending);
+        }
+        if !self.parties.iter().any(|p| p.entity_id() == proposed_by) {
+            return Err(AmendmentError::NotAParty(proposed_by.clone()));
+        }
+        let current = self.revision_content();
+        let amended = changes.apply_to(&current);
+        if changes.is_empty() || amended == current {
+            return Err(AmendmentError::NoChanges);
+        }
+
+        let affected_parties = changes.affected_parties(&self.parties, &self.agreement_type, &current);
+        let (report, amended_content_hash) = self.evaluate_revision(amended, self.revision + 1);
+        report.into_result()?;
+
+        self.pending_amendment = Some(Amendment {
+            target_revision: self.revision + 1,
+            proposed_by: proposed_by.clone(),
+            proposed_at: OffsetDateTime::now_utc(),
+            changes,
+            affected_parties,
+            amended_content_hash,
+            approvals: Vec::new(),
+        });
+        Ok(amended_content_hash)
+    }
+
+    /*
+    Records an affected party's approval, which must verify under the key registered for that party.
+    Returns true once the last approval is in and the amendment has been applied.
+    */
+    pub fn approve_amendment(&mut self, approval: ContractSignature) -> Result<bool, AmendmentError> {
+        if !matches!(self.status, ContractStatus::Active | ContractStatus::Suspended) {
+            return Err(AmendmentError::NotAmendable(self.status));
+        }
+        let pending = self.pending_amendment.as_mut().ok_or(AmendmentError::NoPendingAmendment)?;
+        if !pending.affects(&approval.entity_id) {
+            return Err(AmendmentError::NotAffected(approval.entity_id));
+        }
+        let Some(registered_key) = self.party_keys.get(&approval.entity_id) else {
+            return Err(AmendmentError::KeyNotRegistered(approval.entity_id));
+        };
+        if !approval.verify_with(&pending.amended_content_hash, registered_key) {
+            return Err(AmendmentError::InvalidApproval(approval.entity_id));
+        }
+        pending.approvals.retain(|a| a.entity_id != approval.entity_id);
+        pending.approvals.push(approval);
+
+        if pending.is_fully_approved() {
+            self.apply_pending_amendment();
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    pub fn approve_amendment_as(&mut self, entity_id: &EntityId, signing_key: &SigningKey) -> Result<bool, AmendmentError> {
+        let pending = self.pending_amendment.as_ref().ok_or(AmendmentError::NoPendingAmendment)?;
+        let approval = ContractSignature::create(entity_id.clone(), pending.amended_content_hash, signing_key);
+        self.approve_amendment(approval)
+    }
+
+    // Withdraws the pending amendment; only the party that proposed it, or HBank, may do so.
+    pub fn withdraw_amendment(&mut self, withdrawn_by: &EntityId) -> Result<&WithdrawnAmendment, AmendmentError> {
+        let pending = self.pending_amendment.as_ref().ok_or(AmendmentError::NoPendingAmendment)?;
+        let is_hbank = self.parties.iter().any(|p| matches!(p, Party::HBank(info) if info.entity_id == *withdrawn_by));
+        if pending.proposed_by != *withdrawn_by && !is_hbank {
+            return Err(AmendmentError::NotAllowedToWithdraw(withdrawn_by.clone()));
+        }
+        let amendment = self.pending_amendment.take().ok_or(AmendmentError::NoPendingAmendment)?;
+        self.withdrawn_amendments.push(WithdrawnAmendment {
+            amendment,
+            withdrawn_by: withdrawn_by.clone(),
+            withdrawn_at: OffsetDateTime::now_utc(),
+        });
+        Ok(self.withdrawn_amendments.last().expect("an amendment was just withdrawn"))
+    }
+
+    fn apply_pending_amendment(&mut self) {
+        let Some(amendment) = self.pending_amendment.take() else {
+            return;
+        };
+        let current = self.revision_content();
+        let amended = amendment.changes.apply_to(&current);
+        self.revisions.push(ContractRevision {
+            number: self.revision,
+            content: current,
+            content_hash: self.content_hash(),
+            amendment: self.current_amendment.take(),
+        });
+        self.replace_revision_content(amended);
+        self.revision += 1;
+
+        // The approvals are the affected parties' signatures of the new revision.
+        for approval in &amendment.approvals {
+            self.signatures.retain(|s| s.entity_id != approval.entity_id);
+            self.signatures.push(approval.clone());
+        }
+        self.current_amendment = Some(amendment);
+    }
+
+    pub fn revision_content_at(&self, number: u32) -> Option<RevisionContent> {
+        if number == self.revision {
+            return Some(self.revision_content());
+        }
+        self.revisions.iter().find(|r| r.number == number).map(|r| r.content.clone())
+    }
+
+    pub fn diff_revisions(&self, from: u32, to: u32) -> Result<RevisionDiff, AmendmentError> {
+        let from_content = self.revision_content_at(from).ok_or(AmendmentError::UnknownRevision(from))?;
+        let to_content = self.revision_content_at(to).ok_or(AmendmentError::UnknownRevision(to))?;
+        Ok(RevisionDiff::between(from, &from_content, to, &to_content))
+    }
+    ///////////////////////////////////////////////////////
+
+    ///////////////////////////////////////////////////////
+    //           Consent revocation
+    pub fn revoke_consent(&mut self, entity_id: &EntityId) -> Result<ConsentRevocation, RevocationError> {
+        self.revoke_consent_at(entity_id, OffsetDateTime::now_utc())
+    }
+
+    pub fn revoke_consent_at(&mut self, entity_id: &EntityId, at: OffsetDateTime) -> Result<ConsentRevocation, RevocationError> {
+        if self.status.is_terminal() {
+            return Err(RevocationError::ContractEnded(self.status));
+        }
+        if !self.parties.iter().any(|p| matches!(p, Party::DataOriginator(info) if info.entity_id == *entity_id)) {
+            return Err(RevocationError::NotAnOriginator(entity_id.clone()));
+        }
+        if self.has_revoked(entity_id) {
+            return Err(RevocationError::AlreadyRevoked(entity_id.clone()));
+        }
+        let revocation = ConsentRevocation { entity_id: entity_id.clone(), contract_id: self.contract_id.clone(), revoked_at: at };
+        self.revocations.push(revocation.clone());
+        Ok(revocation)
+    }
+
+    pub fn has_revoked(&self, entity_id: &EntityId) -> bool {
+        self.revocations.iter().any(|r| r.entity_id == *entity_id)
+    }
+
+    // Whether the originator had revoked consent on or before the given day.
+    pub fn had_revoked_by(&self, entity_id: &EntityId, date: Date) -> bool {
+        self.revocations.iter().any(|r| r.entity_id == *entity_id && r.revoked_at.date() <= date)
+    }
+
+    // What the data is used for: terms.purpose if set, otherwise the purpose implied by the agreement type.
+    pub fn data_use_purpose(&self) -> DataUsePurpose {
+        self.terms.purpose.unwrap_or_else(|| self.agreement_type.kind().default_purpose())
+    }
+
+    pub fn data_categories(&self) -> Vec<DataCategory> {
+        if self.terms.data_categories.is_empty() {
+            DataCategory::ALL.to_vec()
+        } else {
+            self.terms.data_categories.clone()
+        }
+    }
+
+    // The types of the parties the data is shared with.
+    pub fn data_recipient_types(&self) -> Vec<PartyType> {
+        let mut recipient_types: Vec<PartyType> = Vec::new();
+        for party_type in self.parties.iter().map(Party::party_type) {
+            if DATA_RECEIVING_ROLES.contains(&party_type) && !recipient_types.contains(&party_type) {
+                recipient_types.push(party_type);
+            }
+        }
+        recipient_types
+    }
+
+    // The DataOriginators who have not withdrawn from this contract.
+    pub fn consenting_originators(&self) -> impl Iterator<Item = &EntityId> {
+        self.parties.iter()
+            .filter_map(|party| match party {
+                Party::DataOriginator(info) => Some(&info.entity_id),
+                _ => None,
+            })
+            .filter(|entity_id| !self.has_revoked(entity_id))
+    }
+    ///////////////////////////////////////////////////////
+
+    ///////////////////////////////////////////////////////
+    //           Effective dates, expiry and renewal
+    /*
+    as_of() answers whether the contract was in force on a given date, replaying the status history up to that day.
+    A contract is in force from the later of terms.effective_date and the day it became Active, until the last day
+    allowed by terms.duration, except while it is Suspended. Terminated and Expired contracts stay ended.
+    */
+    pub fn as_of(&self, date: Date) -> ContractStanding {
+        let status_on_date = self.status_history.iter().rev()
+            .find(|t| t.at.date() <= date)
+            .map(|t| (t.to, t.at.date()));
+        let last_day = self.last_day_in_force();
+
+        match status_on_date {
+            None | Some((ContractStatus::Draft | ContractStatus::Proposed | ContractStatus::Signed, _)) => ContractStanding::NotExecuted,
+            Some((ContractStatus::Terminated, on)) => ContractStanding::Terminated { on },
+            Some((ContractStatus::Expired, on)) => ContractStanding::Expired { last_day: last_day.unwrap_or(on) },
+            Some((ContractStatus::Active | ContractStatus::Suspended, _)) if last_day.is_some_and(|last_day| last_day < date) => {
+                ContractStanding::Expired { last_day: last_day.unwrap_or(date) }
+            }
+            Some((ContractStatus::Suspended, _)) => ContractStanding::Suspended,
+            Some((ContractStatus::Active, _)) => match self.effective_from() {
+                Some(effective_date) if date < effective_date => ContractStanding::NotYetEffective { effective_date },
+                _ => ContractStanding::InForce { last_day },
+            },
+        }
+    }
+
+    pub fn is_in_force(&self, date: Date) -> bool {
+        self.as_of(date).is_in_force()
+    }
+
+    /*
+    Whether the contract was in force on any day from from to to (inclusive).
+    A period in force can only begin on the day the contract takes effect or on a status change, so only those days
+    (and from itself) need checking.
+    */
+    pub fn was_in_force_between(&self, from: Date, to: Date) -> bool {
+        let status_changes = self.status_history.iter().map(|t| t.at.date());
+        std::iter::once(from)
+            .chain(self.effective_from())
+            .chain(status_changes)
+            .filter(|date| *date >= from && *date <= to)
+            .any(|date| self.is_in_force(date))
+    }
+
+    pub fn is_in_force_today(&self) -> bool {
+        self.is_in_force(OffsetDateTime::now_utc().date())
+    }
+
+    // The later of terms.effective_date and the day the contract first became Active; None until it has been executed.
+    pub fn effective_from(&self) -> Option<Date> {
+        let activated_on = self.status_history.iter().find(|t| t.to == ContractStatus::Active)?.at.date();
+        Some(self.terms.effective_date.map_or(activated_on, |effective_date| effective_date.max(activated_on)))
+    }
+
+    // The last day the contract can be in force under its duration, or None if it runs until terminated (or has not been executed).
+    pub fn last_day_in_force(&self) -> Option<Date> {
+        last_day_in_force(&self.terms.duration, self.effective_from()?, self.non_renewal_notice.as_ref())
+    }
+
+    // Stops an auto-renewing contract from renewing again. Returns the last day it will be in force.
+    pub fn give_non_renewal_notice(&mut self, given_by: &EntityId, given_on: Date) -> Result<Date, RenewalError> {
+        if !matches!(self.status, ContractStatus::Active | ContractStatus::Suspended) {
+            return Err(RenewalError::NotInForce(self.status));
+        }
+        if !self.parties.iter().any(|p| p.entity_id() == given_by) {
+            return Err(RenewalError::NotAParty(given_by.clone()));
+        }
+        if !matches!(self.terms.duration, ContractDuration::AutoRenew { .. }) {
+            return Err(RenewalError::NotAutoRenewing);
+        }
+        if self.non_renewal_notice.is_some() {
+            return Err(RenewalError::NoticeAlreadyGiven);
+        }
+        self.non_renewal_notice = Some(NonRenewalNotice { given_by: given_by.clone(), given_on });
+        Ok(self.last_day_in_force().unwrap_or(given_on))
+    }
+
+    /*
+    Moves an Active or Suspended contract whose last day in force is before the given date to Expired.
+    The transition is recorded at the start of the day after the last day in force. Returns whether the contract expired.
+    */
+    pub fn expire_if_due(&mut self, date: Date) -> Result<bool, LifecycleError> {
+        if !matches!(self.status, ContractStatus::Active | ContractStatus::Suspended) {
+            return Ok(false);
+        }
+        let Some(last_day) = self.last_day_in_force().filter(|last_day| *last_day < date) else {
+            return Ok(false);
+        };
+        let expired_at = last_day.next_day().unwrap_or(date).midnight().assume_utc();
+        let expired_at = self.last_transition_at().map_or(expired_at, |last| last.max(expired_at));
+        self.transition_at(ContractStatus::Expired, expired_at)?;
+        Ok(true)
+    }
+    ///////////////////////////////////////////////////////
+
+    ///////////////////////////////////////////////////////
+    //           Lifecycle transitions
+    /*
+    All status changes go through transition_to(), which checks the move is allowed by ContractStatus::can_transition_to(),
+    runs any guard attached to the target state, and records a timestamped StatusTransition. Transitions are recorded in
+    chronological order, which as_of() relies on, so one cannot be back-dated before the last.
+    Proposing and executing (Signed -> Active) a contract both require that it passes validation.
+    Signing and executing both require a valid signature from every party.
+    Reinstating a Suspended contract reruns neither: its content was validated and signed when it was executed and cannot
+    change while it is suspended other than by an amendment every affected party approved. Only the caller knows why
+    the contract was suspended, so it is up to the caller to check that reason has gone (see reinstate_at()).
+    */
+    pub fn transition_to(&mut self, next: ContractStatus) -> Result<(), LifecycleError> {
+        self.transition_at(next, OffsetDateTime::now_utc())
+    }
+
+    pub fn transition_at(&mut self, next: ContractStatus, at: OffsetDateTime) -> Result<(), LifecycleError> {
+        if !self.status.can_transition_to(next) {
+            return Err(LifecycleError::InvalidTransition { from: self.status, to: next });
+        }
+        if let Some(last) = self.last_transition_at().filter(|last| at < *last) {
+            return Err(LifecycleError::OutOfOrder { at, last });
+        }
+        let executing = next == ContractStatus::Active && self.status == ContractStatus::Signed;
+        let mut report = ValidationReport::new();
+        if next == ContractStatus::Proposed || executing {
+            report.extend(self.validation_report());
+        }
+        if next == ContractStatus::Signed || executing {
+            self.validate_signatures(&mut report);
+        }
+        report.into_result()?;
+        self.status_history.push(StatusTransition { from: self.status, to: next, at });
+        self.status = next;
+        // An ended contract can no longer be amended, so an amendment still awaiting approval lapses with it.
+        if next.is_terminal() {
+            self.pending_amendment = None;
         }
         Ok(())
     }
 
-    pub fn validate_and_execute_contract(&self) -> Result<(), ValidationError> {
-        self.validate_generator_rate_spec()?;
-        self.validate_individual_contribution_level()?;
-        self.validate_irb_requirement()?;
-        self.validate_individual_age_wrt_agency_privacy()?;
-        self.validate_residual_payees()?;
+    pub fn propose(&mut self) -> Result<(), LifecycleError> {
+        self.transition_to(ContractStatus::Proposed)
+    }
+
+    pub fn sign(&mut self) -> Result<(), LifecycleError> {
+        self.transition_to(ContractStatus::Signed)
+    }
+
+    pub fn suspend(&mut self) -> Result<(), LifecycleError> {
+        self.transition_to(ContractStatus::Suspended)
+    }
+
+    pub fn reinstate(&mut self) -> Result<(), LifecycleError> {
+        self.reinstate_at(OffsetDateTime::now_utc())
+    }
+
+    // Lifts a suspension once the reason for it has gone, without validating or checking signatures again.
+    pub fn reinstate_at(&mut self, at: OffsetDateTime) -> Result<(), LifecycleError> {
+        if self.status != ContractStatus::Suspended {
+            return Err(LifecycleError::InvalidTransition { from: self.status, to: ContractStatus::Active });
+        }
+        self.transition_at(ContractStatus::Active, at)
+    }
+
+    pub fn terminate(&mut self) -> Result<(), LifecycleError> {
+        self.transition_to(ContractStatus::Terminated)
+    }
+
+    pub fn expire(&mut self) -> Result<(), LifecycleError> {
+        self.transition_to(ContractStatus::Expired)
+    }
 
-        // Here you would add the actual execution logic
-        println!("Contract validated successfully. Ready for execution.");
-        
+    // Validates a Signed contract and moves it to Active, recording when execution took place.
+    pub fn validate_and_execute_contract(&mut self) -> Result<(), LifecycleError> {
+        if self.status != ContractStatus::Signed {
+            return Err(LifecycleError::InvalidTransition { from: self.status, to: ContractStatus::Active });
+        }
+        self.transition_to(ContractStatus::Active)?;
         Ok(())
     }
+    ///////////////////////////////////////////////////////
 }
 
 impl PartialEq for HealthDataContract {
@@ -291,4 +1220,205 @@ impl PartialEq for HealthDataContract {
     }
 }
 
-impl Eq for HealthDataContract {}
\ No newline at end of file
+impl Eq for HealthDataContract {}
+
+// A HealthDataContract serializes as its versioned ContractDocument.
+impl Serialize for HealthDataContract {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_document().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+    use crate::persons::consent::Consent;
+    use crate::test_support::{adult, execute, generator_storage, party, sale};
+
+    // A GeneratorStorageAgreement needs no individuals, which keeps lifecycle tests short.
+    fn storage_contract() -> (HealthDataContract, Vec<(EntityId, SigningKey)>) {
+        generator_storage("C-GS-001", DataPrivacyLevel::SafeHarbor)
+    }
+
+    fn executed_contract() -> HealthDataContract {
+        let (mut contract, keys) = storage_contract();
+        execute(&mut contract, &keys);
+        contract
+    }
+
+    #[test]
+    fn transition_cannot_be_back_dated_before_the_last() {
+        let mut contract = executed_contract();
+        let activated_at = contract.last_transition_at().unwrap();
+
+        let err = contract.transition_at(ContractStatus::Suspended, activated_at - Duration::days(1)).unwrap_err();
+        assert!(matches!(err, LifecycleError::OutOfOrder { last, .. } if last == activated_at));
+        assert_eq!(contract.get_status(), ContractStatus::Active);
+        assert_eq!(contract.get_status_history().len(), 3);
+    }
+
+    #[test]
+    fn standing_follows_the_history_in_order() {
+        let mut contract = executed_contract();
+        let activated_at = contract.last_transition_at().unwrap();
+        contract.transition_at(ContractStatus::Suspended, activated_at + Duration::days(2)).unwrap();
+        contract.reinstate_at(activated_at + Duration::days(4)).unwrap();
+
+        assert!(contract.is_in_force(activated_at.date()));
+        assert_eq!(contract.as_of(activated_at.date() + Duration::days(3)), ContractStanding::Suspended);
+        assert!(contract.is_in_force(activated_at.date() + Duration::days(5)));
+    }
+
+    #[test]
+    fn document_with_an_inconsistent_status_history_is_rejected() {
+        let contract = executed_contract();
+        let load = |document: ContractDocument| HealthDataContract::from_document(document, HashMap::new(), HashMap::new());
+        assert!(load(contract.to_document()).is_ok());
+
+        let mut out_of_order = contract.to_document();
+        out_of_order.status_history[2].at = out_of_order.status_history[1].at - Duration::days(1);
+        let mut broken_chain = contract.to_document();
+        broken_chain.status_history[1].from = ContractStatus::Draft;
+        let mut wrong_status = contract.to_document();
+        wrong_status.status = ContractStatus::Suspended;
+
+        for document in [out_of_order, broken_chain, wrong_status] {
+            let Err(ContractJsonError::Invalid(report)) = load(document) else {
+                panic!("an inconsistent status history was accepted");
+            };
+            assert!(report.has_code(ValidationCode::StatusHistoryInconsistent));
+        }
+    }
+
+    #[test]
+    fn signed_document_loads_through_migration() {
+        // Signed while the schema was at version 3 and stored before signed_schema_version was written out.
+        let (mut contract, keys) = storage_contract();
+        contract.signed_schema_version = 3;
+        execute(&mut contract, &keys);
+        let mut document = serde_json::to_value(contract.to_document()).unwrap();
+        document["schema_version"] = serde_json::Value::from(3);
+        document.as_object_mut().unwrap().remove("signed_schema_version");
+
+        let loaded = HealthDataContract::from_json(&document.to_string(), HashMap::new(), HashMap::new()).unwrap();
+        assert_eq!(loaded.get_status(), ContractStatus::Active);
+        assert_eq!(loaded.content_hash(), contract.content_hash());
+        assert_eq!(loaded.to_document().signed_schema_version, Some(3));
+
+        // The signatures only verify against the content as it was encoded when they were made.
+        document["signed_schema_version"] = serde_json::Value::from(CONTRACT_SCHEMA_VERSION);
+        let Err(ContractJsonError::Invalid(report)) = HealthDataContract::from_json(&document.to_string(), HashMap::new(), HashMap::new()) else {
+            panic!("signatures were checked against the wrong schema version");
+        };
+        assert!(report.has_code(ValidationCode::SignatureInvalid));
+    }
+
+    #[test]
+    fn changing_signed_content_signs_under_the_current_schema_version() {
+        let (mut contract, keys) = storage_contract();
+        contract.signed_schema_version = 3;
+        contract.set_terms(Terms::default()).unwrap();
+        assert_eq!(contract.signed_schema_version, CONTRACT_SCHEMA_VERSION);
+        execute(&mut contract, &keys);
+        assert_eq!(contract.to_document().signed_schema_version, Some(CONTRACT_SCHEMA_VERSION));
+    }
+
+    const ORIGINATOR_PSEUDONYM: &str = "PSN-0123456789abcdef0123456789abcdef";
+
+    // A DirectSale by a DataOriginator, known by an HBank pseudonym, to recipient I-R001 of their lab results.
+    fn originator_sale(consent: Consent) -> Result<HealthDataContract, ValidationReport> {
+        sale()
+            .originator(ORIGINATOR_PSEUDONYM, consent)
+            .terms(Terms { data_categories: vec![DataCategory::Labs], ..Terms::default() })
+            .build()
+    }
+
+    fn sale_consent() -> Consent {
+        Consent::new(vec![DataCategory::Labs], vec![DataUsePurpose::Commercial], vec![PartyType::DataRecipient])
+    }
+
+    #[test]
+    fn originators_consenting_to_the_sale_are_accepted() {
+        let contract = originator_sale(sale_consent()).unwrap();
+        assert_eq!(contract.consenting_originators().count(), 1);
+    }
+
+    #[test]
+    fn originators_whose_consent_misses_anything_are_rejected() {
+        let without_purpose = Consent { purposes: vec![DataUsePurpose::Research], ..sale_consent() };
+        let without_category = Consent { categories: vec![DataCategory::Genomic], ..sale_consent() };
+        let without_recipient_type = Consent { recipient_types: vec![PartyType::DataConsultant], ..sale_consent() };
+
+        for (consent, missing) in [
+            (without_purpose, "commercial use"),
+            (without_category, "sharing lab results"),
+            (without_recipient_type, "sharing with a DataRecipient"),
+        ] {
+            let report = originator_sale(consent).unwrap_err();
+            let issues: Vec<&ValidationIssue> = report.errors().collect();
+            assert_eq!(issues.len(), 1, "{}", report);
+            assert_eq!(issues[0].code, ValidationCode::ConsentNotCovered);
+            assert_eq!(issues[0].subject, ValidationSubject::Party(EntityId(ORIGINATOR_PSEUDONYM.to_string())));
+            assert!(issues[0].message.contains(missing), "{}", issues[0].message);
+        }
+    }
+
+    #[test]
+    fn custodians_and_recipients_may_be_corporations() {
+        assert!(sale().recipient("C-R001").build().is_ok());
+
+        let sale = sale().recipient("C-R001");
+        let unknown = HealthDataContract::new(
+            sale.parties(), sale.agreement_type(), ContractLegalFramework::CommonLaw, Terms::default(),
+            Some(GeneratorRateSpecification::KnowledgeRate(Rate::percent(3).unwrap())), None, None, false, None,
+            "C-DS-001".to_string(), None, DataPrivacyLevel::SafeHarbor, HashMap::new(), HashMap::new(),
+        );
+        let report = unknown.validation_report();
+        let not_found: Vec<&ValidationIssue> = report.errors().filter(|issue| issue.code == ValidationCode::IndividualNotFound).collect();
+        assert_eq!(not_found.len(), 2);
+        assert!(not_found.iter().any(|issue| issue.subject == ValidationSubject::Party(EntityId("C-R001".to_string()))));
+    }
+
+    #[test]
+    fn fees_above_the_whole_amount_are_rejected() {
+        // With the 3% generator rate, 60% + 40% leaves the custodian less than nothing.
+        let fees = |hbank_fee| Terms {
+            fees: FeeTerms { hbank_fee: Rate::percent(hbank_fee).unwrap(), consultant: Rate::ZERO, originator: Rate::percent(40).unwrap() },
+            ..Terms::default()
+        };
+        let report = sale().terms(fees(60)).build().unwrap_err();
+        assert_eq!(report.codes(), vec![ValidationCode::CombinedRatesExceedTotal]);
+        assert!(sale().terms(fees(57)).build().is_ok());
+    }
+
+    #[test]
+    fn originator_must_take_part_under_a_pseudonym() {
+        // An AgentStorageAgreement between the originator, under the given entity_id, and HBank.
+        let storage = |entity_id: &str, individual: Individual| {
+            let originator = party(PartyType::DataOriginator, entity_id);
+            let hbank = party(PartyType::HBank, "C-HB001");
+            let category = ContractCategory::TwoParty(TwoPartyLegalStructure::Storage_or_Exchange(
+                StorageExchangeLegalStructure::AgentStorageAgreement { agent: originator.clone(), h_bank: hbank.clone() },
+            ));
+            HealthDataContract::builder("C-AS-001", category)
+                .parties([originator.clone(), hbank])
+                .legal_framework(ContractLegalFramework::CommonLaw)
+                .privacy_level(DataPrivacyLevel::SafeHarbor)
+                .individual_as(originator.entity_id().clone(), individual)
+                .build()
+        };
+        let person_id = EntityId("I-O001".to_string());
+        let report = storage("I-O001", adult(&person_id)).unwrap_err();
+        assert!(report.has_code(ValidationCode::OriginatorNotPseudonymous));
+
+        // A person_id shaped like a pseudonym is still the person's own id.
+        let disguised = EntityId(ORIGINATOR_PSEUDONYM.to_string());
+        let report = storage(ORIGINATOR_PSEUDONYM, adult(&disguised)).unwrap_err();
+        assert!(report.has_code(ValidationCode::OriginatorNotPseudonymous));
+
+        let report = storage(ORIGINATOR_PSEUDONYM, adult(&person_id)).err().unwrap_or_default();
+        assert!(!report.has_code(ValidationCode::OriginatorNotPseudonymous), "{}", report);
+    }
+}
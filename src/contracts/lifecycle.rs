@@ -0,0 +1,135 @@
+use std::fmt;
+use time::OffsetDateTime;
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+
+use crate::contracts::validation::ValidationReport;
+
+/*
+Every HealthDataContract moves through the same lifecycle:
+
+    Draft -> Proposed -> Signed -> Active <-> Suspended
+                                     |           |
+                                     v           v
+                              Terminated / Expired
+
+A contract may also be terminated (cancelled) before it ever becomes Active.
+Terminated and Expired are terminal states.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum ContractStatus {
+    Draft,
+    Proposed,
+    Signed,
+    Active,
+    Suspended,
+    Terminated,
+    Expired,
+}
+
+impl ContractStatus {
+    pub fn can_transition_to(&self, next: ContractStatus) -> bool {
+        use ContractStatus::*;
+        matches!(
+            (self, next),
+            (Draft, Proposed)
+                | (Proposed, Draft)
+                | (Proposed, Signed)
+                | (Signed, Active)
+                | (Active, Suspended)
+                | (Suspended, Active)
+                | (Draft | Proposed | Signed | Active | Suspended, Terminated)
+                | (Active | Suspended, Expired)
+        )
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, ContractStatus::Terminated | ContractStatus::Expired)
+    }
+}
+
+impl fmt::Display for ContractStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// A record of a single status change, kept so the full history of a contract can be audited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct StatusTransition {
+    pub from: ContractStatus,
+    pub to: ContractStatus,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+pub enum LifecycleError {
+    InvalidTransition { from: ContractStatus, to: ContractStatus },
+    ValidationFailed(ValidationReport),
+    // The history is kept in chronological order, so a transition cannot be recorded before the last one.
+    OutOfOrder { at: OffsetDateTime, last: OffsetDateTime },
+}
+
+impl fmt::Display for LifecycleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LifecycleError::InvalidTransition { from, to } => {
+                write!(f, "Lifecycle Error: cannot move contract from {} to {}", from, to)
+            }
+            LifecycleError::ValidationFailed(e) => write!(f, "Lifecycle Error: {}", e),
+            LifecycleError::OutOfOrder { at, last } => {
+                write!(f, "Lifecycle Error: cannot record a transition at {} before the last one at {}", at, last)
+            }
+        }
+    }
+}
+
+impl From<ValidationReport> for LifecycleError {
+    fn from(e: ValidationReport) -> Self {
+        LifecycleError::ValidationFailed(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ContractStatus::*;
+
+    const ALL: [ContractStatus; 7] = [Draft, Proposed, Signed, Active, Suspended, Terminated, Expired];
+
+    #[test]
+    fn only_the_documented_transitions_are_allowed() {
+        let allowed = [
+            (Draft, Proposed),
+            (Draft, Terminated),
+            (Proposed, Draft),
+            (Proposed, Signed),
+            (Proposed, Terminated),
+            (Signed, Active),
+            (Signed, Terminated),
+            (Active, Suspended),
+            (Active, Terminated),
+            (Active, Expired),
+            (Suspended, Active),
+            (Suspended, Terminated),
+            (Suspended, Expired),
+        ];
+        for from in ALL {
+            for to in ALL {
+                assert_eq!(from.can_transition_to(to), allowed.contains(&(from, to)), "{} -> {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn terminal_states_have_no_way_out() {
+        for status in ALL {
+            assert_eq!(status.is_terminal(), matches!(status, Terminated | Expired));
+            if status.is_terminal() {
+                assert!(ALL.iter().all(|next| !status.can_transition_to(*next)));
+            }
+        }
+    }
+}
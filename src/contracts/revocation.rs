@@ -0,0 +1,38 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+use time::OffsetDateTime;
+
+use crate::contracts::structs_enums::EntityId;
+use crate::contracts::lifecycle::ContractStatus;
+
+/*
+A DataOriginator may withdraw consent from a contract at any time before it ends.
+The revocation is recorded on the contract (it does not change the signed content), and from then on
+the originator is no longer counted as a participant of the contract's cohort and their data may not be used by new jobs.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ConsentRevocation {
+    pub entity_id: EntityId,
+    pub contract_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub revoked_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+pub enum RevocationError {
+    NotAnOriginator(EntityId),
+    AlreadyRevoked(EntityId),
+    ContractEnded(ContractStatus),
+}
+
+impl fmt::Display for RevocationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevocationError::NotAnOriginator(entity_id) => write!(f, "Revocation Error: {} is not a DataOriginator of the contract", entity_id.0),
+            RevocationError::AlreadyRevoked(entity_id) => write!(f, "Revocation Error: {} has already revoked consent", entity_id.0),
+            RevocationError::ContractEnded(status) => write!(f, "Revocation Error: the contract is {} and can no longer be withdrawn from", status),
+        }
+    }
+}
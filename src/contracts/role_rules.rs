@@ -0,0 +1,285 @@
+use serde::Serialize;
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::validation::*;
+
+/*
+Which roles each agreement type allows, and how many parties may fill each role.
+
+Every AgreementKind has one table of RoleRules, one rule per slot of its legal structure
+(e.g. DirectSale { agent_a, agent_b, generators, h_bank }). A party whose PartyType is not covered by any rule
+is not allowed in that agreement. Within a table the roles of different rules never overlap,
+so each party is counted against exactly one rule.
+
+The rules are always checked against the contract's whole party set, never only against the parties being added.
+The tables are public so a UI can show what each agreement type requires.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RoleRule {
+    pub slot: &'static str,
+    pub roles: &'static [PartyType],
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct AgreementRoleRules {
+    pub kind: AgreementKind,
+    pub rules: &'static [RoleRule],
+}
+
+const fn exactly_one(slot: &'static str, roles: &'static [PartyType]) -> RoleRule {
+    RoleRule { slot, roles, min: 1, max: Some(1) }
+}
+
+const fn at_least_one(slot: &'static str, roles: &'static [PartyType]) -> RoleRule {
+    RoleRule { slot, roles, min: 1, max: None }
+}
+
+const HOLDER_ROLES: &[PartyType] = &[PartyType::DataOriginator, PartyType::DataCustodian];
+const AGENT_ROLES: &[PartyType] = &[PartyType::DataOriginator, PartyType::DataCustodian, PartyType::DataRecipient];
+const RECIPIENT_ROLES: &[PartyType] = &[PartyType::DataRecipient];
+const GENERATOR_ROLES: &[PartyType] = &[PartyType::DataGenerator];
+// The roles that receive data they did not already hold; an Individual's consent must name each one present.
+pub const DATA_RECEIVING_ROLES: &[PartyType] = &[PartyType::DataCustodian, PartyType::DataRecipient, PartyType::DataConsultant, PartyType::Advertiser];
+const H_BANK: RoleRule = exactly_one("h_bank", &[PartyType::HBank]);
+
+const AGENT_STORAGE_RULES: &[RoleRule] = &[exactly_one("agent", HOLDER_ROLES), H_BANK];
+const GENERATOR_STORAGE_RULES: &[RoleRule] = &[exactly_one("generator", GENERATOR_ROLES), H_BANK];
+const PHILANTHROPIC_RULES: &[RoleRule] = &[exactly_one("donor", &[PartyType::Donor]), H_BANK];
+const ADVERTISER_RULES: &[RoleRule] = &[exactly_one("advertiser", &[PartyType::Advertiser]), H_BANK];
+const CONSULT_RULES: &[RoleRule] = &[
+    exactly_one("agent", AGENT_ROLES),
+    exactly_one("consultant", &[PartyType::DataConsultant]),
+    H_BANK,
+];
+const BILATERAL_TRANSACTION_RULES: &[RoleRule] = &[
+    exactly_one("agent_a", HOLDER_ROLES),
+    exactly_one("agent_b", RECIPIENT_ROLES),
+    at_least_one("generators", GENERATOR_ROLES),
+    H_BANK,
+];
+const CONSORTIUM_RULES: &[RoleRule] = &[
+    at_least_one("agents", AGENT_ROLES),
+    at_least_one("generators", GENERATOR_ROLES),
+    H_BANK,
+];
+const PARTICIPATION_RULES: &[RoleRule] = &[
+    at_least_one("agents", AGENT_ROLES),
+    at_least_one("funders", &[PartyType::Funder]),
+    at_least_one("generators", GENERATOR_ROLES),
+    H_BANK,
+];
+const DATA_EXCHANGE_RULES: &[RoleRule] = &[
+    at_least_one("agents_a", HOLDER_ROLES),
+    at_least_one("agents_b", RECIPIENT_ROLES),
+    at_least_one("generators", GENERATOR_ROLES),
+    H_BANK,
+];
+
+impl AgreementKind {
+    pub fn role_rules(&self) -> AgreementRoleRules {
+        let rules = match self {
+            AgreementKind::AgentStorageAgreement | AgreementKind::AgentExchangeAgreement => AGENT_STORAGE_RULES,
+            AgreementKind::GeneratorStorageAgreement | AgreementKind::GeneratorExchangeAgreement => GENERATOR_STORAGE_RULES,
+            AgreementKind::PhilanthropicAgreement => PHILANTHROPIC_RULES,
+            AgreementKind::AdvertiserAgreement => ADVERTISER_RULES,
+            AgreementKind::ConsultAgreement => CONSULT_RULES,
+            AgreementKind::DirectSale |
+            AgreementKind::PurchaseAgreement |
+            AgreementKind::LicensingAgreement |
+            AgreementKind::AccessAgreement |
+            AgreementKind::SubscriptionAgreement => BILATERAL_TRANSACTION_RULES,
+            AgreementKind::ConsortiumAgreement => CONSORTIUM_RULES,
+            AgreementKind::ParticipationAgreement => PARTICIPATION_RULES,
+            AgreementKind::DataExchangeAgreement => DATA_EXCHANGE_RULES,
+        };
+        AgreementRoleRules { kind: *self, rules }
+    }
+}
+
+impl ContractCategory {
+    pub fn role_rules(&self) -> AgreementRoleRules {
+        self.kind().role_rules()
+    }
+}
+
+// The rules for every agreement type, in AgreementKind::ALL order.
+pub fn all_role_rules() -> Vec<AgreementRoleRules> {
+    AgreementKind::ALL.iter().map(|kind| kind.role_rules()).collect()
+}
+
+impl RoleRule {
+    pub fn covers(&self, party_type: PartyType) -> bool {
+        self.roles.contains(&party_type)
+    }
+
+    fn describe_roles(&self) -> String {
+        self.roles.iter().map(|role| format!("{:?}", role)).collect::<Vec<_>>().join(" or ")
+    }
+}
+
+impl AgreementRoleRules {
+    pub fn rule_for(&self, party_type: PartyType) -> Option<&RoleRule> {
+        self.rules.iter().find(|rule| rule.covers(party_type))
+    }
+
+    pub fn allows(&self, party_type: PartyType) -> bool {
+        self.rule_for(party_type).is_some()
+    }
+
+    pub fn min_parties(&self) -> usize {
+        self.rules.iter().map(|rule| rule.min).sum()
+    }
+
+    // None when at least one slot is unbounded.
+    pub fn max_parties(&self) -> Option<usize> {
+        self.rules.iter().map(|rule| rule.max).sum()
+    }
+
+    /*
+    Checks a party set against the table. Disallowed roles and slots over their maximum are always errors.
+    Slots under their minimum are only reported when enforce_minimums is set, so that parties can still be added
+    to a Draft contract in several steps while the final contract must be complete.
+    */
+    pub fn check(&self, parties: &[Party], enforce_minimums: bool, report: &mut ValidationReport) {
+        for party in parties {
+            if !self.allows(party.party_type()) {
+                report.error(
+                    ValidationCode::RoleNotAllowed,
+                    ValidationSubject::Party(party.entity_id().clone()),
+                    format!("{} does not allow a {:?} party.", self.kind, party.party_type()),
+                );
+            }
+        }
+
+        for rule in self.rules {
+            let count = parties.iter().filter(|party| rule.covers(party.party_type())).count();
+            if enforce_minimums && count < rule.min {
+                report.error(
+                    ValidationCode::RequiredRoleMissing,
+                    ValidationSubject::Field(format!("parties.{}", rule.slot)),
+                    format!("{} requires at least {} {} party in {}; found {}.", self.kind, rule.min, rule.describe_roles(), rule.slot, count),
+                );
+            }
+            if let Some(max) = rule.max {
+                if count > max {
+                    report.error(
+                        ValidationCode::RoleCountExceeded,
+                        ValidationSubject::Field(format!("parties.{}", rule.slot)),
+                        format!("{} allows at most {} {} party in {}; found {}.", self.kind, max, rule.describe_roles(), rule.slot, count),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::contracts::health_data_contract::HealthDataContract;
+    use crate::test_support::party;
+
+    fn custodian() -> Party {
+        party(PartyType::DataCustodian, "I-C001")
+    }
+
+    fn recipient() -> Party {
+        party(PartyType::DataRecipient, "I-R001")
+    }
+
+    fn generator(id: &str) -> Party {
+        party(PartyType::DataGenerator, id)
+    }
+
+    fn hbank() -> Party {
+        party(PartyType::HBank, "C-HB001")
+    }
+
+    fn direct_sale(agent_a: Party, generators: Vec<Party>) -> ContractCategory {
+        ContractCategory::ThreePlusParty(TransactionLegalStructure::DirectSale { agent_a, agent_b: recipient(), generators, h_bank: hbank() })
+    }
+
+    // A Draft contract straight from HealthDataContract::new(), which runs no validation, so parties can be added later.
+    fn draft(agreement_type: ContractCategory, parties: Vec<Party>) -> HealthDataContract {
+        HealthDataContract::new(
+            parties, agreement_type, ContractLegalFramework::CommonLaw, Terms::default(), None, None, None, false, None,
+            "C-DS-001".to_string(), None, DataPrivacyLevel::SafeHarbor, HashMap::new(), HashMap::new(),
+        )
+    }
+
+    fn check(parties: &[Party], enforce_minimums: bool) -> ValidationReport {
+        let mut report = ValidationReport::new();
+        AgreementKind::DirectSale.role_rules().check(parties, enforce_minimums, &mut report);
+        report
+    }
+
+    #[test]
+    fn counts_per_agreement_type() {
+        let direct_sale = AgreementKind::DirectSale.role_rules();
+        assert_eq!((direct_sale.min_parties(), direct_sale.max_parties()), (4, None));
+        let storage = AgreementKind::GeneratorStorageAgreement.role_rules();
+        assert_eq!((storage.min_parties(), storage.max_parties()), (2, Some(2)));
+        assert_eq!(all_role_rules().len(), AgreementKind::ALL.len());
+    }
+
+    #[test]
+    fn complete_party_set_passes() {
+        let parties = [custodian(), recipient(), generator("C-G001"), generator("C-G002"), hbank()];
+        assert!(check(&parties, true).is_valid());
+    }
+
+    #[test]
+    fn missing_role_is_only_reported_when_minimums_are_enforced() {
+        let parties = [custodian(), recipient(), hbank()];
+        assert!(check(&parties, false).is_valid());
+
+        let report = check(&parties, true);
+        assert_eq!(report.codes(), vec![ValidationCode::RequiredRoleMissing]);
+        assert!(report.errors().any(|issue| issue.subject == ValidationSubject::Field("parties.generators".into())));
+    }
+
+    #[test]
+    fn slot_over_its_maximum_is_reported_even_while_drafting() {
+        let parties = [custodian(), recipient(), party(PartyType::DataRecipient, "I-R002"), generator("C-G001"), hbank()];
+        for enforce_minimums in [false, true] {
+            let report = check(&parties, enforce_minimums);
+            assert_eq!(report.codes(), vec![ValidationCode::RoleCountExceeded]);
+            assert!(report.errors().any(|issue| issue.subject == ValidationSubject::Field("parties.agent_b".into())));
+        }
+    }
+
+    #[test]
+    fn role_outside_the_table_is_not_allowed() {
+        let advertiser = party(PartyType::Advertiser, "C-A001");
+        let report = check(&[custodian(), recipient(), generator("C-G001"), hbank(), advertiser.clone()], false);
+        assert_eq!(report.codes(), vec![ValidationCode::RoleNotAllowed]);
+        assert!(report.errors().any(|issue| issue.subject == ValidationSubject::Party(advertiser.entity_id().clone())));
+    }
+
+    #[test]
+    fn parties_added_in_several_calls_are_counted_together() {
+        let mut contract = draft(direct_sale(custodian(), vec![generator("C-G001")]), vec![custodian(), hbank()]);
+        contract.add_parties(vec![recipient(), generator("C-G001")]).unwrap();
+
+        let report = contract.add_parties(vec![party(PartyType::DataRecipient, "I-R002")]).unwrap_err();
+        assert!(report.has_code(ValidationCode::RoleCountExceeded));
+        assert_eq!(contract.get_parties().len(), 4);
+    }
+
+    #[test]
+    fn embedded_party_listed_under_another_role_is_a_mismatch() {
+        // The agreement type names I-C001 as the custodian selling, but the contract lists it as a DataOriginator.
+        let originator = party(PartyType::DataOriginator, "I-C001");
+        let contract = draft(direct_sale(custodian(), vec![generator("C-G001")]), vec![originator, recipient(), generator("C-G001"), hbank()]);
+
+        let report = contract.validation_report();
+        let mismatches: Vec<_> = report.errors().filter(|issue| issue.code == ValidationCode::CategoryPartyRoleMismatch).collect();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].subject, ValidationSubject::Party(EntityId("I-C001".to_string())));
+        assert!(!report.has_code(ValidationCode::RoleNotAllowed));
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use schemars::{JsonSchema, schema_for};
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::lifecycle::*;
+use crate::contracts::validation::ValidationReport;
+use crate::contracts::signatures::ContractSignature;
+use crate::contracts::amendments::{Amendment, ContractRevision, WithdrawnAmendment};
+use crate::contracts::revocation::ConsentRevocation;
+use crate::contracts::duration::NonRenewalNotice;
+
+/*
+The versioned JSON representation of a HealthDataContract.
+This is what gets persisted, sent to HBroker and published, so its shape is part of the public contract:
+any breaking change to ContractDocument must bump CONTRACT_SCHEMA_VERSION and add a step to migrate() that rewrites
+documents of the previous version. Stored documents of every earlier version can then still be read:
+    1 -> 2: a free-text residual disbursement schedule cannot be turned into a dated one, so a version 1 document
+            is only migrated if it has no residual payments,
+    2 -> 3: percentages written as numbers become Rates, and residual amounts in cents become USD Money,
+    3 -> 4: privacy level HIPPA_minus becomes Identified and HIPPA_deidentified becomes SafeHarbor.
+Migration rewrites the document, not its signatures. The signed content names the schema version it was signed under
+(signed_schema_version, which from_json() takes to be the stored version when a document leaves it out), so the
+signatures of a Signed, Active or Suspended contract still verify after migration, as long as the steps it went
+through left the signed fields as they were. Documents before version 4 register no party keys, so a signed contract
+stored in one migrates but fails the signature check and has to be signed again.
+
+The private individuals_map and corporations_map are deliberately NOT part of the document
+(contracts are public, person records are not). They are supplied again when a document is loaded,
+so that the normal validation can be run.
+*/
+pub const CONTRACT_SCHEMA_VERSION: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(title = "HealthDataContract")]
+pub struct ContractDocument {
+    pub schema_version: u32,
+    pub contract_id: String,
+    pub cohort_id: Option<String>,
+    pub parties: Vec<Party>,
+    #[serde(default)]
+    pub party_keys: BTreeMap<EntityId, String>,
+    pub agreement_type: ContractCategory,
+    pub legal_framework: ContractLegalFramework,
+    pub terms: Terms,
+    pub generator_rate: Option<GeneratorRateSpecification>,
+    pub residual_payments: Option<Residuals>,
+    pub individual_contribution_level: Option<IndividualContributionLevel>,
+    pub irb_required: bool,
+    pub irb_approved: Option<bool>,
+    pub privacy_level: DataPrivacyLevel,
+    pub status: ContractStatus,
+    pub status_history: Vec<StatusTransition>,
+    #[serde(default)]
+    pub signatures: Vec<ContractSignature>,
+    // The schema version the signatures were made under. Documents that leave it out were signed under schema_version.
+    #[serde(default)]
+    pub signed_schema_version: Option<u32>,
+    #[serde(default = "first_revision")]
+    pub revision: u32,
+    #[serde(default)]
+    pub revisions: Vec<ContractRevision>,
+    #[serde(default)]
+    pub current_amendment: Option<Amendment>,
+    #[serde(default)]
+    pub pending_amendment: Option<Amendment>,
+    #[serde(default)]
+    pub withdrawn_amendments: Vec<WithdrawnAmendment>,
+    #[serde(default)]
+    pub revocations: Vec<ConsentRevocation>,
+    #[serde(default)]
+    pub non_renewal_notice: Option<NonRenewalNotice>,
+}
+
+fn first_revision() -> u32 {
+    1
+}
+
+#[derive(Debug)]
+pub enum ContractJsonError {
+    Malformed(serde_json::Error),
+    UnsupportedVersion { found: u64, supported: u32 },
+    MigrationFailed { from: u64, reason: String },
+    Invalid(ValidationReport),
+}
+
+impl fmt::Display for ContractJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContractJsonError::Malformed(e) => write!(f, "Contract JSON Error: malformed document: {}", e),
+            ContractJsonError::UnsupportedVersion { found, supported } => {
+                write!(f, "Contract JSON Error: schema_version {} is not supported (expected 1 to {})", found, supported)
+            }
+            ContractJsonError::MigrationFailed { from, reason } => {
+                write!(f, "Contract JSON Error: version {} document cannot be migrated: {}", from, reason)
+            }
+            ContractJsonError::Invalid(report) => write!(f, "Contract JSON Error: {}", report),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ContractJsonError {
+    fn from(e: serde_json::Error) -> Self {
+        ContractJsonError::Malformed(e)
+    }
+}
+
+impl From<ValidationReport> for ContractJsonError {
+    fn from(report: ValidationReport) -> Self {
+        ContractJsonError::Invalid(report)
+    }
+}
+
+impl ContractDocument {
+    /*
+    Parses a document, checking schema_version before attempting to read the rest of the fields. Documents of an
+    earlier version are migrated to the current one first.
+    */
+    pub fn from_json(json: &str) -> Result<Self, ContractJsonError> {
+        let mut value: Value = serde_json::from_str(json)?;
+        let found = value.get("schema_version").and_then(|v| v.as_u64()).unwrap_or(0);
+        if found == 0 || found > CONTRACT_SCHEMA_VERSION as u64 {
+            return Err(ContractJsonError::UnsupportedVersion { found, supported: CONTRACT_SCHEMA_VERSION });
+        }
+        if let Some(document) = value.as_object_mut() {
+            document.entry("signed_schema_version").or_insert(Value::from(found));
+        }
+        migrate(&mut value, found)?;
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+// Rewrites a document of version from, one version at a time, into the current version.
+fn migrate(document: &mut Value, from: u64) -> Result<(), ContractJsonError> {
+    for version in from..CONTRACT_SCHEMA_VERSION as u64 {
+        match version {
+            1 => for_each_content(document, |content| {
+                if content.get("residual_payments").is_some_and(|residuals| !residuals.is_null()) {
+                    return Err("its residual disbursement schedule is free text".to_string());
+                }
+                Ok(())
+            }),
+            2 => for_each_content(document, migrate_rates_and_amounts),
+            3 => {
+                if let Some(privacy_level) = document.get_mut("privacy_level") {
+                    match privacy_level.as_str() {
+                        Some("HIPPA_minus") => *privacy_level = Value::from("Identified"),
+                        Some("HIPPA_deidentified") => *privacy_level = Value::from("SafeHarbor"),
+                        _ => {}
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+        .map_err(|reason| ContractJsonError::MigrationFailed { from, reason })?;
+    }
+    document["schema_version"] = Value::from(CONTRACT_SCHEMA_VERSION);
+    Ok(())
+}
+
+/*
+Applies a migration step to every place amendable content appears: the contract itself, each earlier revision and
+the changes of each amendment. The step sees an object holding any of terms, generator_rate and residual_payments.
+*/
+fn for_each_content(document: &mut Value, mut step: impl FnMut(&mut Map<String, Value>) -> Result<(), String>) -> Result<(), String> {
+    let Some(contract) = document.as_object_mut() else {
+        return Err("it is not a JSON object".to_string());
+    };
+    step(contract)?;
+    for key in ["current_amendment", "pending_amendment"] {
+        if let Some(changes) = contract.get_mut(key).and_then(|amendment| amendment.get_mut("changes")).and_then(Value::as_object_mut) {
+            step(changes)?;
+        }
+    }
+    for revision in contract.get_mut("revisions").and_then(Value::as_array_mut).into_iter().flatten() {
+        if let Some(content) = revision.get_mut("content").and_then(Value::as_object_mut) {
+            step(content)?;
+        }
+        if let Some(changes) = revision.get_mut("amendment").and_then(|amendment| amendment.get_mut("changes")).and_then(Value::as_object_mut) {
+            step(changes)?;
+        }
+    }
+    Ok(())
+}
+
+// Version 2 wrote percentages as numbers and residual amounts in (USD) cents.
+fn migrate_rates_and_amounts(content: &mut Map<String, Value>) -> Result<(), String> {
+    if let Some(generator_rate) = content.get_mut("generator_rate").and_then(Value::as_object_mut) {
+        for (kind, rate) in generator_rate.iter_mut() {
+            match kind.as_str() {
+                "Tiered" => {
+                    if let Some(tiers) = rate.as_object_mut() {
+                        for tier in tiers.values_mut() {
+                            *tier = percent_to_rate(tier)?;
+                        }
+                    }
+                }
+                _ => *rate = percent_to_rate(rate)?,
+            }
+        }
+    }
+    if let Some(fees) = content.get_mut("terms").and_then(|terms| terms.get_mut("fees")).and_then(Value::as_object_mut) {
+        for (old, new) in [("hbank_fee_percent", "hbank_fee"), ("consultant_percent", "consultant")] {
+            if let Some(percent) = fees.remove(old) {
+                fees.insert(new.to_string(), percent_to_rate(&percent)?);
+            }
+        }
+    }
+    if let Some(amount) = content.get_mut("residual_payments").and_then(|residuals| residuals.get_mut("amount")).and_then(Value::as_object_mut) {
+        if let Some(cents) = amount.get_mut("FixedPerDisbursement") {
+            let amount_minor = cents.as_i64().ok_or_else(|| format!("residual amount {} is not a whole number of cents", cents))?;
+            *cents = serde_json::json!({ "amount_minor": amount_minor, "currency": "USD" });
+        }
+        if let Some(percent) = amount.get_mut("PercentOfRevenue") {
+            *percent = percent_to_rate(percent)?;
+        }
+    }
+    Ok(())
+}
+
+fn percent_to_rate(percent: &Value) -> Result<Value, String> {
+    let percent = percent.as_f64().ok_or_else(|| format!("percentage {} is not a number", percent))?;
+    let rate = format!("{:.4}", percent);
+    Ok(Value::from(rate.trim_end_matches('0').trim_end_matches('.')))
+}
+
+// The published JSON Schema for ContractDocument.
+pub fn contract_json_schema() -> serde_json::Value {
+    let mut schema = serde_json::to_value(schema_for!(ContractDocument)).expect("JSON Schema is always serializable");
+    schema["$id"] = serde_json::Value::String(format!("urn:h-bank:health_data_contract:v{}", CONTRACT_SCHEMA_VERSION));
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::contracts::health_data_contract::HealthDataContract;
+    use crate::contracts::validation::ValidationCode;
+    use crate::finance::money::{Currency, Money, Rate};
+    use crate::persons::{Corporation, Individual};
+    use crate::test_support::{adult, corporation, party};
+
+    // One stored document per earlier schema version: a Draft DirectSale from custodian I-C001 to recipient I-R001.
+    const VERSION_1: &str = r#"{
+        "schema_version": 1,
+        "contract_id": "C-DS-001",
+        "cohort_id": null,
+        "parties": [
+            {"DataCustodian": {"name": "DataCustodian I-C001", "entity_id": "I-C001"}},
+            {"DataRecipient": {"name": "DataRecipient I-R001", "entity_id": "I-R001"}},
+            {"DataGenerator": {"name": "DataGenerator C-G001", "entity_id": "C-G001"}},
+            {"HBank": {"name": "HBank C-HB001", "entity_id": "C-HB001"}}
+        ],
+        "agreement_type": {"ThreePlusParty": {"DirectSale": {
+            "agent_a": {"DataCustodian": {"name": "DataCustodian I-C001", "entity_id": "I-C001"}},
+            "agent_b": {"DataRecipient": {"name": "DataRecipient I-R001", "entity_id": "I-R001"}},
+            "generators": [{"DataGenerator": {"name": "DataGenerator C-G001", "entity_id": "C-G001"}}],
+            "h_bank": {"HBank": {"name": "HBank C-HB001", "entity_id": "C-HB001"}}
+        }}},
+        "legal_framework": "CommonLaw",
+        "terms": {
+            "data_borrowers_full_list": null,
+            "data_request_explanation": null,
+            "data_request_purpose_executive_summary": null,
+            "fees": {"hbank_fee_percent": 2.5, "consultant_percent": 0}
+        },
+        "generator_rate": {"KnowledgeRate": 5},
+        "residual_payments": null,
+        "individual_contribution_level": null,
+        "irb_required": false,
+        "irb_approved": null,
+        "privacy_level": "HIPPA_minus",
+        "status": "Draft",
+        "status_history": []
+    }"#;
+
+    const VERSION_2: &str = r#"{
+        "schema_version": 2,
+        "contract_id": "C-DS-001",
+        "cohort_id": null,
+        "parties": [
+            {"DataCustodian": {"name": "DataCustodian I-C001", "entity_id": "I-C001"}},
+            {"DataRecipient": {"name": "DataRecipient I-R001", "entity_id": "I-R001"}},
+            {"DataGenerator": {"name": "DataGenerator C-G001", "entity_id": "C-G001"}},
+            {"HBank": {"name": "HBank C-HB001", "entity_id": "C-HB001"}}
+        ],
+        "agreement_type": {"ThreePlusParty": {"DirectSale": {
+            "agent_a": {"DataCustodian": {"name": "DataCustodian I-C001", "entity_id": "I-C001"}},
+            "agent_b": {"DataRecipient": {"name": "DataRecipient I-R001", "entity_id": "I-R001"}},
+            "generators": [{"DataGenerator": {"name": "DataGenerator C-G001", "entity_id": "C-G001"}}],
+            "h_bank": {"HBank": {"name": "HBank C-HB001", "entity_id": "C-HB001"}}
+        }}},
+        "legal_framework": "CommonLaw",
+        "terms": {
+            "data_borrowers_full_list": null,
+            "data_request_explanation": null,
+            "data_request_purpose_executive_summary": null,
+            "fees": {"hbank_fee_percent": 2.5, "consultant_percent": 0}
+        },
+        "generator_rate": {"Tiered": {"knowledge_rate": 5, "usage_rate": 2.5}},
+        "residual_payments": {
+            "beneficiaries": [{"DataCustodian": {"name": "DataCustodian I-C001", "entity_id": "I-C001"}}],
+            "disbursement_schedule": {"frequency": "Monthly", "start_date": "2030-01-01", "end_date": null},
+            "amount": {"FixedPerDisbursement": 1250}
+        },
+        "individual_contribution_level": null,
+        "irb_required": false,
+        "irb_approved": null,
+        "privacy_level": "HIPPA_deidentified",
+        "status": "Draft",
+        "status_history": []
+    }"#;
+
+    const VERSION_3: &str = r#"{
+        "schema_version": 3,
+        "contract_id": "C-DS-001",
+        "cohort_id": null,
+        "parties": [
+            {"DataCustodian": {"name": "DataCustodian I-C001", "entity_id": "I-C001"}},
+            {"DataRecipient": {"name": "DataRecipient I-R001", "entity_id": "I-R001"}},
+            {"DataGenerator": {"name": "DataGenerator C-G001", "entity_id": "C-G001"}},
+            {"HBank": {"name": "HBank C-HB001", "entity_id": "C-HB001"}}
+        ],
+        "agreement_type": {"ThreePlusParty": {"DirectSale": {
+            "agent_a": {"DataCustodian": {"name": "DataCustodian I-C001", "entity_id": "I-C001"}},
+            "agent_b": {"DataRecipient": {"name": "DataRecipient I-R001", "entity_id": "I-R001"}},
+            "generators": [{"DataGenerator": {"name": "DataGenerator C-G001", "entity_id": "C-G001"}}],
+            "h_bank": {"HBank": {"name": "HBank C-HB001", "entity_id": "C-HB001"}}
+        }}},
+        "legal_framework": "CommonLaw",
+        "terms": {
+            "data_borrowers_full_list": null,
+            "data_request_explanation": null,
+            "data_request_purpose_executive_summary": null,
+            "fees": {"hbank_fee": "2.5", "consultant": "0"}
+        },
+        "generator_rate": {"UsageRate": "2.5"},
+        "residual_payments": {
+            "beneficiaries": [{"DataCustodian": {"name": "DataCustodian I-C001", "entity_id": "I-C001"}}],
+            "disbursement_schedule": {"frequency": "Monthly", "start_date": "2030-01-01", "end_date": null},
+            "amount": {"FixedPerDisbursement": {"amount_minor": 1250, "currency": "USD"}}
+        },
+        "individual_contribution_level": null,
+        "irb_required": false,
+        "irb_approved": null,
+        "privacy_level": "HIPPA_deidentified",
+        "status": "Draft",
+        "status_history": []
+    }"#;
+
+    fn individuals() -> HashMap<EntityId, Individual> {
+        ["I-C001", "I-R001"].into_iter()
+            .map(|id| (EntityId(id.to_string()), adult(&EntityId(id.to_string()))))
+            .collect()
+    }
+
+    fn corporations() -> HashMap<EntityId, Corporation> {
+        [party(PartyType::DataGenerator, "C-G001"), party(PartyType::HBank, "C-HB001")].iter()
+            .map(|p| (p.entity_id().clone(), corporation(p)))
+            .collect()
+    }
+
+    // Migrating, loading and saving again gives back the migrated document unchanged.
+    fn assert_round_trips(document: &ContractDocument) {
+        let contract = HealthDataContract::from_document(document.clone(), individuals(), corporations()).unwrap();
+        assert_eq!(ContractDocument::from_json(&contract.to_json().unwrap()).unwrap(), *document);
+    }
+
+    // The stored Draft document once proposed and signed by all four parties, and executed too when active.
+    fn signed(draft: &str, active: bool) -> Value {
+        let mut document: Value = serde_json::from_str(draft).unwrap();
+        let mut status_history = vec![
+            serde_json::json!({ "from": "Draft", "to": "Proposed", "at": "2030-01-02T09:00:00Z" }),
+            serde_json::json!({ "from": "Proposed", "to": "Signed", "at": "2030-01-03T09:00:00Z" }),
+        ];
+        if active {
+            status_history.push(serde_json::json!({ "from": "Signed", "to": "Active", "at": "2030-01-04T09:00:00Z" }));
+        }
+        document["status"] = Value::from(if active { "Active" } else { "Signed" });
+        document["status_history"] = Value::from(status_history);
+        document["signatures"] = ["I-C001", "I-R001", "C-G001", "C-HB001"].iter()
+            .map(|entity_id| serde_json::json!({
+                "entity_id": entity_id,
+                "content_hash": "ab".repeat(32),
+                "public_key": "cd".repeat(32),
+                "signature": "ef".repeat(64),
+                "signed_at": "2030-01-03T09:00:00Z",
+            }))
+            .collect();
+        document
+    }
+
+    /*
+    A migrated Signed or Active document keeps its status, history and signatures, and records the version they were
+    made under. Documents before version 4 register no party keys, so the contract itself has to be signed again.
+    */
+    fn assert_signed_document_migrates(stored: &Value, from: u32) -> ContractDocument {
+        let document = ContractDocument::from_json(&stored.to_string()).unwrap();
+        assert_eq!(serde_json::to_value(document.status).unwrap(), stored["status"]);
+        assert_eq!(serde_json::to_value(&document.status_history).unwrap(), stored["status_history"]);
+        assert_eq!(serde_json::to_value(&document.signatures).unwrap(), stored["signatures"]);
+        assert_eq!(document.signed_schema_version, Some(from));
+
+        let Err(ContractJsonError::Invalid(report)) = HealthDataContract::from_document(document.clone(), individuals(), corporations()) else {
+            panic!("a version {} document without party keys passed the signature check", from);
+        };
+        assert!(report.has_code(ValidationCode::PartyKeyMissing));
+        document
+    }
+
+    fn two_and_a_half_percent() -> Rate {
+        Rate::from_basis_points(250).unwrap()
+    }
+
+    #[test]
+    fn version_1_documents_are_migrated() {
+        let document = ContractDocument::from_json(VERSION_1).unwrap();
+
+        assert_eq!(document.schema_version, CONTRACT_SCHEMA_VERSION);
+        assert_eq!(document.privacy_level, DataPrivacyLevel::Identified);
+        assert_eq!(document.generator_rate, Some(GeneratorRateSpecification::KnowledgeRate(Rate::percent(5).unwrap())));
+        assert_eq!(document.terms.fees, FeeTerms { hbank_fee: two_and_a_half_percent(), ..FeeTerms::default() });
+        assert_eq!(document.revision, 1);
+        assert_round_trips(&document);
+    }
+
+    #[test]
+    fn version_1_documents_with_residuals_are_not_migrated() {
+        let mut document: Value = serde_json::from_str(VERSION_1).unwrap();
+        document["residual_payments"] = serde_json::json!({ "beneficiaries": [], "disbursement_schedule": "monthly, from January 2030", "amount": 1250 });
+
+        let err = ContractDocument::from_json(&document.to_string()).unwrap_err();
+        assert!(matches!(err, ContractJsonError::MigrationFailed { from: 1, .. }));
+    }
+
+    #[test]
+    fn version_2_documents_are_migrated() {
+        let document = ContractDocument::from_json(VERSION_2).unwrap();
+
+        assert_eq!(document.privacy_level, DataPrivacyLevel::SafeHarbor);
+        assert_eq!(
+            document.generator_rate,
+            Some(GeneratorRateSpecification::Tiered { knowledge_rate: Rate::percent(5).unwrap(), usage_rate: two_and_a_half_percent() }),
+        );
+        assert_eq!(document.terms.fees.hbank_fee, two_and_a_half_percent());
+        let residuals = document.residual_payments.as_ref().unwrap();
+        assert_eq!(residuals.Amount, ResidualAmount::FixedPerDisbursement(Money::new(1_250, Currency::usd())));
+        assert_round_trips(&document);
+    }
+
+    #[test]
+    fn signed_and_active_version_2_documents_are_migrated() {
+        for active in [false, true] {
+            let document = assert_signed_document_migrates(&signed(VERSION_2, active), 2);
+            assert_eq!(document.terms.fees.hbank_fee, two_and_a_half_percent());
+            let residuals = document.residual_payments.as_ref().unwrap();
+            assert_eq!(residuals.Amount, ResidualAmount::FixedPerDisbursement(Money::new(1_250, Currency::usd())));
+        }
+    }
+
+    #[test]
+    fn version_3_documents_are_migrated() {
+        let document = ContractDocument::from_json(VERSION_3).unwrap();
+
+        assert_eq!(document.privacy_level, DataPrivacyLevel::SafeHarbor);
+        assert_eq!(document.generator_rate, Some(GeneratorRateSpecification::UsageRate(two_and_a_half_percent())));
+        assert!(document.party_keys.is_empty());
+        assert_round_trips(&document);
+    }
+
+    #[test]
+    fn signed_and_active_version_3_documents_are_migrated() {
+        for active in [false, true] {
+            let document = assert_signed_document_migrates(&signed(VERSION_3, active), 3);
+            assert_eq!(document.privacy_level, DataPrivacyLevel::SafeHarbor);
+            assert_eq!(document.generator_rate, Some(GeneratorRateSpecification::UsageRate(two_and_a_half_percent())));
+        }
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        for version in [0, CONTRACT_SCHEMA_VERSION as u64 + 1] {
+            let mut document: Value = serde_json::from_str(VERSION_3).unwrap();
+            document["schema_version"] = Value::from(version);
+            let err = ContractDocument::from_json(&document.to_string()).unwrap_err();
+            assert!(matches!(err, ContractJsonError::UnsupportedVersion { found, .. } if found == version));
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use ed25519_dalek::{Signature, Signer, Verifier};
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::lifecycle::ContractStatus;
+
+// Parties sign with an Ed25519 key; re-exported so callers do not need to depend on ed25519-dalek directly.
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+/*
+Contracts are public and legally binding, so each party signs the contract content.
+
+The content is encoded canonically (the JSON serialization of SignableContent, whose field order is fixed)
+and hashed with SHA-256. Each party signs that ContentHash with its own Ed25519 key.
+The public key of every party is registered with the contract when it is built and is itself part of the signed
+content, so a signature only counts if it verifies under the key registered for its party; a signature carrying any
+other key is rejected, whatever key it embeds.
+Lifecycle status, history and the signatures themselves are not part of the signed content.
+
+Because a signature covers the hash of the content, any later change to the parties or terms changes the hash,
+and the existing signatures no longer verify. Contracts also drop their signatures whenever their content is modified.
+*/
+
+/*
+The exact contract content that parties sign. Field order defines the canonical encoding; do not reorder.
+schema_version is the version the content was signed under, not the current one, so that a schema change does not by
+itself invalidate the signatures of contracts signed before it.
+*/
+#[derive(Serialize)]
+pub struct SignableContent<'a> {
+    pub schema_version: u32,
+    pub contract_id: &'a str,
+    pub revision: u32,
+    pub cohort_id: Option<&'a str>,
+    pub parties: &'a [Party],
+    pub party_keys: &'a BTreeMap<EntityId, String>,
+    pub agreement_type: &'a ContractCategory,
+    pub legal_framework: &'a ContractLegalFramework,
+    pub terms: &'a Terms,
+    pub generator_rate: Option<&'a GeneratorRateSpecification>,
+    pub residual_payments: Option<&'a Residuals>,
+    pub individual_contribution_level: Option<&'a IndividualContributionLevel>,
+    pub irb_required: bool,
+    pub irb_approved: Option<bool>,
+    pub privacy_level: &'a DataPrivacyLevel,
+}
+
+impl SignableContent<'_> {
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("contract content is always serializable")
+    }
+
+    pub fn content_hash(&self) -> ContentHash {
+        ContentHash::of(&self.canonical_bytes())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(into = "String", try_from = "String")]
+#[schemars(with = "String")]
+pub struct ContentHash(pub [u8; 32]);
+
+impl ContentHash {
+    pub fn of(bytes: &[u8]) -> Self {
+        ContentHash(Sha256::digest(bytes).into())
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl From<ContentHash> for String {
+    fn from(hash: ContentHash) -> Self {
+        hash.to_string()
+    }
+}
+
+impl TryFrom<String> for ContentHash {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let bytes = hex::decode(&value).map_err(|e| format!("Invalid content hash {}: {}", value, e))?;
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| format!("Content hash {} is not 32 bytes", value))?;
+        Ok(ContentHash(bytes))
+    }
+}
+
+// A party's Ed25519 signature over a contract ContentHash. Keys and signatures are stored hex-encoded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ContractSignature {
+    pub entity_id: EntityId,
+    pub content_hash: ContentHash,
+    pub public_key: String,
+    pub signature: String,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schemars(with = "String")]
+    pub signed_at: OffsetDateTime,
+}
+
+impl ContractSignature {
+    pub fn create(entity_id: EntityId, content_hash: ContentHash, signing_key: &SigningKey) -> Self {
+        let signature = signing_key.sign(&content_hash.0);
+        ContractSignature {
+            entity_id,
+            content_hash,
+            public_key: encode_public_key(&signing_key.verifying_key()),
+            signature: hex::encode(signature.to_bytes()),
+            signed_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    // True when the signature is a valid Ed25519 signature of the given hash under the embedded public key.
+    pub fn verify(&self, content_hash: &ContentHash) -> bool {
+        if self.content_hash != *content_hash {
+            return false;
+        }
+        let Some(public_key) = hex::decode(&self.public_key).ok().and_then(|b| <[u8; 32]>::try_from(b).ok()) else {
+            return false;
+        };
+        let Some(signature) = hex::decode(&self.signature).ok().and_then(|b| <[u8; 64]>::try_from(b).ok()) else {
+            return false;
+        };
+        let Ok(verifying_key) = VerifyingKey::from_bytes(&public_key) else {
+            return false;
+        };
+        verifying_key.verify(&content_hash.0, &Signature::from_bytes(&signature)).is_ok()
+    }
+
+    // True when the signature verifies and was made with the key registered for its party.
+    pub fn verify_with(&self, content_hash: &ContentHash, registered_key: &str) -> bool {
+        self.public_key == registered_key && self.verify(content_hash)
+    }
+}
+
+// The hex encoding public keys are registered and embedded in signatures under.
+pub fn encode_public_key(key: &VerifyingKey) -> String {
+    hex::encode(key.to_bytes())
+}
+
+#[derive(Debug)]
+pub enum SignatureError {
+    NotAParty(EntityId),
+    StaleContentHash { entity_id: EntityId },
+    InvalidSignature { entity_id: EntityId },
+    KeyNotRegistered(EntityId),
+    KeyMismatch { entity_id: EntityId },
+    NotOpenForSignature(ContractStatus),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::NotAParty(entity_id) => write!(f, "Signature Error: {} is not a party to the contract", entity_id.0),
+            SignatureError::StaleContentHash { entity_id } => write!(f, "Signature Error: signature from {} is over a different version of the contract", entity_id.0),
+            SignatureError::InvalidSignature { entity_id } => write!(f, "Signature Error: signature from {} does not verify", entity_id.0),
+            SignatureError::KeyNotRegistered(entity_id) => write!(f, "Signature Error: no public key is registered for {}", entity_id.0),
+            SignatureError::KeyMismatch { entity_id } => write!(f, "Signature Error: signature from {} was not made with its registered key", entity_id.0),
+            SignatureError::NotOpenForSignature(status) => write!(f, "Signature Error: contracts can only be signed while Proposed (status is {})", status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::{HealthDataContract, ValidationCode};
+    use crate::test_support::sale;
+
+    // A DirectSale from an individual custodian to an individual recipient, proposed and open for signature.
+    fn proposed_sale() -> (HealthDataContract, Vec<(EntityId, SigningKey)>) {
+        let sale = sale();
+        (sale.proposed(), sale.keys())
+    }
+
+    fn signed_by_everyone(contract: &mut HealthDataContract, keys: &[(EntityId, SigningKey)]) {
+        for (entity_id, signing_key) in keys {
+            contract.sign_as(entity_id, signing_key).unwrap();
+        }
+    }
+
+    fn revised_terms() -> Terms {
+        Terms { data_request_explanation: Some("Revised".to_string()), ..Terms::default() }
+    }
+
+    #[test]
+    fn fully_signed_contract_executes() {
+        let (mut contract, keys) = proposed_sale();
+        signed_by_everyone(&mut contract, &keys);
+        contract.sign().unwrap();
+        contract.validate_and_execute_contract().unwrap();
+        assert_eq!(contract.get_status(), ContractStatus::Active);
+        assert_eq!(contract.get_signatures().len(), keys.len());
+        assert!(contract.signature_report().is_valid());
+    }
+
+    #[test]
+    fn signature_with_an_unregistered_key_is_rejected() {
+        let (mut contract, _) = proposed_sale();
+
+        // A valid Ed25519 signature, but made with a key the custodian never registered.
+        let forger = SigningKey::from_bytes(&[99; 32]);
+        let custodian = EntityId("I-C001".to_string());
+        let forged = ContractSignature::create(custodian.clone(), contract.content_hash(), &forger);
+        assert!(forged.verify(&contract.content_hash()));
+
+        let err = contract.attach_signature(forged).unwrap_err();
+        assert!(matches!(err, SignatureError::KeyMismatch { entity_id } if entity_id == custodian));
+        assert!(contract.get_signatures().is_empty());
+    }
+
+    #[test]
+    fn signature_over_stale_content_is_rejected() {
+        let (mut contract, keys) = proposed_sale();
+        let (entity_id, signing_key) = &keys[0];
+        let stale = ContractSignature::create(entity_id.clone(), contract.content_hash(), signing_key);
+
+        contract.set_terms(revised_terms()).unwrap();
+        let err = contract.attach_signature(stale).unwrap_err();
+        assert!(matches!(err, SignatureError::StaleContentHash { .. }));
+    }
+
+    #[test]
+    fn changing_terms_drops_every_signature() {
+        let (mut contract, keys) = proposed_sale();
+        signed_by_everyone(&mut contract, &keys);
+        assert!(contract.signature_report().is_valid());
+
+        contract.set_terms(revised_terms()).unwrap();
+        assert!(contract.get_signatures().is_empty());
+        let report = contract.signature_report();
+        assert_eq!(report.errors().filter(|issue| issue.code == ValidationCode::SignatureMissing).count(), keys.len());
+        assert!(contract.sign().is_err());
+    }
+
+    #[test]
+    fn registering_a_new_key_drops_every_signature() {
+        let (mut contract, keys) = proposed_sale();
+        signed_by_everyone(&mut contract, &keys);
+        let rotated = SigningKey::from_bytes(&[42; 32]);
+        contract.register_party_key(EntityId("I-R001".to_string()), &rotated.verifying_key()).unwrap();
+        assert!(contract.get_signatures().is_empty());
+    }
+
+    #[test]
+    fn signed_content_cannot_be_edited() {
+        let (mut contract, keys) = proposed_sale();
+        signed_by_everyone(&mut contract, &keys);
+        contract.sign().unwrap();
+        contract.validate_and_execute_contract().unwrap();
+
+        let report = contract.set_terms(Terms::default()).unwrap_err();
+        assert!(report.has_code(ValidationCode::ContractNotEditable));
+        assert!(contract.signature_report().is_valid());
+    }
+}
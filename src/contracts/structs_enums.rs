@@ -1,17 +1,21 @@
 use std::fmt::Debug;
-use time::Date;
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+use time::Date;
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+use crate::contracts::duration::{ContractDuration, TermLength};
+use crate::finance::money::{Money, Rate};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
 pub struct EntityId(pub String);
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct PartyInfo {
     pub name: String,
     pub entity_id: EntityId,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum Party {
     HBank(PartyInfo),
     DataOriginator(PartyInfo),
@@ -25,7 +29,7 @@ pub enum Party {
 }
 
 impl Party {
-    fn new(name: String, entity_id: EntityId, party_type: PartyType) -> Self {
+    pub fn new(name: String, entity_id: EntityId, party_type: PartyType) -> Self {
         let info = PartyInfo { name, entity_id };
         match party_type {
             PartyType::HBank => Party::HBank(info),
@@ -40,7 +44,7 @@ impl Party {
         }
     }
 
-    fn as_party_info(&self) -> &PartyInfo {
+    pub fn as_party_info(&self) -> &PartyInfo {
         match self {
             Party::HBank(info) => info,
             Party::DataOriginator(info) => info,
@@ -53,9 +57,27 @@ impl Party {
             Party::Advertiser(info) => info,
         }
     }
+
+    pub fn entity_id(&self) -> &EntityId {
+        &self.as_party_info().entity_id
+    }
+
+    pub fn party_type(&self) -> PartyType {
+        match self {
+            Party::HBank(_) => PartyType::HBank,
+            Party::DataOriginator(_) => PartyType::DataOriginator,
+            Party::DataCustodian(_) => PartyType::DataCustodian,
+            Party::DataRecipient(_) => PartyType::DataRecipient,
+            Party::DataConsultant(_) => PartyType::DataConsultant,
+            Party::DataGenerator(_) => PartyType::DataGenerator,
+            Party::Funder(_) => PartyType::Funder,
+            Party::Donor(_) => PartyType::Donor,
+            Party::Advertiser(_) => PartyType::Advertiser,
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub enum PartyType {
     HBank,
     DataOriginator,
@@ -68,7 +90,7 @@ pub enum PartyType {
     Advertiser,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum StorageExchangeLegalStructure {
     AgentStorageAgreement { agent: Party, h_bank: Party },
     AgentExchangeAgreement { agent: Party, h_bank: Party },
@@ -76,17 +98,41 @@ pub enum StorageExchangeLegalStructure {
     GeneratorExchangeAgreement { generator: Party, h_bank: Party },
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum DonationLegalStructure {
-    PhilanthropicAgreement { donor: Party, h_bank: Party },
+    PhilanthropicAgreement {
+        donor: Party,
+        h_bank: Party,
+        #[serde(default)]
+        earmark: Earmark,
+    },
+}
+
+// What a donation may be spent on. Funds are drawn down as they subsidize research access fees.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum Earmark {
+    Cohort(String),
+    DiseaseArea(String),
+    #[default]
+    GeneralOperations,
+}
+
+impl std::fmt::Display for Earmark {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Earmark::Cohort(cohort_id) => write!(f, "cohort {}", cohort_id),
+            Earmark::DiseaseArea(disease_area) => write!(f, "disease area {}", disease_area),
+            Earmark::GeneralOperations => write!(f, "general operations"),
+        }
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum AdLegalStructure {
     AdvertiserAgreement { advertiser: Party, h_bank: Party },
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum TransactionLegalStructure {
     ConsultAgreement { agent: Party, consultant: Party, h_bank: Party },
     DirectSale { agent_a: Party, agent_b: Party, generators: Vec<Party>, h_bank: Party },
@@ -99,52 +145,420 @@ pub enum TransactionLegalStructure {
     DataExchangeAgreement { agents_a: Vec<Party>, agents_b: Vec<Party>, generators: Vec<Party>, h_bank: Party },
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum TwoPartyLegalStructure {
+    // Public name kept as is; renaming it would break existing callers.
+    #[allow(non_camel_case_types)]
     Storage_or_Exchange(StorageExchangeLegalStructure),
     Donation(DonationLegalStructure),
     Advertisement(AdLegalStructure),
 }
 
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum ContractCategory {
     TwoParty(TwoPartyLegalStructure),
     ThreePlusParty(TransactionLegalStructure),
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl StorageExchangeLegalStructure {
+    pub fn embedded_parties(&self) -> Vec<&Party> {
+        match self {
+            StorageExchangeLegalStructure::AgentStorageAgreement { agent, h_bank } |
+            StorageExchangeLegalStructure::AgentExchangeAgreement { agent, h_bank } => vec![agent, h_bank],
+            StorageExchangeLegalStructure::GeneratorStorageAgreement { generator, h_bank } |
+            StorageExchangeLegalStructure::GeneratorExchangeAgreement { generator, h_bank } => vec![generator, h_bank],
+        }
+    }
+}
+
+impl TwoPartyLegalStructure {
+    pub fn embedded_parties(&self) -> Vec<&Party> {
+        match self {
+            TwoPartyLegalStructure::Storage_or_Exchange(structure) => structure.embedded_parties(),
+            TwoPartyLegalStructure::Donation(DonationLegalStructure::PhilanthropicAgreement { donor, h_bank, .. }) => vec![donor, h_bank],
+            TwoPartyLegalStructure::Advertisement(AdLegalStructure::AdvertiserAgreement { advertiser, h_bank }) => vec![advertiser, h_bank],
+        }
+    }
+}
+
+impl TransactionLegalStructure {
+    pub fn embedded_parties(&self) -> Vec<&Party> {
+        match self {
+            TransactionLegalStructure::ConsultAgreement { agent, consultant, h_bank } => vec![agent, consultant, h_bank],
+            TransactionLegalStructure::DirectSale { agent_a, agent_b, generators, h_bank } |
+            TransactionLegalStructure::PurchaseAgreement { agent_a, agent_b, generators, h_bank } |
+            TransactionLegalStructure::LicensingAgreement { agent_a, agent_b, generators, h_bank } |
+            TransactionLegalStructure::AccessAgreement { agent_a, agent_b, generators, h_bank } |
+            TransactionLegalStructure::SubscriptionAgreement { agent_a, agent_b, generators, h_bank } => {
+                [agent_a, agent_b].into_iter().chain(generators).chain([h_bank]).collect()
+            },
+            TransactionLegalStructure::ConsortiumAgreement { agents, generators, h_bank } => {
+                agents.iter().chain(generators).chain([h_bank]).collect()
+            },
+            TransactionLegalStructure::ParticipationAgreement { agents, funders, generators, h_bank } => {
+                agents.iter().chain(funders).chain(generators).chain([h_bank]).collect()
+            },
+            TransactionLegalStructure::DataExchangeAgreement { agents_a, agents_b, generators, h_bank } => {
+                agents_a.iter().chain(agents_b).chain(generators).chain([h_bank]).collect()
+            },
+        }
+    }
+}
+
+impl ContractCategory {
+    // Every Party named inside the legal structure itself (as opposed to HealthDataContract::parties).
+    pub fn embedded_parties(&self) -> Vec<&Party> {
+        match self {
+            ContractCategory::TwoParty(two_party_type) => two_party_type.embedded_parties(),
+            ContractCategory::ThreePlusParty(three_plus_party_type) => three_plus_party_type.embedded_parties(),
+        }
+    }
+
+    // What the donation is earmarked for, if this is a PhilanthropicAgreement.
+    pub fn earmark(&self) -> Option<&Earmark> {
+        match self {
+            ContractCategory::TwoParty(TwoPartyLegalStructure::Donation(DonationLegalStructure::PhilanthropicAgreement { earmark, .. })) => Some(earmark),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> AgreementKind {
+        match self {
+            ContractCategory::TwoParty(TwoPartyLegalStructure::Storage_or_Exchange(structure)) => match structure {
+                StorageExchangeLegalStructure::AgentStorageAgreement { .. } => AgreementKind::AgentStorageAgreement,
+                StorageExchangeLegalStructure::AgentExchangeAgreement { .. } => AgreementKind::AgentExchangeAgreement,
+                StorageExchangeLegalStructure::GeneratorStorageAgreement { .. } => AgreementKind::GeneratorStorageAgreement,
+                StorageExchangeLegalStructure::GeneratorExchangeAgreement { .. } => AgreementKind::GeneratorExchangeAgreement,
+            },
+            ContractCategory::TwoParty(TwoPartyLegalStructure::Donation(DonationLegalStructure::PhilanthropicAgreement { .. })) => AgreementKind::PhilanthropicAgreement,
+            ContractCategory::TwoParty(TwoPartyLegalStructure::Advertisement(AdLegalStructure::AdvertiserAgreement { .. })) => AgreementKind::AdvertiserAgreement,
+            ContractCategory::ThreePlusParty(structure) => match structure {
+                TransactionLegalStructure::ConsultAgreement { .. } => AgreementKind::ConsultAgreement,
+                TransactionLegalStructure::DirectSale { .. } => AgreementKind::DirectSale,
+                TransactionLegalStructure::PurchaseAgreement { .. } => AgreementKind::PurchaseAgreement,
+                TransactionLegalStructure::LicensingAgreement { .. } => AgreementKind::LicensingAgreement,
+                TransactionLegalStructure::AccessAgreement { .. } => AgreementKind::AccessAgreement,
+                TransactionLegalStructure::SubscriptionAgreement { .. } => AgreementKind::SubscriptionAgreement,
+                TransactionLegalStructure::ConsortiumAgreement { .. } => AgreementKind::ConsortiumAgreement,
+                TransactionLegalStructure::ParticipationAgreement { .. } => AgreementKind::ParticipationAgreement,
+                TransactionLegalStructure::DataExchangeAgreement { .. } => AgreementKind::DataExchangeAgreement,
+            },
+        }
+    }
+}
+
+// The agreement type of a ContractCategory without its embedded parties, e.g. for looking up rules or listing options in a UI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum AgreementKind {
+    AgentStorageAgreement,
+    AgentExchangeAgreement,
+    GeneratorStorageAgreement,
+    GeneratorExchangeAgreement,
+    PhilanthropicAgreement,
+    AdvertiserAgreement,
+    ConsultAgreement,
+    DirectSale,
+    PurchaseAgreement,
+    LicensingAgreement,
+    AccessAgreement,
+    SubscriptionAgreement,
+    ConsortiumAgreement,
+    ParticipationAgreement,
+    DataExchangeAgreement,
+}
+
+impl AgreementKind {
+    pub const ALL: [AgreementKind; 15] = [
+        AgreementKind::AgentStorageAgreement,
+        AgreementKind::AgentExchangeAgreement,
+        AgreementKind::GeneratorStorageAgreement,
+        AgreementKind::GeneratorExchangeAgreement,
+        AgreementKind::PhilanthropicAgreement,
+        AgreementKind::AdvertiserAgreement,
+        AgreementKind::ConsultAgreement,
+        AgreementKind::DirectSale,
+        AgreementKind::PurchaseAgreement,
+        AgreementKind::LicensingAgreement,
+        AgreementKind::AccessAgreement,
+        AgreementKind::SubscriptionAgreement,
+        AgreementKind::ConsortiumAgreement,
+        AgreementKind::ParticipationAgreement,
+        AgreementKind::DataExchangeAgreement,
+    ];
+}
+
+impl std::fmt::Display for AgreementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl AgreementKind {
+    /*
+    What the data is used for under this kind of agreement, unless terms.purpose says otherwise. Storage, exchange and
+    philanthropic agreements only move data or money to HBank, so their data is held for storage only.
+    */
+    pub fn default_purpose(&self) -> DataUsePurpose {
+        match self {
+            AgreementKind::AgentStorageAgreement
+            | AgreementKind::AgentExchangeAgreement
+            | AgreementKind::GeneratorStorageAgreement
+            | AgreementKind::GeneratorExchangeAgreement
+            | AgreementKind::PhilanthropicAgreement => DataUsePurpose::Storage,
+            AgreementKind::AdvertiserAgreement => DataUsePurpose::Advertising,
+            AgreementKind::DirectSale
+            | AgreementKind::PurchaseAgreement
+            | AgreementKind::LicensingAgreement
+            | AgreementKind::SubscriptionAgreement => DataUsePurpose::Commercial,
+            AgreementKind::ConsultAgreement
+            | AgreementKind::AccessAgreement
+            | AgreementKind::ConsortiumAgreement
+            | AgreementKind::ParticipationAgreement
+            | AgreementKind::DataExchangeAgreement => DataUsePurpose::Research,
+        }
+    }
+}
+
+// The kinds of health data an Individual can consent to share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum DataCategory {
+    Genomic,
+    Hla,
+    Labs,
+    Notes,
+}
+
+impl DataCategory {
+    pub const ALL: [DataCategory; 4] = [DataCategory::Genomic, DataCategory::Hla, DataCategory::Labs, DataCategory::Notes];
+}
+
+impl std::fmt::Display for DataCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataCategory::Genomic => write!(f, "genomic data"),
+            DataCategory::Hla => write!(f, "HLA typing"),
+            DataCategory::Labs => write!(f, "lab results"),
+            DataCategory::Notes => write!(f, "clinical notes"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum DataUsePurpose {
+    // Holding the data at HBank, and exchanging it through HBank, without putting it to any further use.
+    Storage,
+    Research,
+    Commercial,
+    Advertising,
+}
+
+impl std::fmt::Display for DataUsePurpose {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataUsePurpose::Storage => write!(f, "storage by HBank"),
+            DataUsePurpose::Research => write!(f, "research"),
+            DataUsePurpose::Commercial => write!(f, "commercial use"),
+            DataUsePurpose::Advertising => write!(f, "advertising"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum ContractLegalFramework {
     UCC,
     CommonLaw,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/*
+The share of contract compensation given to the data generators.
+KnowledgeRate applies to the first transaction of a datum, UsageRate to subsequent transactions.
+A contract may fix one of them, or give both as Tiered and let settlement pick one from how often
+the data bundle has already been transacted.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum GeneratorRateSpecification {
-    KnowledgeRate(f64),
-    UsageRate(f64),
+    KnowledgeRate(Rate),
+    UsageRate(Rate),
+    Tiered { knowledge_rate: Rate, usage_rate: Rate },
+}
+
+impl GeneratorRateSpecification {
+    // The rate that applies to a transaction preceded by prior_transactions others of the same data bundle.
+    pub fn resolve(&self, prior_transactions: u64) -> GeneratorRateSpecification {
+        match self {
+            GeneratorRateSpecification::Tiered { knowledge_rate, .. } if prior_transactions == 0 => GeneratorRateSpecification::KnowledgeRate(*knowledge_rate),
+            GeneratorRateSpecification::Tiered { usage_rate, .. } => GeneratorRateSpecification::UsageRate(*usage_rate),
+            fixed => fixed.clone(),
+        }
+    }
+
+    // The highest rate the specification can pay out.
+    pub fn max_rate(&self) -> Rate {
+        match self {
+            GeneratorRateSpecification::KnowledgeRate(rate) | GeneratorRateSpecification::UsageRate(rate) => *rate,
+            GeneratorRateSpecification::Tiered { knowledge_rate, usage_rate } => *knowledge_rate.max(usage_rate),
+        }
+    }
+}
+
+// The shares of each settled amount taken by HBank and paid to any consultants and data originators on the contract.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FeeTerms {
+    pub hbank_fee: Rate,
+    pub consultant: Rate,
+    // Shared by the DataOriginators on the contract who have not revoked their consent.
+    #[serde(default)]
+    pub originator: Rate,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/*
+The plan a SubscriptionAgreement's subscriber (agent_b) pays for. Each period costs price and includes a number of
+job runs and data refreshes; usage beyond them is charged per unit. A period's bill is due payment_terms_days after
+the period ends, and access is suspended while a bill is overdue.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SubscriptionPlan {
+    pub tier: String,
+    pub price: Money,
+    pub period: TermLength,
+    pub included_job_runs: u32,
+    pub included_data_refreshes: u32,
+    pub overage_per_job_run: Money,
+    pub overage_per_data_refresh: Money,
+    pub payment_terms_days: u32,
+}
+
+/*
+Royalties a LicensingAgreement's licensee (agent_b) owes on revenue from derivative works of the licensed data.
+Revenue is reported once per reporting_period; licensor_rate of it is owed to the licensor (agent_a), and
+generator_rate is shared by the generators. Royalties are due payment_terms_days after a report is filed.
+
+The licensor is the only holder of the data on a LicensingAgreement: a DataOriginator licensing their own data, or a
+DataCustodian licensing data it holds. In the latter case the originators are not parties to the licence, and passing
+the licensor's royalties on to them is a matter between the custodian and the originators, outside this contract.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RoyaltyTerms {
+    #[serde(alias = "originator_rate")]
+    pub licensor_rate: Rate,
+    pub generator_rate: Rate,
+    pub reporting_period: TermLength,
+    pub payment_terms_days: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum IndividualContributionLevel {
     DataOnly,
     DataAndParticipation,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/*
+How far the data under a contract or in a cohort has been stripped of identifying information, loosest first.
+The levels form a partial order by strictness (a < b when b reveals less than a):
+
+    Identified < LimitedDataSet < SafeHarbor, ExpertDetermination < SyntheticOnly, AggregateOnly
+
+SafeHarbor and ExpertDetermination are two different HIPAA de-identification methods, and synthetic records and
+aggregate statistics are different kinds of output, so neither pair is comparable.
+
+Compatibility is checked with permits(): a cohort at some level admits anything at that level or a stricter one.
+A contract permitting only stricter use can therefore join a looser cohort. Its records must still not be released
+more openly than it allows, so a cohort releases at the strictest of its own level and the levels of its contracts.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub enum DataPrivacyLevel {
-    HIPPA_minus,
-    HIPPA_deidentified,
+    // The two levels before the lattice, accepted so older records can still be read.
+    #[serde(alias = "HIPPA_minus")]
+    Identified,
+    LimitedDataSet,
+    #[serde(alias = "HIPPA_deidentified")]
+    SafeHarbor,
+    ExpertDetermination,
+    SyntheticOnly,
+    AggregateOnly,
+}
+
+impl DataPrivacyLevel {
+    pub const ALL: [DataPrivacyLevel; 6] = [
+        DataPrivacyLevel::Identified,
+        DataPrivacyLevel::LimitedDataSet,
+        DataPrivacyLevel::SafeHarbor,
+        DataPrivacyLevel::ExpertDetermination,
+        DataPrivacyLevel::SyntheticOnly,
+        DataPrivacyLevel::AggregateOnly,
+    ];
+
+    // Levels on the same tier are only comparable with themselves.
+    fn tier(&self) -> u8 {
+        match self {
+            DataPrivacyLevel::Identified => 0,
+            DataPrivacyLevel::LimitedDataSet => 1,
+            DataPrivacyLevel::SafeHarbor | DataPrivacyLevel::ExpertDetermination => 2,
+            DataPrivacyLevel::SyntheticOnly | DataPrivacyLevel::AggregateOnly => 3,
+        }
+    }
+
+    pub fn is_deidentified(&self) -> bool {
+        self.tier() >= 2
+    }
+
+    // A cohort or release at this level admits a contract or release at other, i.e. when other is at least as strict.
+    pub fn permits(&self, other: DataPrivacyLevel) -> bool {
+        *self <= other
+    }
+
+    // The stricter of the two levels, or None when they are not comparable.
+    pub fn stricter_of(&self, other: DataPrivacyLevel) -> Option<DataPrivacyLevel> {
+        match self.partial_cmp(&other)? {
+            std::cmp::Ordering::Less => Some(other),
+            _ => Some(*self),
+        }
+    }
+}
+
+impl PartialOrd for DataPrivacyLevel {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        if self == other {
+            Some(std::cmp::Ordering::Equal)
+        } else if self.tier() == other.tier() {
+            None
+        } else {
+            Some(self.tier().cmp(&other.tier()))
+        }
+    }
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Terms {
     pub data_borrowers_full_list: Option<Vec<String>>,
     pub data_request_explanation: Option<String>,
     pub data_request_purpose_executive_summary: Option<String>,
+    // The contract takes effect on this date, or on the day it becomes Active if that is later.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    pub effective_date: Option<Date>,
+    #[serde(default)]
+    pub duration: ContractDuration,
+    #[serde(default)]
+    pub fees: FeeTerms,
+    #[serde(default)]
+    pub subscription: Option<SubscriptionPlan>,
+    #[serde(default)]
+    pub royalties: Option<RoyaltyTerms>,
+    // Overrides the purpose implied by the agreement type, e.g. a LicensingAgreement for academic research.
+    #[serde(default)]
+    pub purpose: Option<DataUsePurpose>,
+    // The categories of data the contract covers. Left empty, it covers all of them.
+    #[serde(default)]
+    pub data_categories: Vec<DataCategory>,
+    // The disease area the research is in, if any. Donations earmarked for that area can subsidize its access fees.
+    #[serde(default)]
+    pub disease_area: Option<String>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Residuals {
     // Residuals are a form of post-contract compensation, typically paid by the data borrower to HBank and then dispersed to the beneficiary.
     /*
@@ -152,7 +566,55 @@ pub struct Residuals {
     E.g., an LLM would continue to use the borrowed data even after the initial contract period because it effectively memorizes the data. 
     This justifies ongoing payments to the data providers.
     Details on residual payments are to be described in the contract Terms.
+    The field names predate the naming lint and are kept so existing callers still compile.
      */
+    #[serde(rename = "beneficiaries")]
     pub Beneficiaries: Vec<Party>,
-    pub DisbursementSchedule: String,
-}
\ No newline at end of file
+    #[serde(rename = "disbursement_schedule")]
+    pub DisbursementSchedule: DisbursementSchedule,
+    // What each disbursement pays, shared equally among the beneficiaries.
+    #[serde(rename = "amount")]
+    pub Amount: ResidualAmount,
+}
+
+/*
+When residuals are disbursed. Periodic schedules pay on start_date and then every period after it, up to and including
+end_date (or indefinitely if there is none). RevenueTriggered schedules pay whenever revenue is reported between the two dates.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct DisbursementSchedule {
+    pub frequency: DisbursementFrequency,
+    #[schemars(with = "String")]
+    pub start_date: Date,
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    pub end_date: Option<Date>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum DisbursementFrequency {
+    OneOff,
+    Monthly,
+    Quarterly,
+    Annually,
+    RevenueTriggered,
+}
+
+impl DisbursementFrequency {
+    // Months between disbursements, for the periodic frequencies.
+    pub fn months(&self) -> Option<u32> {
+        match self {
+            DisbursementFrequency::Monthly => Some(1),
+            DisbursementFrequency::Quarterly => Some(3),
+            DisbursementFrequency::Annually => Some(12),
+            DisbursementFrequency::OneOff | DisbursementFrequency::RevenueTriggered => None,
+        }
+    }
+}
+
+// Dated schedules pay a fixed amount per disbursement; RevenueTriggered schedules pay a share of the reported revenue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum ResidualAmount {
+    FixedPerDisbursement(Money),
+    PercentOfRevenue(Rate),
+}
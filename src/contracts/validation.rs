@@ -0,0 +1,276 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+use crate::contracts::structs_enums::EntityId;
+
+/*
+Validation of a HealthDataContract collects every violated rule into a ValidationReport
+rather than stopping at the first failure. Each ValidationIssue carries a stable ValidationCode
+(safe to match on in code and tests), a Severity, the party or field at fault, and a human-readable message.
+A report with no Error-severity issues is considered valid; Warnings are informational.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ValidationCode {
+    RequiredFieldMissing,
+    RequiredRoleMissing,
+    RoleCountExceeded,
+    RoleNotAllowed,
+    GeneratorRateUnexpected,
+    GeneratorRateMissing,
+    ContributionLevelMissing,
+    ContributionLevelWithoutOriginator,
+    IrbNotGranted,
+    IrbApprovalMissing,
+    IndividualUnderage,
+    IndividualNotFound,
+    ResidualBeneficiaryNotParty,
+    CategoryPartyNotInContract,
+    ContractPartyNotInCategory,
+    CategoryPartyRoleMismatch,
+    ContractNotEditable,
+    SignatureMissing,
+    SignatureInvalid,
+    PartyKeyMissing,
+    PartyKeyNotAParty,
+    TermEndsBeforeEffectiveDate,
+    RenewalTermEmpty,
+    RenewalNoticeExceedsTerm,
+    ResidualBeneficiariesMissing,
+    ResidualScheduleEndsBeforeStart,
+    ResidualAmountMismatch,
+    CombinedRatesExceedTotal,
+    EarmarkTargetEmpty,
+    CampaignBudgetInvalid,
+    CampaignEndsBeforeStart,
+    CampaignOutsideContractTerm,
+    TargetingIdentifiesIndividuals,
+    HealthTargetingNotAllowed,
+    SubscriptionPlanMissing,
+    SubscriptionPlanUnexpected,
+    SubscriptionPlanInvalid,
+    RoyaltyTermsUnexpected,
+    RoyaltyTermsInvalid,
+    ConsentNotCovered,
+    OriginatorNotPseudonymous,
+    StatusHistoryInconsistent,
+}
+
+impl ValidationCode {
+    // The stable string form of the code, identical to its serialized representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValidationCode::RequiredFieldMissing => "REQUIRED_FIELD_MISSING",
+            ValidationCode::RequiredRoleMissing => "REQUIRED_ROLE_MISSING",
+            ValidationCode::RoleCountExceeded => "ROLE_COUNT_EXCEEDED",
+            ValidationCode::RoleNotAllowed => "ROLE_NOT_ALLOWED",
+            ValidationCode::GeneratorRateUnexpected => "GENERATOR_RATE_UNEXPECTED",
+            ValidationCode::GeneratorRateMissing => "GENERATOR_RATE_MISSING",
+            ValidationCode::ContributionLevelMissing => "CONTRIBUTION_LEVEL_MISSING",
+            ValidationCode::ContributionLevelWithoutOriginator => "CONTRIBUTION_LEVEL_WITHOUT_ORIGINATOR",
+            ValidationCode::IrbNotGranted => "IRB_NOT_GRANTED",
+            ValidationCode::IrbApprovalMissing => "IRB_APPROVAL_MISSING",
+            ValidationCode::IndividualUnderage => "INDIVIDUAL_UNDERAGE",
+            ValidationCode::IndividualNotFound => "INDIVIDUAL_NOT_FOUND",
+            ValidationCode::ResidualBeneficiaryNotParty => "RESIDUAL_BENEFICIARY_NOT_PARTY",
+            ValidationCode::CategoryPartyNotInContract => "CATEGORY_PARTY_NOT_IN_CONTRACT",
+            ValidationCode::ContractPartyNotInCategory => "CONTRACT_PARTY_NOT_IN_CATEGORY",
+            ValidationCode::CategoryPartyRoleMismatch => "CATEGORY_PARTY_ROLE_MISMATCH",
+            ValidationCode::ContractNotEditable => "CONTRACT_NOT_EDITABLE",
+            ValidationCode::SignatureMissing => "SIGNATURE_MISSING",
+            ValidationCode::SignatureInvalid => "SIGNATURE_INVALID",
+            ValidationCode::PartyKeyMissing => "PARTY_KEY_MISSING",
+            ValidationCode::PartyKeyNotAParty => "PARTY_KEY_NOT_A_PARTY",
+            ValidationCode::TermEndsBeforeEffectiveDate => "TERM_ENDS_BEFORE_EFFECTIVE_DATE",
+            ValidationCode::RenewalTermEmpty => "RENEWAL_TERM_EMPTY",
+            ValidationCode::RenewalNoticeExceedsTerm => "RENEWAL_NOTICE_EXCEEDS_TERM",
+            ValidationCode::ResidualBeneficiariesMissing => "RESIDUAL_BENEFICIARIES_MISSING",
+            ValidationCode::ResidualScheduleEndsBeforeStart => "RESIDUAL_SCHEDULE_ENDS_BEFORE_START",
+            ValidationCode::ResidualAmountMismatch => "RESIDUAL_AMOUNT_MISMATCH",
+            ValidationCode::CombinedRatesExceedTotal => "COMBINED_RATES_EXCEED_TOTAL",
+            ValidationCode::EarmarkTargetEmpty => "EARMARK_TARGET_EMPTY",
+            ValidationCode::CampaignBudgetInvalid => "CAMPAIGN_BUDGET_INVALID",
+            ValidationCode::CampaignEndsBeforeStart => "CAMPAIGN_ENDS_BEFORE_START",
+            ValidationCode::CampaignOutsideContractTerm => "CAMPAIGN_OUTSIDE_CONTRACT_TERM",
+            ValidationCode::TargetingIdentifiesIndividuals => "TARGETING_IDENTIFIES_INDIVIDUALS",
+            ValidationCode::HealthTargetingNotAllowed => "HEALTH_TARGETING_NOT_ALLOWED",
+            ValidationCode::SubscriptionPlanMissing => "SUBSCRIPTION_PLAN_MISSING",
+            ValidationCode::SubscriptionPlanUnexpected => "SUBSCRIPTION_PLAN_UNEXPECTED",
+            ValidationCode::SubscriptionPlanInvalid => "SUBSCRIPTION_PLAN_INVALID",
+            ValidationCode::RoyaltyTermsUnexpected => "ROYALTY_TERMS_UNEXPECTED",
+            ValidationCode::RoyaltyTermsInvalid => "ROYALTY_TERMS_INVALID",
+            ValidationCode::ConsentNotCovered => "CONSENT_NOT_COVERED",
+            ValidationCode::OriginatorNotPseudonymous => "ORIGINATOR_NOT_PSEUDONYMOUS",
+            ValidationCode::StatusHistoryInconsistent => "STATUS_HISTORY_INCONSISTENT",
+        }
+    }
+}
+
+impl fmt::Display for ValidationCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// What a ValidationIssue refers to: a specific party, a named contract field, or the contract as a whole.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValidationSubject {
+    Party(EntityId),
+    Field(String),
+    Contract,
+}
+
+impl fmt::Display for ValidationSubject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationSubject::Party(entity_id) => write!(f, "party {}", entity_id.0),
+            ValidationSubject::Field(field) => write!(f, "field {}", field),
+            ValidationSubject::Contract => write!(f, "contract"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationIssue {
+    pub code: ValidationCode,
+    pub severity: Severity,
+    pub subject: ValidationSubject,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {:?} ({}): {}", self.code, self.severity, self.subject, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn new() -> Self {
+        ValidationReport { issues: Vec::new() }
+    }
+
+    pub fn error(&mut self, code: ValidationCode, subject: ValidationSubject, message: impl Into<String>) {
+        self.issues.push(ValidationIssue { code, severity: Severity::Error, subject, message: message.into() });
+    }
+
+    pub fn warning(&mut self, code: ValidationCode, subject: ValidationSubject, message: impl Into<String>) {
+        self.issues.push(ValidationIssue { code, severity: Severity::Warning, subject, message: message.into() });
+    }
+
+    pub fn extend(&mut self, other: ValidationReport) {
+        self.issues.extend(other.issues);
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Warning)
+    }
+
+    pub fn has_code(&self, code: ValidationCode) -> bool {
+        self.issues.iter().any(|issue| issue.code == code)
+    }
+
+    pub fn codes(&self) -> Vec<ValidationCode> {
+        self.issues.iter().map(|issue| issue.code).collect()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    // Ok(()) when there are no errors (warnings are allowed), otherwise the full report.
+    pub fn into_result(self) -> Result<(), ValidationReport> {
+        if self.is_valid() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Validation Report: {} error(s), {} warning(s)", self.errors().count(), self.warnings().count())?;
+        for issue in &self.issues {
+            write!(f, "\n  {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Date, Month, OffsetDateTime};
+
+    use super::*;
+    use crate::contracts::*;
+    use crate::finance::money::Rate;
+    use crate::persons::Individual;
+    use crate::test_support::sale;
+
+    #[test]
+    fn complete_contract_builds_without_issues() {
+        let contract = sale().build().unwrap();
+        assert!(contract.validation_report().is_valid());
+    }
+
+    #[test]
+    fn missing_required_fields_are_reported_together() {
+        let sale = sale();
+        let report = HealthDataContract::builder("C-DS-001", sale.agreement_type())
+            .parties(sale.parties())
+            .generator_rate(GeneratorRateSpecification::KnowledgeRate(Rate::percent(3).unwrap()))
+            .build()
+            .unwrap_err();
+
+        let missing: Vec<_> = report.errors().filter(|issue| issue.code == ValidationCode::RequiredFieldMissing).collect();
+        assert_eq!(missing.len(), 2);
+        assert!(missing.iter().any(|issue| issue.subject == ValidationSubject::Field("legal_framework".into())));
+        assert!(missing.iter().any(|issue| issue.subject == ValidationSubject::Field("privacy_level".into())));
+    }
+
+    #[test]
+    fn underage_recipient_is_rejected() {
+        let recipient = EntityId("I-R001".to_string());
+        let sixteen_years_ago = Date::from_calendar_date(OffsetDateTime::now_utc().year() - 16, Month::January, 1).unwrap();
+        let report = sale()
+            .builder()
+            .individual(Individual::new(recipient.0.clone(), recipient.clone(), sixteen_years_ago))
+            .build()
+            .unwrap_err();
+        assert!(report.errors().any(|issue| issue.code == ValidationCode::IndividualUnderage && issue.subject == ValidationSubject::Party(recipient.clone())));
+    }
+
+    #[test]
+    fn report_is_valid_with_only_warnings() {
+        let mut report = ValidationReport::new();
+        report.warning(ValidationCode::IrbApprovalMissing, ValidationSubject::Field("irb_approved".into()), "Pending.");
+        assert!(report.is_valid());
+        report.error(ValidationCode::IrbNotGranted, ValidationSubject::Field("irb_approved".into()), "Denied.");
+        assert!(!report.is_valid());
+        assert_eq!(report.codes(), vec![ValidationCode::IrbApprovalMissing, ValidationCode::IrbNotGranted]);
+        assert!(report.into_result().is_err());
+    }
+
+    #[test]
+    fn codes_serialize_to_their_stable_names() {
+        let code = ValidationCode::CombinedRatesExceedTotal;
+        assert_eq!(serde_json::to_string(&code).unwrap(), format!("\"{}\"", code.as_str()));
+        assert_eq!(code.as_str(), "COMBINED_RATES_EXCEED_TOTAL");
+    }
+}
@@ -1,5 +1,9 @@
 pub mod cohort_manager;
 pub mod synthetic_data_generator;
+pub mod deidentification;
+pub mod anonymity;
 
 pub use cohort_manager::CohortManager;
 pub use synthetic_data_generator::SyntheticDataGenerator;
+pub use deidentification::SafeHarborDeidentifier;
+pub use anonymity::AnonymityPolicy;
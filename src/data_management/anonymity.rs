@@ -0,0 +1,415 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+use crate::contracts::structs_enums::AgreementKind;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::data_management::deidentification::Table;
+
+/*
+k-anonymity and l-diversity checks on a dataset before it leaves HBank.
+
+Rows sharing the same values in every quasi-identifier column (age, ZIP prefix, sex, ...) form an equivalence class.
+A dataset is k-anonymous when every class has at least k rows, and (distinct) l-diverse when every class holds at least
+l distinct values of each sensitive attribute. Datasets released under the agreement types in RELEASE_CHECKED_AGREEMENTS
+must satisfy both; check_release() blocks them otherwise and reports the violating classes. HBank sets the policy of
+each contract in a cohort (HBankInterface::set_anonymity_policy()), with k and l of at least MIN_RELEASE_K and
+MIN_RELEASE_L, and every dataset release (HBankInterface::release_dataset()) goes through check_release().
+
+To reach the targets, suggest() generalizes the quasi-identifiers step by step (Datafly-style: always the column with
+the most distinct values left), until the rows of the remaining violating classes are few enough to suppress, i.e.
+at most max_suppressed_rows. If no column can be generalized further, every remaining violating row is suppressed,
+even beyond that limit. apply() carries out a plan, and anonymize() does both.
+*/
+pub const RELEASE_CHECKED_AGREEMENTS: [AgreementKind; 2] = [
+    AgreementKind::DirectSale,
+    AgreementKind::LicensingAgreement,
+];
+
+pub const SUPPRESSED_VALUE: &str = "*";
+
+// The smallest k and l HBank accepts for releases; with k or l of 1 every table passes.
+pub const MIN_RELEASE_K: usize = 2;
+pub const MIN_RELEASE_L: usize = 2;
+
+// How a quasi-identifier is coarsened, one level at a time. Its last level always replaces the value with "*".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Generalization {
+    // Numbers grouped into bands of each width in turn, e.g. [5, 10]: 44 -> "40-44" -> "40-49". Other values are left alone.
+    NumericBands(Vec<u32>),
+    // One more trailing character masked at each level, e.g. "100" -> "10*" -> "1**".
+    MaskSuffix { max_masked: usize },
+    SuppressOnly,
+}
+
+impl Generalization {
+    pub fn max_level(&self) -> usize {
+        match self {
+            Generalization::NumericBands(widths) => widths.len() + 1,
+            Generalization::MaskSuffix { max_masked } => max_masked + 1,
+            Generalization::SuppressOnly => 1,
+        }
+    }
+
+    pub fn generalize(&self, value: &str, level: usize) -> String {
+        if level == 0 {
+            return value.to_string();
+        }
+        if level >= self.max_level() {
+            return SUPPRESSED_VALUE.to_string();
+        }
+        match self {
+            Generalization::NumericBands(widths) => {
+                let width = widths[level - 1].max(1) as i64;
+                match value.trim().parse::<i64>() {
+                    Ok(number) => {
+                        let low = number.div_euclid(width) * width;
+                        format!("{}-{}", low, low + width - 1)
+                    }
+                    Err(_) => value.to_string(),
+                }
+            }
+            Generalization::MaskSuffix { .. } => {
+                let chars: Vec<char> = value.chars().collect();
+                let kept = chars.len().saturating_sub(level);
+                chars[..kept].iter().collect::<String>() + &SUPPRESSED_VALUE.repeat(chars.len() - kept)
+            }
+            Generalization::SuppressOnly => SUPPRESSED_VALUE.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuasiIdentifier {
+    pub column: String,
+    pub generalization: Generalization,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnonymityPolicy {
+    pub k: usize,
+    pub l: usize,
+    pub quasi_identifiers: Vec<QuasiIdentifier>,
+    pub sensitive_attributes: Vec<String>,
+    pub max_suppressed_rows: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassViolation {
+    // The class's values of the quasi-identifiers, in policy order.
+    pub quasi_identifier_values: Vec<String>,
+    pub size: usize,
+    pub below_k: bool,
+    // The sensitive attributes with fewer than l distinct values in the class.
+    pub below_l: Vec<String>,
+    pub rows: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnonymityReport {
+    pub k: usize,
+    pub l: usize,
+    pub rows: usize,
+    pub equivalence_classes: usize,
+    pub smallest_class: usize,
+    pub violations: Vec<ClassViolation>,
+}
+
+impl AnonymityReport {
+    pub fn is_satisfied(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn violating_rows(&self) -> usize {
+        self.violations.iter().map(|v| v.size).sum()
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnonymizationPlan {
+    // The generalization level of each quasi-identifier, in policy order. 0 leaves the column as it is.
+    pub generalization_levels: Vec<(String, usize)>,
+    // Indexes of the rows to drop, into the original table.
+    pub suppressed_rows: Vec<usize>,
+    pub within_suppression_limit: bool,
+}
+
+#[derive(Debug)]
+pub enum AnonymityError {
+    InvalidTarget { k: usize, l: usize },
+    TargetTooLow { k: usize, l: usize },
+    UnknownColumn(String),
+    RaggedRow { row: usize, expected: usize, found: usize },
+    PlanMismatch(String),
+    ReleaseBlocked { contract_id: String, report: AnonymityReport },
+}
+
+impl fmt::Display for AnonymityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnonymityError::InvalidTarget { k, l } => write!(f, "Anonymity Error: k and l must be at least 1 (found k = {}, l = {})", k, l),
+            AnonymityError::TargetTooLow { k, l } => write!(
+                f,
+                "Anonymity Error: releases need k of at least {} and l of at least {} (found k = {}, l = {})",
+                MIN_RELEASE_K, MIN_RELEASE_L, k, l
+            ),
+            AnonymityError::UnknownColumn(column) => write!(f, "Anonymity Error: column {} is not in the table", column),
+            AnonymityError::RaggedRow { row, expected, found } => {
+                write!(f, "Anonymity Error: row {} has {} cells but the table has {} columns", row, found, expected)
+            }
+            AnonymityError::PlanMismatch(reason) => write!(f, "Anonymity Error: the plan does not fit this policy and table: {}", reason),
+            AnonymityError::ReleaseBlocked { contract_id, report } => write!(
+                f,
+                "Anonymity Error: the dataset cannot be released under contract {}: {} equivalence class(es) covering {} row(s) miss k = {} or l = {}",
+                contract_id, report.violations.len(), report.violating_rows(), report.k, report.l
+            ),
+        }
+    }
+}
+
+impl AnonymityPolicy {
+    pub fn requires_release_check(kind: AgreementKind) -> bool {
+        RELEASE_CHECKED_AGREEMENTS.contains(&kind)
+    }
+
+    // Whether the targets are strict enough for HBank to release datasets under.
+    pub fn check_release_targets(&self) -> Result<(), AnonymityError> {
+        if self.k < MIN_RELEASE_K || self.l < MIN_RELEASE_L {
+            return Err(AnonymityError::TargetTooLow { k: self.k, l: self.l });
+        }
+        Ok(())
+    }
+
+    // The table's equivalence classes that miss k or l.
+    pub fn check(&self, table: &Table) -> Result<AnonymityReport, AnonymityError> {
+        let (qi_columns, sensitive_columns) = self.column_indexes(table)?;
+        let rows: Vec<usize> = (0..table.rows.len()).collect();
+        Ok(self.report(table, &qi_columns, &sensitive_columns, &vec![0; qi_columns.len()], &rows))
+    }
+
+    // Checks a dataset about to be released under the contract, blocking it if the agreement type requires the targets.
+    pub fn check_release(&self, contract: &HealthDataContract, table: &Table) -> Result<AnonymityReport, AnonymityError> {
+        let report = self.check(table)?;
+        if Self::requires_release_check(contract.get_agreement_type().kind()) && !report.is_satisfied() {
+            return Err(AnonymityError::ReleaseBlocked { contract_id: contract.get_contract_id().to_string(), report });
+        }
+        Ok(report)
+    }
+
+    pub fn suggest(&self, table: &Table) -> Result<AnonymizationPlan, AnonymityError> {
+        let (qi_columns, sensitive_columns) = self.column_indexes(table)?;
+        let rows: Vec<usize> = (0..table.rows.len()).collect();
+        let mut levels = vec![0; qi_columns.len()];
+        loop {
+            let report = self.report(table, &qi_columns, &sensitive_columns, &levels, &rows);
+            let within_suppression_limit = report.violating_rows() <= self.max_suppressed_rows;
+            let next = (!within_suppression_limit).then(|| self.column_to_generalize(table, &qi_columns, &levels)).flatten();
+            let Some(next) = next else {
+                let mut suppressed_rows: Vec<usize> = report.violations.iter().flat_map(|v| v.rows.iter().copied()).collect();
+                suppressed_rows.sort();
+                return Ok(AnonymizationPlan {
+                    generalization_levels: self.quasi_identifiers.iter().map(|qi| qi.column.clone()).zip(levels).collect(),
+                    suppressed_rows,
+                    within_suppression_limit,
+                });
+            };
+            levels[next] += 1;
+        }
+    }
+
+    // The table with the plan's generalizations applied and its suppressed rows removed.
+    pub fn apply(&self, plan: &AnonymizationPlan, table: &Table) -> Result<Table, AnonymityError> {
+        let (qi_columns, _) = self.column_indexes(table)?;
+        if plan.generalization_levels.len() != qi_columns.len()
+            || plan.generalization_levels.iter().zip(&self.quasi_identifiers).any(|((column, _), qi)| *column != qi.column)
+        {
+            return Err(AnonymityError::PlanMismatch("its quasi-identifiers differ from the policy's".to_string()));
+        }
+        if let Some(row) = plan.suppressed_rows.iter().find(|row| **row >= table.rows.len()) {
+            return Err(AnonymityError::PlanMismatch(format!("row {} is not in the table", row)));
+        }
+        let levels: Vec<usize> = plan.generalization_levels.iter().map(|(_, level)| *level).collect();
+        let suppressed: HashSet<usize> = plan.suppressed_rows.iter().copied().collect();
+        let rows = table.rows.iter().enumerate()
+            .filter(|(row, _)| !suppressed.contains(row))
+            .map(|(_, cells)| {
+                let mut cells = cells.clone();
+                for (qi, (column, level)) in self.quasi_identifiers.iter().zip(qi_columns.iter().zip(&levels)) {
+                    cells[*column] = qi.generalization.generalize(&cells[*column], *level);
+                }
+                cells
+            })
+            .collect();
+        Ok(Table::new(table.columns.clone(), rows))
+    }
+
+    // Suggests a plan and applies it, returning the anonymized table with the plan and the report on the result.
+    pub fn anonymize(&self, table: &Table) -> Result<(Table, AnonymizationPlan, AnonymityReport), AnonymityError> {
+        let plan = self.suggest(table)?;
+        let anonymized = self.apply(&plan, table)?;
+        let report = self.check(&anonymized)?;
+        Ok((anonymized, plan, report))
+    }
+
+    fn column_indexes(&self, table: &Table) -> Result<(Vec<usize>, Vec<usize>), AnonymityError> {
+        if self.k == 0 || self.l == 0 {
+            return Err(AnonymityError::InvalidTarget { k: self.k, l: self.l });
+        }
+        if let Some((row, cells)) = table.rows.iter().enumerate().find(|(_, cells)| cells.len() != table.columns.len()) {
+            return Err(AnonymityError::RaggedRow { row, expected: table.columns.len(), found: cells.len() });
+        }
+        let index = |column: &String| table.column_index(column).ok_or_else(|| AnonymityError::UnknownColumn(column.clone()));
+        let qi_columns = self.quasi_identifiers.iter().map(|qi| index(&qi.column)).collect::<Result<_, _>>()?;
+        let sensitive_columns = self.sensitive_attributes.iter().map(index).collect::<Result<_, _>>()?;
+        Ok((qi_columns, sensitive_columns))
+    }
+
+    fn report(&self, table: &Table, qi_columns: &[usize], sensitive_columns: &[usize], levels: &[usize], rows: &[usize]) -> AnonymityReport {
+        let mut classes: BTreeMap<Vec<String>, Vec<usize>> = BTreeMap::new();
+        for &row in rows {
+            let key = self.quasi_identifiers.iter().zip(qi_columns.iter().zip(levels))
+                .map(|(qi, (column, level))| qi.generalization.generalize(&table.rows[row][*column], *level))
+                .collect();
+            classes.entry(key).or_default().push(row);
+        }
+
+        let violations = classes.iter()
+            .filter_map(|(values, class_rows)| {
+                let below_k = class_rows.len() < self.k;
+                let below_l: Vec<String> = self.sensitive_attributes.iter().zip(sensitive_columns)
+                    .filter(|(_, column)| class_rows.iter().map(|row| &table.rows[*row][**column]).collect::<HashSet<_>>().len() < self.l)
+                    .map(|(attribute, _)| attribute.clone())
+                    .collect();
+                (below_k || !below_l.is_empty()).then(|| ClassViolation {
+                    quasi_identifier_values: values.clone(),
+                    size: class_rows.len(),
+                    below_k,
+                    below_l,
+                    rows: class_rows.clone(),
+                })
+            })
+            .collect();
+
+        AnonymityReport {
+            k: self.k,
+            l: self.l,
+            rows: rows.len(),
+            equivalence_classes: classes.len(),
+            smallest_class: classes.values().map(Vec::len).min().unwrap_or(0),
+            violations,
+        }
+    }
+
+    // The quasi-identifier that can still be generalized and has the most distinct values at its current level.
+    fn column_to_generalize(&self, table: &Table, qi_columns: &[usize], levels: &[usize]) -> Option<usize> {
+        self.quasi_identifiers.iter().enumerate()
+            .filter(|(index, qi)| levels[*index] < qi.generalization.max_level())
+            .max_by_key(|(index, qi)| {
+                let distinct = table.rows.iter()
+                    .map(|cells| qi.generalization.generalize(&cells[qi_columns[*index]], levels[*index]))
+                    .collect::<HashSet<_>>()
+                    .len();
+                // Ties go to the first quasi-identifier in policy order.
+                (distinct, std::cmp::Reverse(*index))
+            })
+            .map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::sale;
+
+    fn policy(k: usize, l: usize) -> AnonymityPolicy {
+        AnonymityPolicy {
+            k,
+            l,
+            quasi_identifiers: vec![
+                QuasiIdentifier { column: "age".to_string(), generalization: Generalization::NumericBands(vec![10, 20]) },
+                QuasiIdentifier { column: "zip".to_string(), generalization: Generalization::MaskSuffix { max_masked: 3 } },
+            ],
+            sensitive_attributes: vec!["diagnosis".to_string()],
+            max_suppressed_rows: 0,
+        }
+    }
+
+    fn table(rows: &[[&str; 3]]) -> Table {
+        Table::new(
+            vec!["age".to_string(), "zip".to_string(), "diagnosis".to_string()],
+            rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect(),
+        )
+    }
+
+    fn patients() -> Table {
+        table(&[
+            ["31", "021", "asthma"],
+            ["34", "022", "diabetes"],
+            ["36", "023", "asthma"],
+            ["38", "024", "copd"],
+        ])
+    }
+
+    #[test]
+    fn unique_rows_miss_k() {
+        let report = policy(2, 1).check(&patients()).unwrap();
+        assert!(!report.is_satisfied());
+        assert_eq!(report.equivalence_classes, 4);
+        assert_eq!(report.smallest_class, 1);
+        assert!(report.violations.iter().all(|v| v.below_k && v.below_l.is_empty()));
+        assert_eq!(report.violating_rows(), 4);
+    }
+
+    #[test]
+    fn class_with_one_diagnosis_misses_l() {
+        let rows = table(&[
+            ["31", "021", "asthma"],
+            ["31", "021", "asthma"],
+            ["45", "100", "asthma"],
+            ["45", "100", "copd"],
+        ]);
+        let report = policy(2, 2).check(&rows).unwrap();
+        assert_eq!(report.violations.len(), 1);
+        let violation = &report.violations[0];
+        assert!(!violation.below_k);
+        assert_eq!(violation.below_l, vec!["diagnosis".to_string()]);
+        assert_eq!(violation.rows, vec![0, 1]);
+    }
+
+    #[test]
+    fn anonymize_reaches_k_and_l() {
+        let (anonymized, plan, report) = policy(2, 2).anonymize(&patients()).unwrap();
+        assert!(report.is_satisfied());
+        assert!(plan.within_suppression_limit);
+        assert_eq!(anonymized.rows.len(), 4);
+        assert!(anonymized.rows.iter().all(|row| row[0] == "30-39"));
+    }
+
+    #[test]
+    fn zero_targets_are_rejected() {
+        assert!(matches!(policy(0, 1).check(&patients()), Err(AnonymityError::InvalidTarget { .. })));
+    }
+
+    #[test]
+    fn releases_need_targets_above_one() {
+        assert!(matches!(policy(1, 2).check_release_targets(), Err(AnonymityError::TargetTooLow { k: 1, l: 2 })));
+        assert!(matches!(policy(2, 1).check_release_targets(), Err(AnonymityError::TargetTooLow { k: 2, l: 1 })));
+        assert!(policy(MIN_RELEASE_K, MIN_RELEASE_L).check_release_targets().is_ok());
+    }
+
+    #[test]
+    fn release_under_a_direct_sale_is_blocked_until_anonymized() {
+        let contract = sale().executed();
+        let policy = policy(2, 2);
+
+        let err = policy.check_release(&contract, &patients()).unwrap_err();
+        assert!(matches!(err, AnonymityError::ReleaseBlocked { report, .. } if report.violating_rows() == 4));
+
+        let (anonymized, _, _) = policy.anonymize(&patients()).unwrap();
+        assert!(policy.check_release(&contract, &anonymized).unwrap().is_satisfied());
+    }
+}
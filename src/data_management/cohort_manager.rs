@@ -1,7 +1,10 @@
 use std::collections::{HashMap,HashSet};
 use crate::contracts::health_data_contract::HealthDataContract;
-use crate::contracts::structs_enums::{EntityId, DataPrivacyLevel, Party};
+use crate::contracts::structs_enums::{EntityId, DataPrivacyLevel};
+use crate::contracts::revocation::ConsentRevocation;
+use crate::data_management::anonymity::AnonymityPolicy;
 use serde::{Serialize, Deserialize};
+use time::{Date, OffsetDateTime};
 
 pub struct CohortManager {
     cohorts: HashMap<String, Cohort>,
@@ -12,6 +15,8 @@ pub struct Cohort {
     contracts: Vec<HealthDataContract>,
     privacy_level: DataPrivacyLevel,
     total_participants: usize,
+    // The k-anonymity and l-diversity targets HBank set for releases under each contract, by contract id.
+    anonymity_policies: HashMap<String, AnonymityPolicy>,
 }
 
 impl CohortManager {
@@ -31,6 +36,7 @@ impl CohortManager {
             contracts: Vec::new(),
             privacy_level,
             total_participants: 0,
+            anonymity_policies: HashMap::new(),
         };
 
         self.cohorts.insert(cohort_id, new_cohort);
@@ -40,8 +46,20 @@ impl CohortManager {
     pub fn add_contract_to_cohort(&mut self, cohort_id: &str, contract: HealthDataContract) -> Result<(), String> {
         let cohort = self.cohorts.get_mut(cohort_id).ok_or_else(|| format!("Cohort with ID {} not found", cohort_id))?;
 
-        if cohort.privacy_level != *contract.get_privacy_level() {
-            return Err("Contract privacy level does not match cohort privacy level".to_string());
+        if !contract.is_active() {
+            return Err(format!("Contract {} is {}; only Active contracts can join a cohort", contract.get_contract_id(), contract.get_status()));
+        }
+
+        if let Some(last_day) = contract.last_day_in_force().filter(|last_day| *last_day < today()) {
+            return Err(format!("Contract {} expired on {} and can no longer join a cohort", contract.get_contract_id(), last_day));
+        }
+
+        // A contract at the cohort's level or a stricter one may join it.
+        if !cohort.privacy_level.permits(*contract.get_privacy_level()) {
+            return Err(format!(
+                "Contract {} is at {:?}, which is neither {:?} nor stricter, so it cannot join cohort {}",
+                contract.get_contract_id(), contract.get_privacy_level(), cohort.privacy_level, cohort_id
+            ));
         }
 
         cohort.contracts.push(contract);
@@ -56,6 +74,7 @@ impl CohortManager {
             .ok_or_else(|| format!("Contract with ID {} not found in cohort {}", contract_id, cohort_id))?;
 
         cohort.contracts.remove(contract_index);
+        cohort.anonymity_policies.remove(contract_id);
         cohort.update_total_participants();
         Ok(())
     }
@@ -65,35 +84,239 @@ impl CohortManager {
 
         Ok(CohortSummary {
             cohort_id: cohort.cohort_id.clone(),
-            privacy_level: cohort.privacy_level.clone(),
-            total_participants: cohort.total_participants,
+            privacy_level: cohort.privacy_level,
+            total_participants: cohort.participants_on(today()).len(),
             contract_count: cohort.contracts.len(),
         })
     }
 
+    pub fn get_privacy_level(&self, cohort_id: &str) -> Result<DataPrivacyLevel, String> {
+        let cohort = self.cohorts.get(cohort_id).ok_or_else(|| format!("Cohort with ID {} not found", cohort_id))?;
+        Ok(cohort.privacy_level)
+    }
+
+    /*
+    The level a cohort's data is released at: the strictest of the cohort's level and the levels of its contracts, since
+    records pooled under a stricter contract may not be released more openly than that contract allows. Fails when two
+    of the contracts are at levels that are not comparable (e.g. SafeHarbor and ExpertDetermination).
+    */
+    pub fn get_release_level(&self, cohort_id: &str) -> Result<DataPrivacyLevel, String> {
+        let cohort = self.cohorts.get(cohort_id).ok_or_else(|| format!("Cohort with ID {} not found", cohort_id))?;
+        cohort.contracts.iter().try_fold(cohort.privacy_level, |level, contract| {
+            level.stricter_of(*contract.get_privacy_level()).ok_or_else(|| format!(
+                "Contract {} is at {:?}, which is not comparable with {:?}, so no single level covers all of cohort {}",
+                contract.get_contract_id(), contract.get_privacy_level(), level, cohort_id
+            ))
+        })
+    }
+
+    pub fn get_contract(&self, cohort_id: &str, contract_id: &str) -> Result<&HealthDataContract, String> {
+        let cohort = self.cohorts.get(cohort_id).ok_or_else(|| format!("Cohort with ID {} not found", cohort_id))?;
+        cohort.contracts.iter().find(|c| c.get_contract_id() == contract_id)
+            .ok_or_else(|| format!("Contract with ID {} not found in cohort {}", contract_id, cohort_id))
+    }
+
+    // Sets the anonymity policy that releases under one of the cohort's contracts are checked against.
+    pub fn set_anonymity_policy(&mut self, cohort_id: &str, contract_id: &str, policy: AnonymityPolicy) -> Result<(), String> {
+        let cohort = self.cohorts.get_mut(cohort_id).ok_or_else(|| format!("Cohort with ID {} not found", cohort_id))?;
+        if !cohort.contracts.iter().any(|c| c.get_contract_id() == contract_id) {
+            return Err(format!("Contract with ID {} not found in cohort {}", contract_id, cohort_id));
+        }
+        policy.check_release_targets().map_err(|e| e.to_string())?;
+        cohort.anonymity_policies.insert(contract_id.to_string(), policy);
+        Ok(())
+    }
+
+    pub fn get_anonymity_policy(&self, cohort_id: &str, contract_id: &str) -> Result<Option<&AnonymityPolicy>, String> {
+        let cohort = self.cohorts.get(cohort_id).ok_or_else(|| format!("Cohort with ID {} not found", cohort_id))?;
+        Ok(cohort.anonymity_policies.get(contract_id))
+    }
+
+    /*
+    Changes a contract in place, e.g. to suspend or reinstate it. The cohort holds the only copy of the contract,
+    so this is the way to change its status; the cohort's participants are recounted afterwards.
+    */
+    pub fn update_contract<R>(&mut self, cohort_id: &str, contract_id: &str, update: impl FnOnce(&mut HealthDataContract) -> R) -> Result<R, String> {
+        let cohort = self.cohorts.get_mut(cohort_id).ok_or_else(|| format!("Cohort with ID {} not found", cohort_id))?;
+        let contract = cohort.contracts.iter_mut().find(|c| c.get_contract_id() == contract_id)
+            .ok_or_else(|| format!("Contract with ID {} not found in cohort {}", contract_id, cohort_id))?;
+        let outcome = update(contract);
+        cohort.update_total_participants();
+        Ok(outcome)
+    }
+
     pub fn list_cohorts(&self) -> Vec<String> {
         self.cohorts.keys().cloned().collect()
     }
+
+    /*
+    Withdraws a DataOriginator from a contract, wherever that contract sits.
+    The originator stops counting towards the cohort's participants (unless another contract in the same cohort still covers them).
+    */
+    pub fn revoke_consent(&mut self, contract_id: &str, entity_id: &EntityId) -> Result<RevocationOutcome, String> {
+        let cohort = self.cohorts.values_mut()
+            .find(|cohort| cohort.contracts.iter().any(|c| c.get_contract_id() == contract_id))
+            .ok_or_else(|| format!("Contract with ID {} not found in any cohort", contract_id))?;
+
+        let contract = cohort.contracts.iter_mut().find(|c| c.get_contract_id() == contract_id)
+            .ok_or_else(|| format!("Contract with ID {} not found in cohort {}", contract_id, cohort.cohort_id))?;
+        let revocation = contract.revoke_consent(entity_id).map_err(|e| e.to_string())?;
+
+        cohort.update_total_participants();
+        Ok(RevocationOutcome {
+            revocation,
+            cohort_id: cohort.cohort_id.clone(),
+            total_participants: cohort.total_participants,
+        })
+    }
+
+    // The DataOriginators whose data may currently be used in the cohort.
+    pub fn active_participants(&self, cohort_id: &str) -> Result<HashSet<EntityId>, String> {
+        self.active_participants_on(cohort_id, today())
+    }
+
+    pub fn active_participants_on(&self, cohort_id: &str, date: Date) -> Result<HashSet<EntityId>, String> {
+        let cohort = self.cohorts.get(cohort_id).ok_or_else(|| format!("Cohort with ID {} not found", cohort_id))?;
+        Ok(cohort.participants_on(date).into_iter().cloned().collect())
+    }
+
+    /*
+    Moves every contract whose term has run out by the given date to Expired, and returns their IDs.
+    Expired contracts already stop counting as participants without this; the sweep only brings their status up to date.
+    */
+    pub fn expire_due_contracts(&mut self, date: Date) -> Vec<String> {
+        let mut expired = Vec::new();
+        for cohort in self.cohorts.values_mut() {
+            for contract in cohort.contracts.iter_mut() {
+                if let Ok(true) = contract.expire_if_due(date) {
+                    expired.push(contract.get_contract_id().to_string());
+                }
+            }
+            cohort.update_total_participants();
+        }
+        expired
+    }
+
+    // Revocations by originators who no longer participate in the cohort through any contract.
+    pub fn revoked_participants(&self, cohort_id: &str) -> Result<Vec<ConsentRevocation>, String> {
+        let cohort = self.cohorts.get(cohort_id).ok_or_else(|| format!("Cohort with ID {} not found", cohort_id))?;
+        let participants = cohort.participants();
+        Ok(cohort.contracts.iter()
+            .flat_map(|contract| contract.get_revocations().iter())
+            .filter(|revocation| !participants.contains(&revocation.entity_id))
+            .cloned()
+            .collect())
+    }
+}
+
+impl Default for CohortManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Cohort {
+    /*
+    Originators who have revoked consent under a contract are not counted for that contract,
+    and contracts that are not in force on the date (expired, suspended or not yet effective) contribute nobody.
+    */
+    fn participants_on(&self, date: Date) -> HashSet<&EntityId> {
+        self.contracts.iter()
+            .filter(|contract| contract.is_in_force(date))
+            .flat_map(|contract| contract.consenting_originators())
+            .collect()
+    }
+
+    fn participants(&self) -> HashSet<&EntityId> {
+        self.participants_on(today())
+    }
+
     fn update_total_participants(&mut self) {
-        self.total_participants = self.contracts.iter()
-            .flat_map(|contract| contract.get_parties().iter())
-            .filter(|party| matches!(party, Party::DataOriginator(_)))
-            .map(|party| match party {
-                Party::DataOriginator(info) => &info.entity_id,
-                _ => unreachable!(),
-            })
-            .collect::<HashSet<&EntityId>>()
-            .len();
+        self.total_participants = self.participants().len();
     }
 }
 
+fn today() -> Date {
+    OffsetDateTime::now_utc().date()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevocationOutcome {
+    pub revocation: ConsentRevocation,
+    pub cohort_id: String,
+    pub total_participants: usize,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CohortSummary {
     pub cohort_id: String,
     pub privacy_level: DataPrivacyLevel,
     pub total_participants: usize,
     pub contract_count: usize,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::*;
+    use crate::test_support::{execute, generator_storage};
+
+    fn executed_storage_contract(contract_id: &str, privacy_level: DataPrivacyLevel) -> HealthDataContract {
+        let (mut contract, keys) = generator_storage(contract_id, privacy_level);
+        execute(&mut contract, &keys);
+        contract
+    }
+
+    #[test]
+    fn stricter_contract_joins_a_looser_cohort() {
+        let mut manager = CohortManager::new();
+        manager.create_cohort("CH-LDS".to_string(), DataPrivacyLevel::LimitedDataSet).unwrap();
+
+        for (contract_id, level) in [("C-GS-001", DataPrivacyLevel::LimitedDataSet), ("C-GS-002", DataPrivacyLevel::SafeHarbor), ("C-GS-003", DataPrivacyLevel::AggregateOnly)] {
+            manager.add_contract_to_cohort("CH-LDS", executed_storage_contract(contract_id, level)).unwrap();
+        }
+        assert_eq!(manager.get_cohort_summary("CH-LDS").unwrap().contract_count, 3);
+    }
+
+    #[test]
+    fn looser_or_incomparable_contract_is_kept_out_of_a_stricter_cohort() {
+        let mut manager = CohortManager::new();
+        manager.create_cohort("CH-SH".to_string(), DataPrivacyLevel::SafeHarbor).unwrap();
+
+        assert!(manager.add_contract_to_cohort("CH-SH", executed_storage_contract("C-GS-001", DataPrivacyLevel::Identified)).is_err());
+        assert!(manager.add_contract_to_cohort("CH-SH", executed_storage_contract("C-GS-002", DataPrivacyLevel::ExpertDetermination)).is_err());
+        assert_eq!(manager.get_cohort_summary("CH-SH").unwrap().contract_count, 0);
+    }
+
+    #[test]
+    fn cohort_releases_at_its_strictest_contract_level() {
+        let mut manager = CohortManager::new();
+        manager.create_cohort("CH-LDS".to_string(), DataPrivacyLevel::LimitedDataSet).unwrap();
+        assert_eq!(manager.get_release_level("CH-LDS").unwrap(), DataPrivacyLevel::LimitedDataSet);
+
+        manager.add_contract_to_cohort("CH-LDS", executed_storage_contract("C-GS-001", DataPrivacyLevel::SafeHarbor)).unwrap();
+        assert_eq!(manager.get_release_level("CH-LDS").unwrap(), DataPrivacyLevel::SafeHarbor);
+
+        // No single level is both SafeHarbor and ExpertDetermination.
+        manager.add_contract_to_cohort("CH-LDS", executed_storage_contract("C-GS-002", DataPrivacyLevel::ExpertDetermination)).unwrap();
+        assert!(manager.get_release_level("CH-LDS").is_err());
+    }
+
+    #[test]
+    fn contracts_that_are_not_active_are_refused() {
+        let mut manager = CohortManager::new();
+        manager.create_cohort("CH-SH".to_string(), DataPrivacyLevel::SafeHarbor).unwrap();
+
+        let (draft, _) = generator_storage("C-GS-001", DataPrivacyLevel::SafeHarbor);
+        let (mut proposed, _) = generator_storage("C-GS-002", DataPrivacyLevel::SafeHarbor);
+        proposed.propose().unwrap();
+        let mut suspended = executed_storage_contract("C-GS-003", DataPrivacyLevel::SafeHarbor);
+        suspended.suspend().unwrap();
+
+        for (contract, status) in [(draft, ContractStatus::Draft), (proposed, ContractStatus::Proposed), (suspended, ContractStatus::Suspended)] {
+            let err = manager.add_contract_to_cohort("CH-SH", contract).unwrap_err();
+            assert!(err.contains(&format!("is {}; only Active contracts", status)), "{}", err);
+        }
+        assert_eq!(manager.get_cohort_summary("CH-SH").unwrap().contract_count, 0);
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::fmt;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use crate::contracts::structs_enums::DataPrivacyLevel;
+
+/*
+De-identification of tabular patient records under the HIPAA Safe Harbor method (45 CFR 164.514(b)(2)).
+
+The caller maps each column holding one of the 18 Safe Harbor identifiers to that identifier; columns left out of the
+mapping are taken to hold no identifiers and are kept as they are. Each mapped column is then:
+    - dates: reduced to the year; birth years that put the individual over 89 (as of reference_year) are
+      aggregated into a single "<year> or earlier" category,
+    - ages: kept, except ages over 89, which become "90+",
+    - ZIP codes: truncated to the first 3 digits, or "000" for the 3-digit areas with 20,000 people or fewer,
+    - every other identifier (names, addresses, phone and fax numbers, emails, SSNs, MRNs, account and other numbers,
+      URLs, IP addresses, biometrics, photos): dropped.
+Cells that cannot be read as the identifier they are mapped to are blanked rather than guessed at. Every
+transformation is listed, per column, in the DeidentificationReport.
+
+Datasets released at SafeHarbor (HBankInterface::release_dataset()) always go through deidentify().
+*/
+
+// 3-digit ZIP areas with 20,000 or fewer residents (HHS guidance, 2000 Census). They are reported as "000".
+pub const RESTRICTED_ZIP3: [&str; 17] = [
+    "036", "059", "063", "102", "203", "556", "692", "790", "821", "823", "830", "831", "878", "879", "884", "890", "893",
+];
+
+pub const AGE_90_OR_OLDER: &str = "90+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SafeHarborIdentifier {
+    Name,
+    // Street address, city, county or any other geographic subdivision smaller than a state, except the ZIP code.
+    Address,
+    ZipCode,
+    BirthDate,
+    // Any other date directly related to the individual: admission, discharge, death, ...
+    Date,
+    Age,
+    Phone,
+    Fax,
+    Email,
+    SocialSecurityNumber,
+    MedicalRecordNumber,
+    HealthPlanNumber,
+    AccountNumber,
+    LicenseNumber,
+    VehicleIdentifier,
+    DeviceIdentifier,
+    Url,
+    IpAddress,
+    BiometricIdentifier,
+    Photo,
+    OtherUniqueIdentifier,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SafeHarborAction {
+    Dropped,
+    ReducedToYear,
+    AgesOver89Capped,
+    TruncatedToZip3,
+}
+
+impl SafeHarborIdentifier {
+    pub fn action(&self) -> SafeHarborAction {
+        match self {
+            SafeHarborIdentifier::BirthDate | SafeHarborIdentifier::Date => SafeHarborAction::ReducedToYear,
+            SafeHarborIdentifier::Age => SafeHarborAction::AgesOver89Capped,
+            SafeHarborIdentifier::ZipCode => SafeHarborAction::TruncatedToZip3,
+            _ => SafeHarborAction::Dropped,
+        }
+    }
+}
+
+// Patient records as rows of cells under named columns.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(columns: Vec<String>, rows: Vec<Vec<String>>) -> Self {
+        Table { columns, rows }
+    }
+
+    pub fn column_index(&self, column: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c == column)
+    }
+}
+
+/*
+What was done to one mapped column. cells_changed counts the cells given a new value, cells_aggregated those of them
+put in the catch-all category ("90+", "000" or the earliest birth year), and cells_suppressed the cells blanked
+because they could not be read. A dropped column counts every one of its non-empty cells as suppressed.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColumnTransformation {
+    pub column: String,
+    pub identifier: SafeHarborIdentifier,
+    pub action: SafeHarborAction,
+    pub cells_changed: usize,
+    pub cells_aggregated: usize,
+    pub cells_suppressed: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeidentificationReport {
+    pub privacy_level: DataPrivacyLevel,
+    pub reference_year: i32,
+    pub rows: usize,
+    // Columns not mapped to an identifier, passed through unchanged.
+    pub columns_kept: Vec<String>,
+    pub transformations: Vec<ColumnTransformation>,
+}
+
+impl DeidentificationReport {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn columns_dropped(&self) -> impl Iterator<Item = &str> {
+        self.transformations.iter().filter(|t| t.action == SafeHarborAction::Dropped).map(|t| t.column.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub enum DeidentificationError {
+    UnknownColumn(String),
+    RaggedRow { row: usize, expected: usize, found: usize },
+}
+
+impl fmt::Display for DeidentificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeidentificationError::UnknownColumn(column) => write!(f, "Deidentification Error: mapped column {} is not in the table", column),
+            DeidentificationError::RaggedRow { row, expected, found } => {
+                write!(f, "Deidentification Error: row {} has {} cells but the table has {} columns", row, found, expected)
+            }
+        }
+    }
+}
+
+// What happens to one cell of a mapped column.
+enum CellOutcome {
+    Unchanged,
+    Changed(String),
+    Aggregated(String),
+    Suppressed,
+}
+
+#[derive(Debug, Clone)]
+pub struct SafeHarborDeidentifier {
+    mapping: HashMap<String, SafeHarborIdentifier>,
+    // The year ages are measured against when aggregating birth years, normally the year the data is released.
+    reference_year: i32,
+    restricted_zip3: Vec<String>,
+}
+
+impl SafeHarborDeidentifier {
+    pub fn new(mapping: HashMap<String, SafeHarborIdentifier>, reference_year: i32) -> Self {
+        SafeHarborDeidentifier {
+            mapping,
+            reference_year,
+            restricted_zip3: RESTRICTED_ZIP3.iter().map(|zip3| zip3.to_string()).collect(),
+        }
+    }
+
+    // Replaces the restricted 3-digit ZIP areas, e.g. with a list from more recent census data.
+    pub fn with_restricted_zip3(mut self, restricted_zip3: Vec<String>) -> Self {
+        self.restricted_zip3 = restricted_zip3;
+        self
+    }
+
+    pub fn deidentify(&self, table: &Table) -> Result<(Table, DeidentificationReport), DeidentificationError> {
+        let mut mapped: Vec<&String> = self.mapping.keys().collect();
+        mapped.sort();
+        if let Some(unknown) = mapped.iter().find(|column| table.column_index(column).is_none()) {
+            return Err(DeidentificationError::UnknownColumn(unknown.to_string()));
+        }
+        if let Some((row, cells)) = table.rows.iter().enumerate().find(|(_, cells)| cells.len() != table.columns.len()) {
+            return Err(DeidentificationError::RaggedRow { row, expected: table.columns.len(), found: cells.len() });
+        }
+
+        let date = Regex::new(r"^(\d{4})(-\d{2}-\d{2}.*)?$").expect("date pattern is valid");
+        let zip = Regex::new(r"^(\d{3})\d{2}(-?\d{4})?$").expect("ZIP pattern is valid");
+
+        let mut rows: Vec<Vec<String>> = vec![Vec::new(); table.rows.len()];
+        let mut columns = Vec::new();
+        let mut columns_kept = Vec::new();
+        let mut transformations = Vec::new();
+        for (index, column) in table.columns.iter().enumerate() {
+            let Some(identifier) = self.mapping.get(column).copied() else {
+                columns_kept.push(column.clone());
+                columns.push(column.clone());
+                for (row, cells) in rows.iter_mut().zip(&table.rows) {
+                    row.push(cells[index].clone());
+                }
+                continue;
+            };
+
+            let mut transformation = ColumnTransformation {
+                column: column.clone(),
+                identifier,
+                action: identifier.action(),
+                cells_changed: 0,
+                cells_aggregated: 0,
+                cells_suppressed: 0,
+            };
+            if transformation.action == SafeHarborAction::Dropped {
+                transformation.cells_suppressed = table.rows.iter().filter(|cells| !cells[index].trim().is_empty()).count();
+                transformations.push(transformation);
+                continue;
+            }
+
+            columns.push(column.clone());
+            for (row, cells) in rows.iter_mut().zip(&table.rows) {
+                let value = cells[index].trim();
+                let outcome = if value.is_empty() {
+                    CellOutcome::Unchanged
+                } else {
+                    match identifier {
+                        SafeHarborIdentifier::Age => self.cap_age(value),
+                        SafeHarborIdentifier::ZipCode => self.truncate_zip(&zip, value),
+                        SafeHarborIdentifier::BirthDate => self.reduce_to_year(&date, value, true),
+                        _ => self.reduce_to_year(&date, value, false),
+                    }
+                };
+                row.push(match outcome {
+                    CellOutcome::Unchanged => cells[index].clone(),
+                    CellOutcome::Changed(value) => {
+                        transformation.cells_changed += 1;
+                        value
+                    }
+                    CellOutcome::Aggregated(value) => {
+                        transformation.cells_changed += 1;
+                        transformation.cells_aggregated += 1;
+                        value
+                    }
+                    CellOutcome::Suppressed => {
+                        transformation.cells_suppressed += 1;
+                        String::new()
+                    }
+                });
+            }
+            transformations.push(transformation);
+        }
+
+        let report = DeidentificationReport {
+            privacy_level: DataPrivacyLevel::SafeHarbor,
+            reference_year: self.reference_year,
+            rows: table.rows.len(),
+            columns_kept,
+            transformations,
+        };
+        Ok((Table::new(columns, rows), report))
+    }
+
+    fn reduce_to_year(&self, date: &Regex, value: &str, is_birth_date: bool) -> CellOutcome {
+        let Some(year) = date.captures(value).and_then(|captures| captures[1].parse::<i32>().ok()) else {
+            return CellOutcome::Suppressed;
+        };
+        let earliest_birth_year = self.reference_year - 90;
+        if is_birth_date && year <= earliest_birth_year {
+            return CellOutcome::Aggregated(format!("{} or earlier", earliest_birth_year));
+        }
+        if value == year.to_string() {
+            CellOutcome::Unchanged
+        } else {
+            CellOutcome::Changed(year.to_string())
+        }
+    }
+
+    fn cap_age(&self, value: &str) -> CellOutcome {
+        match value.parse::<f64>() {
+            Ok(age) if age > 89.0 => CellOutcome::Aggregated(AGE_90_OR_OLDER.to_string()),
+            Ok(age) if age >= 0.0 => CellOutcome::Unchanged,
+            _ if value == AGE_90_OR_OLDER => CellOutcome::Unchanged,
+            _ => CellOutcome::Suppressed,
+        }
+    }
+
+    fn truncate_zip(&self, zip: &Regex, value: &str) -> CellOutcome {
+        let Some(zip3) = zip.captures(value).map(|captures| captures[1].to_string()) else {
+            return CellOutcome::Suppressed;
+        };
+        if self.restricted_zip3.contains(&zip3) {
+            CellOutcome::Aggregated("000".to_string())
+        } else {
+            CellOutcome::Changed(zip3)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(columns: &[&str], rows: &[&[&str]]) -> Table {
+        Table::new(
+            columns.iter().map(|column| column.to_string()).collect(),
+            rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect(),
+        )
+    }
+
+    fn deidentify(column: &str, identifier: SafeHarborIdentifier, cells: &[&str]) -> (Vec<String>, ColumnTransformation) {
+        let rows: Vec<&[&str]> = cells.iter().map(std::slice::from_ref).collect();
+        let deidentifier = SafeHarborDeidentifier::new(HashMap::from([(column.to_string(), identifier)]), 2026);
+        let (released, report) = deidentifier.deidentify(&table(&[column], &rows)).unwrap();
+        (released.rows.into_iter().map(|mut row| row.remove(0)).collect(), report.transformations[0].clone())
+    }
+
+    #[test]
+    fn dates_are_reduced_to_the_year() {
+        let (cells, transformation) = deidentify("admitted", SafeHarborIdentifier::Date, &["2024-03-05", "2019-12-31T08:00:00Z", "2021", "05/03/2024", ""]);
+        assert_eq!(cells, ["2024", "2019", "2021", "", ""]);
+        assert_eq!(transformation.action, SafeHarborAction::ReducedToYear);
+        assert_eq!((transformation.cells_changed, transformation.cells_aggregated, transformation.cells_suppressed), (2, 0, 1));
+    }
+
+    #[test]
+    fn birth_years_over_89_are_aggregated() {
+        // Measured against 2026, anyone born in 1936 or earlier is over 89.
+        let (cells, transformation) = deidentify("dob", SafeHarborIdentifier::BirthDate, &["1937-06-01", "1936-06-01", "1920-01-01"]);
+        assert_eq!(cells, ["1937", "1936 or earlier", "1936 or earlier"]);
+        assert_eq!((transformation.cells_changed, transformation.cells_aggregated), (3, 2));
+    }
+
+    #[test]
+    fn ages_over_89_are_capped() {
+        let (cells, transformation) = deidentify("age", SafeHarborIdentifier::Age, &["45", "89", "90", "104", "89.5", "90+", "unknown"]);
+        assert_eq!(cells, ["45", "89", "90+", "90+", "90+", "90+", ""]);
+        assert_eq!(transformation.action, SafeHarborAction::AgesOver89Capped);
+        assert_eq!((transformation.cells_changed, transformation.cells_aggregated, transformation.cells_suppressed), (3, 3, 1));
+    }
+
+    #[test]
+    fn zip_codes_keep_three_digits_unless_the_area_is_small() {
+        let (cells, transformation) = deidentify("zip", SafeHarborIdentifier::ZipCode, &["02139", "94110-1234", "03601", "89301", "1234"]);
+        // 036 and 893 are on the low-population list.
+        assert_eq!(cells, ["021", "941", "000", "000", ""]);
+        assert_eq!((transformation.cells_changed, transformation.cells_aggregated, transformation.cells_suppressed), (4, 2, 1));
+    }
+
+    #[test]
+    fn restricted_zip_areas_can_be_replaced() {
+        let deidentifier = SafeHarborDeidentifier::new(HashMap::from([("zip".to_string(), SafeHarborIdentifier::ZipCode)]), 2026)
+            .with_restricted_zip3(vec!["021".to_string()]);
+        let (released, _) = deidentifier.deidentify(&table(&["zip"], &[&["02139"], &["03601"]])).unwrap();
+        assert_eq!(released.rows, [["000"], ["036"]]);
+    }
+
+    #[test]
+    fn names_mrns_and_phones_are_dropped_and_reported() {
+        let records = table(
+            &["name", "mrn", "phone", "diagnosis"],
+            &[&["Jane Roe", "MRN-1", "555-0100", "asthma"], &["John Doe", "", "555-0101", "copd"]],
+        );
+        let mapping = HashMap::from([
+            ("name".to_string(), SafeHarborIdentifier::Name),
+            ("mrn".to_string(), SafeHarborIdentifier::MedicalRecordNumber),
+            ("phone".to_string(), SafeHarborIdentifier::Phone),
+        ]);
+        let (released, report) = SafeHarborDeidentifier::new(mapping, 2026).deidentify(&records).unwrap();
+
+        assert_eq!(released.columns, ["diagnosis"]);
+        assert_eq!(released.rows, [["asthma"], ["copd"]]);
+        assert_eq!(report.columns_kept, ["diagnosis"]);
+        assert_eq!(report.columns_dropped().collect::<Vec<_>>(), ["name", "mrn", "phone"]);
+        let suppressed: Vec<usize> = report.transformations.iter().map(|t| t.cells_suppressed).collect();
+        assert_eq!(suppressed, [2, 1, 2]);
+        assert_eq!((report.privacy_level, report.reference_year, report.rows), (DataPrivacyLevel::SafeHarbor, 2026, 2));
+    }
+
+    #[test]
+    fn mapping_a_missing_column_or_a_ragged_table_is_an_error() {
+        let deidentifier = SafeHarborDeidentifier::new(HashMap::from([("ssn".to_string(), SafeHarborIdentifier::SocialSecurityNumber)]), 2026);
+        assert!(matches!(deidentifier.deidentify(&table(&["age"], &[&["40"]])), Err(DeidentificationError::UnknownColumn(column)) if column == "ssn"));
+
+        let ragged = table(&["ssn", "age"], &[&["123-45-6789", "40"], &["987-65-4321"]]);
+        assert!(matches!(deidentifier.deidentify(&ragged), Err(DeidentificationError::RaggedRow { row: 1, expected: 2, found: 1 })));
+    }
+}
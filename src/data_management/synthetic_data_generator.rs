@@ -4,6 +4,7 @@ use crate::api::shared_models::SyntheticDataSetup;
 
 pub struct SyntheticDataGenerator {
     // Add fields here as needed, for example:
+    #[allow(dead_code)]
     base_data_path: PathBuf,
 }
 
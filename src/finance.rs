@@ -0,0 +1,26 @@
+/*
+Declare the submodules within the finance module.
+*/
+
+pub mod money;
+pub mod settlement;
+pub mod transaction_registry;
+pub mod residuals;
+pub mod ledger;
+pub mod statements;
+pub mod invoices;
+pub mod donations;
+pub mod subscriptions;
+pub mod royalties;
+
+
+pub use money::*;
+pub use settlement::*;
+pub use transaction_registry::*;
+pub use residuals::*;
+pub use ledger::*;
+pub use statements::*;
+pub use invoices::*;
+pub use donations::*;
+pub use subscriptions::*;
+pub use royalties::*;
@@ -0,0 +1,403 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::Date;
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::finance::settlement::payers_and_payees;
+use crate::finance::money::{Currency, Money};
+
+/*
+Donations received under PhilanthropicAgreements and how they are spent.
+
+Each donation becomes a fund carrying the agreement's earmark. Funds are drawn down to subsidize the access fees
+research recipients owe under the agreement types in SUBSIDIZED_AGREEMENTS. For a subsidy the eligible funds are
+used in this order, oldest donation first within each group:
+    1. funds earmarked for the research contract's cohort,
+    2. funds earmarked for the disease area named in the research contract's terms (compared case-insensitively),
+    3. funds for general operations.
+A subsidy covers as much of the fee as the eligible funds allow; the recipient pays the rest.
+
+Every donor can get a report of their funds and each drawdown made from them.
+*/
+pub const SUBSIDIZED_AGREEMENTS: [AgreementKind; 2] = [
+    AgreementKind::AccessAgreement,
+    AgreementKind::SubscriptionAgreement,
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DonationFund {
+    pub fund_id: String,
+    pub contract_id: String,
+    pub donor: EntityId,
+    pub earmark: Earmark,
+    pub received_on: Date,
+    pub amount: Money,
+    pub drawn: Money,
+}
+
+impl DonationFund {
+    pub fn remaining(&self) -> Money {
+        Money::new(self.amount.amount_minor - self.drawn.amount_minor, self.amount.currency.clone())
+    }
+
+    // How closely the earmark matches the research, or None if the fund cannot be used for it. Lower is a closer match.
+    fn match_rank(&self, cohort_id: Option<&str>, disease_area: Option<&str>) -> Option<u8> {
+        match &self.earmark {
+            Earmark::Cohort(earmarked) => (cohort_id == Some(earmarked.as_str())).then_some(0),
+            Earmark::DiseaseArea(earmarked) => disease_area.filter(|area| area.trim().eq_ignore_ascii_case(earmarked.trim())).map(|_| 1),
+            Earmark::GeneralOperations => Some(2),
+        }
+    }
+}
+
+// Part of a fund spent on one recipient's access fee.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Drawdown {
+    pub drawdown_id: String,
+    pub fund_id: String,
+    pub donor: EntityId,
+    pub earmark: Earmark,
+    pub contract_id: String,
+    pub cohort_id: Option<String>,
+    pub recipient: EntityId,
+    pub drawn_on: Date,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subsidy {
+    pub contract_id: String,
+    pub recipient: EntityId,
+    pub access_fee: Money,
+    pub covered: Money,
+    pub drawdowns: Vec<Drawdown>,
+}
+
+impl Subsidy {
+    // What the recipient still has to pay.
+    pub fn uncovered(&self) -> Money {
+        Money::new(self.access_fee.amount_minor - self.covered.amount_minor, self.access_fee.currency.clone())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FundReport {
+    pub fund: DonationFund,
+    pub drawdowns: Vec<Drawdown>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DonorReport {
+    pub donor: EntityId,
+    pub as_of: Date,
+    pub currency: Currency,
+    pub funds: Vec<FundReport>,
+    pub total_donated: Money,
+    pub total_drawn: Money,
+    pub total_remaining: Money,
+}
+
+impl DonorReport {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+#[derive(Debug)]
+pub enum DonationError {
+    NotPhilanthropic(AgreementKind),
+    NotSubsidized(AgreementKind),
+    NotInForce { contract_id: String, on: Date },
+    MissingParty(PartyType),
+    NonPositiveAmount(Money),
+}
+
+impl fmt::Display for DonationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DonationError::NotPhilanthropic(kind) => write!(f, "Donation Error: donations are received under a PhilanthropicAgreement, not a {}", kind),
+            DonationError::NotSubsidized(kind) => write!(f, "Donation Error: access fees under a {} cannot be subsidized", kind),
+            DonationError::NotInForce { contract_id, on } => write!(f, "Donation Error: contract {} is not in force on {}", contract_id, on),
+            DonationError::MissingParty(party_type) => write!(f, "Donation Error: the contract has no {:?} party", party_type),
+            DonationError::NonPositiveAmount(amount) => write!(f, "Donation Error: the amount must be positive (found {})", amount),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DonationFunds {
+    funds: Vec<DonationFund>,
+    drawdowns: Vec<Drawdown>,
+}
+
+impl DonationFunds {
+    pub fn new() -> Self {
+        DonationFunds {
+            funds: Vec::new(),
+            drawdowns: Vec::new(),
+        }
+    }
+
+    pub fn is_subsidized(kind: AgreementKind) -> bool {
+        SUBSIDIZED_AGREEMENTS.contains(&kind)
+    }
+
+    // Records a donation made under a PhilanthropicAgreement in force on the given date as a new fund.
+    pub fn receive(&mut self, contract: &HealthDataContract, amount: Money, on: Date) -> Result<&DonationFund, DonationError> {
+        let kind = contract.get_agreement_type().kind();
+        let earmark = contract.get_agreement_type().earmark().ok_or(DonationError::NotPhilanthropic(kind))?;
+        if !contract.is_in_force(on) {
+            return Err(DonationError::NotInForce { contract_id: contract.get_contract_id().to_string(), on });
+        }
+        if amount.is_negative() || amount.is_zero() {
+            return Err(DonationError::NonPositiveAmount(amount));
+        }
+        let donor = contract.get_parties().iter().find(|p| p.party_type() == PartyType::Donor)
+            .ok_or(DonationError::MissingParty(PartyType::Donor))?;
+
+        let fund = DonationFund {
+            fund_id: format!("DF-{:05}", self.funds.len() + 1),
+            contract_id: contract.get_contract_id().to_string(),
+            donor: donor.entity_id().clone(),
+            earmark: earmark.clone(),
+            received_on: on,
+            drawn: Money::zero(amount.currency.clone()),
+            amount,
+        };
+        self.funds.push(fund);
+        Ok(self.funds.last().expect("fund was just received"))
+    }
+
+    /*
+    Draws down eligible funds to pay the access fee owed by the recipient (the paying party) of a research contract.
+    The disease area is read from the contract's terms, so funds earmarked for a disease area only go to research the
+    contract itself says is in that area.
+    */
+    pub fn subsidize(&mut self, contract: &HealthDataContract, access_fee: Money, on: Date) -> Result<Subsidy, DonationError> {
+        let kind = contract.get_agreement_type().kind();
+        if !Self::is_subsidized(kind) {
+            return Err(DonationError::NotSubsidized(kind));
+        }
+        if !contract.is_in_force(on) {
+            return Err(DonationError::NotInForce { contract_id: contract.get_contract_id().to_string(), on });
+        }
+        if access_fee.is_negative() || access_fee.is_zero() {
+            return Err(DonationError::NonPositiveAmount(access_fee));
+        }
+        let (payers, _) = payers_and_payees(contract.get_agreement_type()).ok_or(DonationError::NotSubsidized(kind))?;
+        let recipient = payers.first().ok_or(DonationError::MissingParty(PartyType::DataRecipient))?.entity_id().clone();
+        let cohort_id = contract.get_cohort_id();
+        let disease_area = contract.get_terms().disease_area.as_deref();
+
+        let mut eligible: Vec<(u8, usize)> = self.funds.iter().enumerate()
+            .filter(|(_, fund)| fund.amount.currency == access_fee.currency && fund.received_on <= on && fund.remaining().amount_minor > 0)
+            .filter_map(|(index, fund)| fund.match_rank(cohort_id, disease_area).map(|rank| (rank, index)))
+            .collect();
+        eligible.sort_by_key(|(rank, index)| (*rank, self.funds[*index].received_on, *index));
+
+        let mut still_owed = access_fee.amount_minor;
+        let mut drawdowns = Vec::new();
+        for (_, index) in eligible {
+            if still_owed == 0 {
+                break;
+            }
+            let fund = &mut self.funds[index];
+            let drawn = fund.remaining().amount_minor.min(still_owed);
+            fund.drawn.amount_minor += drawn;
+            still_owed -= drawn;
+
+            let drawdown = Drawdown {
+                drawdown_id: format!("DD-{:06}", self.drawdowns.len() + 1),
+                fund_id: fund.fund_id.clone(),
+                donor: fund.donor.clone(),
+                earmark: fund.earmark.clone(),
+                contract_id: contract.get_contract_id().to_string(),
+                cohort_id: cohort_id.map(str::to_string),
+                recipient: recipient.clone(),
+                drawn_on: on,
+                amount: Money::new(drawn, access_fee.currency.clone()),
+            };
+            self.drawdowns.push(drawdown.clone());
+            drawdowns.push(drawdown);
+        }
+
+        Ok(Subsidy {
+            contract_id: contract.get_contract_id().to_string(),
+            recipient,
+            covered: Money::new(access_fee.amount_minor - still_owed, access_fee.currency.clone()),
+            access_fee,
+            drawdowns,
+        })
+    }
+
+    pub fn get(&self, fund_id: &str) -> Option<&DonationFund> {
+        self.funds.iter().find(|f| f.fund_id == fund_id)
+    }
+
+    pub fn funds(&self) -> &[DonationFund] {
+        &self.funds
+    }
+
+    pub fn drawdowns(&self) -> &[Drawdown] {
+        &self.drawdowns
+    }
+
+    // The undrawn balance of the funds carrying the earmark.
+    pub fn available(&self, earmark: &Earmark, currency: &Currency) -> Money {
+        let amount_minor = self.funds.iter()
+            .filter(|f| f.earmark == *earmark && f.amount.currency == *currency)
+            .map(|f| f.remaining().amount_minor)
+            .sum();
+        Money::new(amount_minor, currency.clone())
+    }
+
+    // The donor's funds received on or before as_of and every drawdown made from them up to that day.
+    pub fn donor_report(&self, donor: &EntityId, currency: &Currency, as_of: Date) -> DonorReport {
+        let funds: Vec<FundReport> = self.funds.iter()
+            .filter(|f| f.donor == *donor && f.amount.currency == *currency && f.received_on <= as_of)
+            .map(|f| {
+                let drawdowns: Vec<Drawdown> = self.drawdowns.iter()
+                    .filter(|d| d.fund_id == f.fund_id && d.drawn_on <= as_of)
+                    .cloned()
+                    .collect();
+                let drawn = drawdowns.iter().map(|d| d.amount.amount_minor).sum();
+                let fund = DonationFund { drawn: Money::new(drawn, currency.clone()), ..f.clone() };
+                FundReport { fund, drawdowns }
+            })
+            .collect();
+
+        let total = |amount: fn(&DonationFund) -> Money| Money::new(funds.iter().map(|r| amount(&r.fund).amount_minor).sum(), currency.clone());
+        DonorReport {
+            donor: donor.clone(),
+            as_of,
+            currency: currency.clone(),
+            total_donated: total(|f| f.amount.clone()),
+            total_drawn: total(|f| f.drawn.clone()),
+            total_remaining: total(DonationFund::remaining),
+            funds,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Duration, OffsetDateTime};
+
+    use super::*;
+    use crate::contracts::*;
+    use crate::test_support::{corporation, execute, party, register_keys, sale, signing_keys};
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    fn today() -> Date {
+        OffsetDateTime::now_utc().date()
+    }
+
+    // An executed PhilanthropicAgreement between donor C-D001 and HBank.
+    fn philanthropic_agreement(contract_id: &str, earmark: Earmark) -> HealthDataContract {
+        let donor = party(PartyType::Donor, "C-D001");
+        let hbank = party(PartyType::HBank, "C-HB001");
+        let keys = signing_keys(&[donor.clone(), hbank.clone()]);
+        let category = ContractCategory::TwoParty(TwoPartyLegalStructure::Donation(DonationLegalStructure::PhilanthropicAgreement {
+            donor: donor.clone(),
+            h_bank: hbank.clone(),
+            earmark,
+        }));
+        let builder = HealthDataContract::builder(contract_id, category)
+            .parties([donor.clone(), hbank.clone()])
+            .legal_framework(ContractLegalFramework::CommonLaw)
+            .privacy_level(DataPrivacyLevel::SafeHarbor)
+            .corporations([corporation(&donor), corporation(&hbank)]);
+        let mut contract = register_keys(builder, &keys).build().unwrap();
+        execute(&mut contract, &keys);
+        contract
+    }
+
+    // An executed AccessAgreement granting recipient I-R001 access to cohort CH-001, research in the given disease area.
+    fn access_agreement(disease_area: Option<&str>) -> HealthDataContract {
+        sale()
+            .kind(AgreementKind::AccessAgreement)
+            .contract_id("C-AA-001")
+            .terms(Terms { disease_area: disease_area.map(str::to_string), ..Terms::default() })
+            .cohort_id("CH-001")
+            .executed()
+    }
+
+    #[test]
+    fn the_closest_earmark_is_drawn_first() {
+        let mut funds = DonationFunds::new();
+        funds.receive(&philanthropic_agreement("C-PA-001", Earmark::GeneralOperations), usd(1_000), today()).unwrap();
+        funds.receive(&philanthropic_agreement("C-PA-002", Earmark::DiseaseArea("Oncology".to_string())), usd(300), today()).unwrap();
+        funds.receive(&philanthropic_agreement("C-PA-003", Earmark::Cohort("CH-001".to_string())), usd(200), today()).unwrap();
+        funds.receive(&philanthropic_agreement("C-PA-004", Earmark::Cohort("CH-002".to_string())), usd(5_000), today()).unwrap();
+
+        let subsidy = funds.subsidize(&access_agreement(Some(" oncology ")), usd(600), today()).unwrap();
+
+        let drawn: Vec<(&str, i64)> = subsidy.drawdowns.iter().map(|d| (d.fund_id.as_str(), d.amount.amount_minor)).collect();
+        assert_eq!(drawn, vec![("DF-00003", 200), ("DF-00002", 300), ("DF-00001", 100)]);
+        assert_eq!(subsidy.recipient, EntityId("I-R001".to_string()));
+        assert_eq!(subsidy.uncovered(), usd(0));
+        assert_eq!(funds.available(&Earmark::Cohort("CH-002".to_string()), &Currency::usd()), usd(5_000));
+    }
+
+    #[test]
+    fn disease_area_funds_need_the_research_to_name_that_area() {
+        let mut funds = DonationFunds::new();
+        funds.receive(&philanthropic_agreement("C-PA-001", Earmark::DiseaseArea("Oncology".to_string())), usd(300), today()).unwrap();
+
+        for disease_area in [None, Some("Cardiology")] {
+            let subsidy = funds.subsidize(&access_agreement(disease_area), usd(100), today()).unwrap();
+            assert!(subsidy.drawdowns.is_empty());
+            assert_eq!(subsidy.uncovered(), usd(100));
+        }
+    }
+
+    #[test]
+    fn funds_are_never_overdrawn() {
+        let mut funds = DonationFunds::new();
+        funds.receive(&philanthropic_agreement("C-PA-001", Earmark::GeneralOperations), usd(500), today()).unwrap();
+        let contract = access_agreement(None);
+
+        let subsidy = funds.subsidize(&contract, usd(800), today()).unwrap();
+        assert_eq!(subsidy.covered, usd(500));
+        assert_eq!(subsidy.uncovered(), usd(300));
+        assert_eq!(funds.get("DF-00001").unwrap().remaining(), usd(0));
+
+        let subsidy = funds.subsidize(&contract, usd(100), today()).unwrap();
+        assert!(subsidy.drawdowns.is_empty());
+        assert_eq!(funds.drawdowns().len(), 1);
+        assert!(matches!(funds.subsidize(&contract, usd(0), today()), Err(DonationError::NonPositiveAmount(_))));
+        assert!(matches!(funds.subsidize(&contract, usd(-100), today()), Err(DonationError::NonPositiveAmount(_))));
+    }
+
+    #[test]
+    fn donations_and_subsidies_need_the_right_agreement() {
+        let mut funds = DonationFunds::new();
+        let access = access_agreement(None);
+        assert!(matches!(funds.receive(&access, usd(100), today()), Err(DonationError::NotPhilanthropic(AgreementKind::AccessAgreement))));
+
+        let donation = philanthropic_agreement("C-PA-001", Earmark::GeneralOperations);
+        assert!(matches!(funds.receive(&donation, usd(0), today()), Err(DonationError::NonPositiveAmount(_))));
+        assert!(matches!(funds.subsidize(&donation, usd(100), today()), Err(DonationError::NotSubsidized(AgreementKind::PhilanthropicAgreement))));
+        assert!(funds.funds().is_empty());
+    }
+
+    #[test]
+    fn donor_reports_include_only_drawdowns_up_to_the_report_date() {
+        let mut funds = DonationFunds::new();
+        let donor = EntityId("C-D001".to_string());
+        let tomorrow = today() + Duration::days(1);
+        funds.receive(&philanthropic_agreement("C-PA-001", Earmark::GeneralOperations), usd(500), today()).unwrap();
+        funds.subsidize(&access_agreement(None), usd(200), tomorrow).unwrap();
+
+        let before = funds.donor_report(&donor, &Currency::usd(), today());
+        assert_eq!((before.total_donated.clone(), before.total_drawn.clone()), (usd(500), usd(0)));
+        let after = funds.donor_report(&donor, &Currency::usd(), tomorrow);
+        assert_eq!(after.total_drawn, usd(200));
+        assert_eq!(after.total_remaining, usd(300));
+        assert_eq!(after.funds[0].drawdowns.len(), 1);
+    }
+}
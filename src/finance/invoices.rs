@@ -0,0 +1,235 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::{Date, Duration};
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::finance::settlement::{Settlement, ShareKind};
+use crate::finance::money::Money;
+
+/*
+Invoices for the amounts recipients owe under Purchase, Licensing and Subscription agreements.
+
+An invoice is issued to the paying party (agent_b) for a settlement of the contract. Its lines show how the amount
+is shared out (HBank's fee, the generators' share, consultant fees and the proceeds to agent_a), and its total is
+the settlement's gross amount. Payment is due payment_terms_days after the invoice is issued.
+*/
+pub const INVOICED_AGREEMENTS: [AgreementKind; 3] = [
+    AgreementKind::PurchaseAgreement,
+    AgreementKind::LicensingAgreement,
+    AgreementKind::SubscriptionAgreement,
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InvoiceStatus {
+    Open,
+    Paid { paid_on: Date },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InvoiceLine {
+    pub description: String,
+    pub payee: EntityId,
+    pub kind: ShareKind,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invoice {
+    pub invoice_id: String,
+    pub contract_id: String,
+    pub revision: u32,
+    pub agreement_kind: AgreementKind,
+    pub bill_to: EntityId,
+    pub issued_on: Date,
+    pub settled_on: Date,
+    pub due_on: Date,
+    pub lines: Vec<InvoiceLine>,
+    pub total: Money,
+    pub status: InvoiceStatus,
+}
+
+impl Invoice {
+    pub fn is_open(&self) -> bool {
+        self.status == InvoiceStatus::Open
+    }
+
+    pub fn is_overdue(&self, on: Date) -> bool {
+        self.is_open() && self.due_on < on
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+#[derive(Debug)]
+pub enum InvoiceError {
+    NotInvoiced(AgreementKind),
+    SettlementMismatch { contract_id: String, settlement_contract_id: String },
+    NoPayer(String),
+    UnknownInvoice(String),
+    AlreadyPaid { invoice_id: String, paid_on: Date },
+    DueDateOutOfRange { issued_on: Date, payment_terms_days: u32 },
+}
+
+impl fmt::Display for InvoiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvoiceError::NotInvoiced(kind) => write!(f, "Invoice Error: invoices are not issued under a {}", kind),
+            InvoiceError::SettlementMismatch { contract_id, settlement_contract_id } => {
+                write!(f, "Invoice Error: the settlement belongs to contract {}, not {}", settlement_contract_id, contract_id)
+            }
+            InvoiceError::NoPayer(contract_id) => write!(f, "Invoice Error: the settlement of contract {} has no paying party", contract_id),
+            InvoiceError::UnknownInvoice(invoice_id) => write!(f, "Invoice Error: invoice {} not found", invoice_id),
+            InvoiceError::AlreadyPaid { invoice_id, paid_on } => write!(f, "Invoice Error: invoice {} was already paid on {}", invoice_id, paid_on),
+            InvoiceError::DueDateOutOfRange { issued_on, payment_terms_days } => {
+                write!(f, "Invoice Error: payment {} days after {} falls beyond the last representable date", payment_terms_days, issued_on)
+            }
+        }
+    }
+}
+
+fn line_description(kind: ShareKind, settlement: &Settlement) -> String {
+    let share = match kind {
+        ShareKind::HBankFee => "HBank fee",
+        ShareKind::GeneratorShare => "Data generator share",
+        ShareKind::ConsultantFee => "Consultant fee",
+        ShareKind::OriginatorShare => "Data originator share",
+        ShareKind::Proceeds => "Proceeds",
+    };
+    format!("{} for {} under contract {}", share, settlement.bundle, settlement.contract_id)
+}
+
+#[derive(Debug, Default)]
+pub struct Invoices {
+    invoices: Vec<Invoice>,
+}
+
+impl Invoices {
+    pub fn new() -> Self {
+        Invoices {
+            invoices: Vec::new(),
+        }
+    }
+
+    pub fn is_invoiced(kind: AgreementKind) -> bool {
+        INVOICED_AGREEMENTS.contains(&kind)
+    }
+
+    // Issues the invoice for a settlement of the contract to its paying party.
+    pub fn issue(&mut self, contract: &HealthDataContract, settlement: &Settlement, issued_on: Date, payment_terms_days: u32) -> Result<&Invoice, InvoiceError> {
+        let agreement_kind = contract.get_agreement_type().kind();
+        if !Self::is_invoiced(agreement_kind) {
+            return Err(InvoiceError::NotInvoiced(agreement_kind));
+        }
+        if settlement.contract_id != contract.get_contract_id() {
+            return Err(InvoiceError::SettlementMismatch {
+                contract_id: contract.get_contract_id().to_string(),
+                settlement_contract_id: settlement.contract_id.clone(),
+            });
+        }
+        let bill_to = settlement.paid_by.first().ok_or_else(|| InvoiceError::NoPayer(settlement.contract_id.clone()))?;
+        let due_on = issued_on.checked_add(Duration::days(payment_terms_days as i64))
+            .ok_or(InvoiceError::DueDateOutOfRange { issued_on, payment_terms_days })?;
+
+        let invoice = Invoice {
+            invoice_id: format!("INV-{:06}", self.invoices.len() + 1),
+            contract_id: settlement.contract_id.clone(),
+            revision: settlement.revision,
+            agreement_kind,
+            bill_to: bill_to.clone(),
+            issued_on,
+            settled_on: settlement.settled_on,
+            due_on,
+            lines: settlement.lines.iter().map(|line| InvoiceLine {
+                description: line_description(line.kind, settlement),
+                payee: line.entity_id.clone(),
+                kind: line.kind,
+                amount: line.amount.clone(),
+            }).collect(),
+            total: settlement.gross.clone(),
+            status: InvoiceStatus::Open,
+        };
+        self.invoices.push(invoice);
+        Ok(self.invoices.last().expect("invoice was just issued"))
+    }
+
+    pub fn mark_paid(&mut self, invoice_id: &str, paid_on: Date) -> Result<&Invoice, InvoiceError> {
+        let invoice = self.invoices.iter_mut().find(|i| i.invoice_id == invoice_id)
+            .ok_or_else(|| InvoiceError::UnknownInvoice(invoice_id.to_string()))?;
+        if let InvoiceStatus::Paid { paid_on } = invoice.status {
+            return Err(InvoiceError::AlreadyPaid { invoice_id: invoice_id.to_string(), paid_on });
+        }
+        invoice.status = InvoiceStatus::Paid { paid_on };
+        Ok(invoice)
+    }
+
+    pub fn get(&self, invoice_id: &str) -> Option<&Invoice> {
+        self.invoices.iter().find(|i| i.invoice_id == invoice_id)
+    }
+
+    pub fn all(&self) -> &[Invoice] {
+        &self.invoices
+    }
+
+    pub fn for_contract<'a>(&'a self, contract_id: &'a str) -> impl Iterator<Item = &'a Invoice> {
+        self.invoices.iter().filter(move |i| i.contract_id == contract_id)
+    }
+
+    pub fn billed_to<'a>(&'a self, entity_id: &'a EntityId) -> impl Iterator<Item = &'a Invoice> {
+        self.invoices.iter().filter(move |i| i.bill_to == *entity_id)
+    }
+
+    // Open invoices already past due on the given date.
+    pub fn overdue(&self, on: Date) -> Vec<&Invoice> {
+        self.invoices.iter().filter(|i| i.is_overdue(on)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Month, OffsetDateTime};
+
+    use super::*;
+    use crate::finance::money::{Currency, Rate};
+    use crate::finance::settlement::SettlementEngine;
+    use crate::test_support::sale;
+
+    fn executed_purchase() -> HealthDataContract {
+        sale()
+            .kind(AgreementKind::PurchaseAgreement)
+            .contract_id("C-PA-001")
+            .generator_rate(GeneratorRateSpecification::KnowledgeRate(Rate::percent(5).unwrap()))
+            .executed()
+    }
+
+    #[test]
+    fn invoice_is_billed_to_the_buyer_for_the_gross_amount() {
+        let contract = executed_purchase();
+        let today = OffsetDateTime::now_utc().date();
+        let settlement = SettlementEngine::new().settle(&contract, Money::new(100_000, Currency::usd()), today).unwrap();
+        let mut invoices = Invoices::new();
+
+        let invoice = invoices.issue(&contract, &settlement, today, 30).unwrap();
+        assert_eq!(invoice.bill_to, EntityId("I-R001".to_string()));
+        assert_eq!(invoice.total, settlement.gross);
+        assert_eq!(invoice.due_on, today + Duration::days(30));
+        let invoice_id = invoice.invoice_id.clone();
+        invoices.mark_paid(&invoice_id, today).unwrap();
+        assert!(matches!(invoices.mark_paid(&invoice_id, today), Err(InvoiceError::AlreadyPaid { .. })));
+    }
+
+    #[test]
+    fn due_date_past_the_last_representable_date_is_an_error() {
+        let contract = executed_purchase();
+        let today = OffsetDateTime::now_utc().date();
+        let settlement = SettlementEngine::new().settle(&contract, Money::new(100_000, Currency::usd()), today).unwrap();
+        let late = Date::from_calendar_date(9999, Month::December, 1).unwrap();
+
+        let err = Invoices::new().issue(&contract, &settlement, late, 60).unwrap_err();
+        assert!(matches!(err, InvoiceError::DueDateOutOfRange { payment_terms_days: 60, .. }));
+        let err = Invoices::new().issue(&contract, &settlement, today, u32::MAX).unwrap_err();
+        assert!(matches!(err, InvoiceError::DueDateOutOfRange { .. }));
+    }
+}
@@ -0,0 +1,590 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::Date;
+
+use crate::contracts::structs_enums::EntityId;
+use crate::finance::settlement::{Settlement, ShareKind};
+use crate::finance::money::{Currency, Money};
+use crate::finance::residuals::{ObligationStatus, ResidualError, ResidualObligations};
+use crate::finance::donations::{DonationFund, Drawdown};
+use crate::finance::royalties::RevenueReport;
+
+/*
+HBank's internal double-entry ledger.
+
+Every EntityId has an account holding what HBank owes that entity (a credit balance) or what the entity owes HBank
+(a debit balance). Next to those sit HBank's own fee account, the escrow account for money HBank actually holds,
+and the donation account for funds given under philanthropic agreements.
+
+Money moves only through JournalEntries in a single currency whose debits equal their credits. Entries are never changed or removed once
+posted; a mistake or refund is corrected by posting a reversing entry. Balances are derived from the entries posted
+on or before a date, so the ledger can be read as of any day.
+
+    deposit          payer sends money         Dr Escrow             Cr payer
+    settlement       gross split of a payment  Dr payer              Cr each share (HBank fee to HBankFees)
+    residual payout  an obligation is paid     Dr payer              Cr beneficiary
+    payout           money leaves HBank        Dr entity             Cr Escrow
+    donation         a donor gives funds       Dr Escrow             Cr Donations
+    subsidy          a fund pays an access fee Dr Donations          Cr recipient
+    royalty          royalties on a report     Dr licensee           Cr each payee
+    refund           reverses an earlier entry (debits and credits swapped)
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AccountId {
+    Entity(EntityId),
+    HBankFees,
+    Escrow,
+    Donations,
+}
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountId::Entity(entity_id) => write!(f, "entity:{}", entity_id.0),
+            AccountId::HBankFees => write!(f, "hbank:fees"),
+            AccountId::Escrow => write!(f, "hbank:escrow"),
+            AccountId::Donations => write!(f, "hbank:donations"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EntrySide {
+    Debit,
+    Credit,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Posting {
+    pub account: AccountId,
+    pub side: EntrySide,
+    pub amount: Money,
+}
+
+impl Posting {
+    pub fn debit(account: AccountId, amount: Money) -> Self {
+        Posting { account, side: EntrySide::Debit, amount }
+    }
+
+    pub fn credit(account: AccountId, amount: Money) -> Self {
+        Posting { account, side: EntrySide::Credit, amount }
+    }
+
+    // Credits count positive, debits negative.
+    pub fn signed_minor(&self) -> i64 {
+        match self.side {
+            EntrySide::Credit => self.amount.amount_minor,
+            EntrySide::Debit => -self.amount.amount_minor,
+        }
+    }
+
+    fn reversed(&self) -> Self {
+        let side = match self.side {
+            EntrySide::Debit => EntrySide::Credit,
+            EntrySide::Credit => EntrySide::Debit,
+        };
+        Posting { account: self.account.clone(), side, amount: self.amount.clone() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EntryKind {
+    Deposit,
+    Settlement,
+    ResidualPayout,
+    Payout,
+    Donation,
+    Subsidy,
+    Royalty,
+    Refund,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub entry_id: String,
+    pub kind: EntryKind,
+    pub posted_on: Date,
+    pub memo: String,
+    // The contract, obligation or entry the posting relates to, if any.
+    pub reference: Option<String>,
+    pub postings: Vec<Posting>,
+}
+
+impl JournalEntry {
+    // Every posting of an entry is in this currency.
+    pub fn currency(&self) -> &Currency {
+        &self.postings[0].amount.currency
+    }
+
+    fn total(&self, side: EntrySide) -> Money {
+        let amount_minor = self.postings.iter().filter(|p| p.side == side).map(|p| p.amount.amount_minor).sum();
+        Money::new(amount_minor, self.currency().clone())
+    }
+
+    pub fn debits(&self) -> Money {
+        self.total(EntrySide::Debit)
+    }
+
+    pub fn credits(&self) -> Money {
+        self.total(EntrySide::Credit)
+    }
+
+    pub fn touches(&self, account: &AccountId) -> bool {
+        self.postings.iter().any(|p| p.account == *account)
+    }
+}
+
+#[derive(Debug)]
+pub enum LedgerError {
+    EmptyEntry,
+    NegativeAmount(Money),
+    MixedCurrencies(Vec<Currency>),
+    Unbalanced { debits: Money, credits: Money },
+    UnknownEntry(String),
+    AlreadyRefunded(String),
+    Residual(String),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::EmptyEntry => write!(f, "Ledger Error: an entry needs at least one non-zero posting"),
+            LedgerError::NegativeAmount(amount) => write!(f, "Ledger Error: posting amounts must be positive (found {})", amount),
+            LedgerError::MixedCurrencies(currencies) => {
+                let codes: Vec<&str> = currencies.iter().map(|c| c.code()).collect();
+                write!(f, "Ledger Error: an entry must be in a single currency (found {})", codes.join(", "))
+            }
+            LedgerError::Unbalanced { debits, credits } => write!(f, "Ledger Error: debits of {} do not equal credits of {}", debits, credits),
+            LedgerError::UnknownEntry(entry_id) => write!(f, "Ledger Error: journal entry {} not found", entry_id),
+            LedgerError::AlreadyRefunded(entry_id) => write!(f, "Ledger Error: journal entry {} has already been refunded", entry_id),
+            LedgerError::Residual(message) => write!(f, "Ledger Error: {}", message),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Ledger {
+    entries: Vec<JournalEntry>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Ledger {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    pub fn get_entry(&self, entry_id: &str) -> Option<&JournalEntry> {
+        self.entries.iter().find(|e| e.entry_id == entry_id)
+    }
+
+    // Posts a balanced, single-currency entry. Zero-amount postings are dropped.
+    pub fn post(
+        &mut self,
+        kind: EntryKind,
+        posted_on: Date,
+        memo: impl Into<String>,
+        reference: Option<String>,
+        postings: Vec<Posting>,
+    ) -> Result<&JournalEntry, LedgerError> {
+        let postings: Vec<Posting> = postings.into_iter().filter(|p| !p.amount.is_zero()).collect();
+        if postings.is_empty() {
+            return Err(LedgerError::EmptyEntry);
+        }
+        if let Some(negative) = postings.iter().find(|p| p.amount.is_negative()) {
+            return Err(LedgerError::NegativeAmount(negative.amount.clone()));
+        }
+        let mut currencies: Vec<Currency> = Vec::new();
+        for posting in &postings {
+            if !currencies.contains(&posting.amount.currency) {
+                currencies.push(posting.amount.currency.clone());
+            }
+        }
+        if currencies.len() > 1 {
+            return Err(LedgerError::MixedCurrencies(currencies));
+        }
+        let entry = JournalEntry {
+            entry_id: format!("JE-{:06}", self.entries.len() + 1),
+            kind,
+            posted_on,
+            memo: memo.into(),
+            reference,
+            postings,
+        };
+        let (debits, credits) = (entry.debits(), entry.credits());
+        if debits != credits {
+            return Err(LedgerError::Unbalanced { debits, credits });
+        }
+        self.entries.push(entry);
+        Ok(self.entries.last().expect("entry was just pushed"))
+    }
+
+    pub fn post_deposit(&mut self, payer: &EntityId, amount: Money, on: Date) -> Result<&JournalEntry, LedgerError> {
+        self.post(
+            EntryKind::Deposit,
+            on,
+            format!("Deposit from {}", payer.0),
+            None,
+            vec![Posting::debit(AccountId::Escrow, amount.clone()), Posting::credit(AccountId::Entity(payer.clone()), amount)],
+        )
+    }
+
+    // The payers are charged the gross amount (shared equally if there are several) and every settlement line is credited.
+    pub fn post_settlement(&mut self, settlement: &Settlement) -> Result<&JournalEntry, LedgerError> {
+        let payer_shares = settlement.gross.split_evenly(settlement.paid_by.len());
+        let mut postings: Vec<Posting> = settlement.paid_by.iter().zip(payer_shares)
+            .map(|(payer, amount)| Posting::debit(AccountId::Entity(payer.clone()), amount))
+            .collect();
+        postings.extend(settlement.lines.iter().map(|line| {
+            let account = match line.kind {
+                ShareKind::HBankFee => AccountId::HBankFees,
+                _ => AccountId::Entity(line.entity_id.clone()),
+            };
+            Posting::credit(account, line.amount.clone())
+        }));
+        self.post(
+            EntryKind::Settlement,
+            settlement.settled_on,
+            format!("Settlement of {} under contract {}", settlement.gross, settlement.contract_id),
+            Some(settlement.contract_id.clone()),
+            postings,
+        )
+    }
+
+    // Marks a residual obligation paid and moves its amount from the paying party to the beneficiary.
+    pub fn post_residual_payout(
+        &mut self,
+        obligations: &mut ResidualObligations,
+        obligation_id: &str,
+        paid_by: &EntityId,
+        on: Date,
+    ) -> Result<&JournalEntry, LedgerError> {
+        let obligation = obligations.get(obligation_id)
+            .ok_or_else(|| LedgerError::Residual(ResidualError::UnknownObligation(obligation_id.to_string()).to_string()))?
+            .clone();
+        if let ObligationStatus::Paid { paid_on } = obligation.status {
+            return Err(LedgerError::Residual(ResidualError::AlreadyPaid { obligation_id: obligation_id.to_string(), paid_on }.to_string()));
+        }
+        let posted = self.post(
+            EntryKind::ResidualPayout,
+            on,
+            format!("Residual due {} under contract {} to {}", obligation.due_on, obligation.contract_id, obligation.beneficiary.0),
+            Some(obligation.obligation_id.clone()),
+            vec![
+                Posting::debit(AccountId::Entity(paid_by.clone()), obligation.amount.clone()),
+                Posting::credit(AccountId::Entity(obligation.beneficiary.clone()), obligation.amount.clone()),
+            ],
+        )?;
+        obligations.mark_paid(obligation_id, on).map_err(|e| LedgerError::Residual(e.to_string()))?;
+        Ok(posted)
+    }
+
+    pub fn post_payout(&mut self, entity_id: &EntityId, amount: Money, on: Date) -> Result<&JournalEntry, LedgerError> {
+        self.post(
+            EntryKind::Payout,
+            on,
+            format!("Payout to {}", entity_id.0),
+            None,
+            vec![Posting::debit(AccountId::Entity(entity_id.clone()), amount.clone()), Posting::credit(AccountId::Escrow, amount)],
+        )
+    }
+
+    pub fn post_donation(&mut self, donor: &EntityId, amount: Money, on: Date, reference: Option<String>) -> Result<&JournalEntry, LedgerError> {
+        self.post(
+            EntryKind::Donation,
+            on,
+            format!("Donation from {}", donor.0),
+            reference,
+            vec![Posting::debit(AccountId::Escrow, amount.clone()), Posting::credit(AccountId::Donations, amount)],
+        )
+    }
+
+    pub fn post_fund_donation(&mut self, fund: &DonationFund) -> Result<&JournalEntry, LedgerError> {
+        self.post_donation(&fund.donor, fund.amount.clone(), fund.received_on, Some(fund.fund_id.clone()))
+    }
+
+    // Credits the recipient with the part of their access fee paid from a donation fund.
+    pub fn post_drawdown(&mut self, drawdown: &Drawdown) -> Result<&JournalEntry, LedgerError> {
+        self.post(
+            EntryKind::Subsidy,
+            drawdown.drawn_on,
+            format!("Subsidy from fund {} ({}) towards contract {}", drawdown.fund_id, drawdown.earmark, drawdown.contract_id),
+            Some(drawdown.drawdown_id.clone()),
+            vec![Posting::debit(AccountId::Donations, drawdown.amount.clone()), Posting::credit(AccountId::Entity(drawdown.recipient.clone()), drawdown.amount.clone())],
+        )
+    }
+
+    // Charges the licensee the royalties computed on a revenue report, as of the day it was filed.
+    pub fn post_royalty(&mut self, report: &RevenueReport) -> Result<&JournalEntry, LedgerError> {
+        let mut postings = vec![Posting::debit(AccountId::Entity(report.licensee.clone()), report.total_royalties())];
+        postings.extend(report.royalties.iter().map(|line| Posting::credit(AccountId::Entity(line.payee.clone()), line.amount.clone())));
+        self.post(
+            EntryKind::Royalty,
+            report.filed_on,
+            format!("Royalties on {} revenue of work {} ({} to {})", report.revenue, report.work_id, report.period_start, report.period_end),
+            Some(report.report_id.clone()),
+            postings,
+        )
+    }
+
+    // Reverses an earlier entry in full. Each entry can be refunded once, and refunds themselves cannot be refunded.
+    pub fn post_refund(&mut self, entry_id: &str, on: Date) -> Result<&JournalEntry, LedgerError> {
+        let original = self.get_entry(entry_id).ok_or_else(|| LedgerError::UnknownEntry(entry_id.to_string()))?;
+        let already_refunded = self.entries.iter()
+            .any(|e| e.kind == EntryKind::Refund && e.reference.as_deref() == Some(entry_id));
+        if already_refunded || original.kind == EntryKind::Refund {
+            return Err(LedgerError::AlreadyRefunded(entry_id.to_string()));
+        }
+        let postings = original.postings.iter().map(Posting::reversed).collect();
+        let memo = format!("Refund of {}", original.memo);
+        self.post(EntryKind::Refund, on, memo, Some(entry_id.to_string()), postings)
+    }
+
+    fn postings_as_of<'a>(&'a self, currency: &'a Currency, as_of: Date) -> impl Iterator<Item = &'a Posting> {
+        self.entries.iter()
+            .filter(move |e| e.posted_on <= as_of && e.currency() == currency)
+            .flat_map(|e| e.postings.iter())
+    }
+
+    // Credits minus debits in the currency, posted on or before as_of. For an entity, a positive balance is owed to it.
+    pub fn balance(&self, account: &AccountId, currency: &Currency, as_of: Date) -> Money {
+        let amount_minor = self.postings_as_of(currency, as_of).filter(|p| p.account == *account).map(Posting::signed_minor).sum();
+        Money::new(amount_minor, currency.clone())
+    }
+
+    pub fn entity_balance(&self, entity_id: &EntityId, currency: &Currency, as_of: Date) -> Money {
+        self.balance(&AccountId::Entity(entity_id.clone()), currency, as_of)
+    }
+
+    // Every account's balance in the currency. The balances always add up to zero.
+    pub fn balances(&self, currency: &Currency, as_of: Date) -> HashMap<AccountId, Money> {
+        let mut balances: HashMap<AccountId, Money> = HashMap::new();
+        for posting in self.postings_as_of(currency, as_of) {
+            balances.entry(posting.account.clone()).or_insert_with(|| Money::zero(currency.clone())).amount_minor += posting.signed_minor();
+        }
+        balances
+    }
+
+    // The entries touching an account, posted between from and to (inclusive).
+    pub fn entries_for<'a>(&'a self, account: &'a AccountId, from: Date, to: Date) -> impl Iterator<Item = &'a JournalEntry> {
+        self.entries.iter().filter(move |e| e.posted_on >= from && e.posted_on <= to && e.touches(account))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Month;
+
+    use super::*;
+    use crate::contracts::structs_enums::{Earmark, PartyType};
+    use crate::finance::money::Rate;
+    use crate::finance::royalties::{RoyaltyLine, RoyaltyShare};
+    use crate::finance::settlement::SettlementLine;
+    use crate::finance::transaction_registry::DataBundleId;
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    fn day(day: u8) -> Date {
+        Date::from_calendar_date(2030, Month::January, day).unwrap()
+    }
+
+    fn entity(id: &str) -> EntityId {
+        EntityId(id.to_string())
+    }
+
+    fn account(id: &str) -> AccountId {
+        AccountId::Entity(entity(id))
+    }
+
+    fn assert_nets_to_zero(ledger: &Ledger, as_of: Date) {
+        let total: i64 = ledger.balances(&Currency::usd(), as_of).values().map(|m| m.amount_minor).sum();
+        assert_eq!(total, 0);
+        for entry in ledger.entries() {
+            assert_eq!(entry.debits(), entry.credits(), "{} is unbalanced", entry.entry_id);
+        }
+    }
+
+    #[test]
+    fn unbalanced_entries_are_rejected() {
+        let mut ledger = Ledger::new();
+        let posted = ledger.post(
+            EntryKind::Deposit,
+            day(1),
+            "short by 10",
+            None,
+            vec![Posting::debit(AccountId::Escrow, usd(100)), Posting::credit(account("I-R001"), usd(90))],
+        );
+
+        assert!(matches!(posted, Err(LedgerError::Unbalanced { ref debits, ref credits }) if *debits == usd(100) && *credits == usd(90)));
+        assert!(ledger.entries().is_empty());
+    }
+
+    #[test]
+    fn empty_entries_are_rejected() {
+        let mut ledger = Ledger::new();
+
+        assert!(matches!(ledger.post(EntryKind::Deposit, day(1), "nothing", None, vec![]), Err(LedgerError::EmptyEntry)));
+        let zeros = vec![Posting::debit(AccountId::Escrow, usd(0)), Posting::credit(account("I-R001"), usd(0))];
+        assert!(matches!(ledger.post(EntryKind::Deposit, day(1), "zeros", None, zeros), Err(LedgerError::EmptyEntry)));
+        assert!(ledger.entries().is_empty());
+    }
+
+    #[test]
+    fn negative_and_mixed_currency_entries_are_rejected() {
+        let mut ledger = Ledger::new();
+        let negative = vec![Posting::debit(AccountId::Escrow, usd(-5)), Posting::credit(account("I-R001"), usd(-5))];
+        assert!(matches!(ledger.post(EntryKind::Deposit, day(1), "negative", None, negative), Err(LedgerError::NegativeAmount(_))));
+
+        let eur = Money::new(100, Currency::new("EUR").unwrap());
+        let mixed = vec![Posting::debit(AccountId::Escrow, usd(100)), Posting::credit(account("I-R001"), eur)];
+        assert!(matches!(ledger.post(EntryKind::Deposit, day(1), "mixed", None, mixed), Err(LedgerError::MixedCurrencies(_))));
+        assert!(ledger.entries().is_empty());
+    }
+
+    #[test]
+    fn balances_are_read_per_account_as_of_a_date() {
+        let mut ledger = Ledger::new();
+        let recipient = entity("I-R001");
+        let custodian = entity("I-C001");
+        ledger.post_deposit(&recipient, usd(1_000), day(1)).unwrap();
+        ledger.post(
+            EntryKind::Settlement,
+            day(2),
+            "sale",
+            None,
+            vec![
+                Posting::debit(account("I-R001"), usd(1_000)),
+                Posting::credit(account("I-C001"), usd(900)),
+                Posting::credit(AccountId::HBankFees, usd(100)),
+            ],
+        ).unwrap();
+        ledger.post_payout(&custodian, usd(900), day(3)).unwrap();
+
+        assert_eq!(ledger.entity_balance(&recipient, &Currency::usd(), day(1)), usd(1_000));
+        assert_eq!(ledger.entity_balance(&recipient, &Currency::usd(), day(2)), usd(0));
+        assert_eq!(ledger.entity_balance(&custodian, &Currency::usd(), day(2)), usd(900));
+        assert_eq!(ledger.entity_balance(&custodian, &Currency::usd(), day(3)), usd(0));
+        assert_eq!(ledger.balance(&AccountId::Escrow, &Currency::usd(), day(2)), usd(-1_000));
+        assert_eq!(ledger.balance(&AccountId::Escrow, &Currency::usd(), day(3)), usd(-100));
+
+        let as_of_day_2 = ledger.balances(&Currency::usd(), day(2));
+        assert_eq!(as_of_day_2[&AccountId::HBankFees], usd(100));
+        assert_eq!(as_of_day_2.len(), 4);
+        assert!(ledger.balances(&Currency::usd(), Date::from_calendar_date(2029, Month::December, 31).unwrap()).is_empty());
+        assert_eq!(ledger.entries_for(&account("I-C001"), day(1), day(2)).count(), 1);
+        for as_of in [day(1), day(2), day(3)] {
+            assert_nets_to_zero(&ledger, as_of);
+        }
+    }
+
+    #[test]
+    fn settlement_postings_net_to_zero() {
+        let line = |id: &str, kind: ShareKind, amount_minor: i64| SettlementLine {
+            entity_id: entity(id),
+            party_type: PartyType::DataCustodian,
+            kind,
+            rate: None,
+            amount: usd(amount_minor),
+        };
+        let settlement = Settlement {
+            contract_id: "C-DS-001".to_string(),
+            revision: 1,
+            settled_on: day(5),
+            gross: usd(1_001),
+            paid_by: vec![entity("I-R001"), entity("I-R002")],
+            bundle: DataBundleId::Bundle("C-DS-001".to_string()),
+            prior_transactions: 0,
+            generator_rate: None,
+            lines: vec![
+                line("C-HB001", ShareKind::HBankFee, 50),
+                line("C-G001", ShareKind::GeneratorShare, 30),
+                line("I-C001", ShareKind::Proceeds, 921),
+            ],
+        };
+        let mut ledger = Ledger::new();
+        let entry = ledger.post_settlement(&settlement).unwrap();
+
+        assert_eq!(entry.debits(), usd(1_001));
+        assert_eq!(entry.reference.as_deref(), Some("C-DS-001"));
+        let balances = ledger.balances(&Currency::usd(), day(5));
+        assert_eq!(balances[&AccountId::HBankFees], usd(50));
+        assert!(!balances.contains_key(&account("C-HB001")));
+        assert_eq!(balances[&account("I-R001")].amount_minor + balances[&account("I-R002")].amount_minor, -1_001);
+        assert_nets_to_zero(&ledger, day(5));
+    }
+
+    #[test]
+    fn donation_and_drawdown_postings_net_to_zero() {
+        let mut ledger = Ledger::new();
+        let donor = entity("C-D001");
+        ledger.post_donation(&donor, usd(500), day(1), Some("F-001".to_string())).unwrap();
+        let drawdown = Drawdown {
+            drawdown_id: "DD-001".to_string(),
+            fund_id: "F-001".to_string(),
+            donor,
+            earmark: Earmark::GeneralOperations,
+            contract_id: "C-AA-001".to_string(),
+            cohort_id: None,
+            recipient: entity("I-R001"),
+            drawn_on: day(2),
+            amount: usd(200),
+        };
+        ledger.post_drawdown(&drawdown).unwrap();
+
+        let balances = ledger.balances(&Currency::usd(), day(2));
+        assert_eq!(balances[&AccountId::Donations], usd(300));
+        assert_eq!(balances[&account("I-R001")], usd(200));
+        assert_eq!(balances[&AccountId::Escrow], usd(-500));
+        assert_nets_to_zero(&ledger, day(2));
+    }
+
+    #[test]
+    fn royalty_postings_net_to_zero() {
+        let royalty = |payee: &str, share: RoyaltyShare, amount_minor: i64| RoyaltyLine {
+            payee: entity(payee),
+            share,
+            rate: Rate::percent(5).unwrap(),
+            amount: usd(amount_minor),
+        };
+        let report = RevenueReport {
+            report_id: "RR-001".to_string(),
+            work_id: "W-001".to_string(),
+            contract_id: "C-LA-001".to_string(),
+            licensee: entity("I-R001"),
+            period_start: day(1),
+            period_end: day(31),
+            revenue: usd(10_000),
+            filed_on: day(31),
+            due_on: day(31),
+            royalties: vec![royalty("I-C001", RoyaltyShare::Licensor, 500), royalty("C-G001", RoyaltyShare::Generator, 200)],
+        };
+        let mut ledger = Ledger::new();
+        ledger.post_royalty(&report).unwrap();
+
+        let balances = ledger.balances(&Currency::usd(), day(31));
+        assert_eq!(balances[&account("I-R001")], usd(-700));
+        assert_eq!(balances[&account("I-C001")], usd(500));
+        assert_eq!(balances[&account("C-G001")], usd(200));
+        assert_nets_to_zero(&ledger, day(31));
+    }
+
+    #[test]
+    fn an_entry_is_refunded_once() {
+        let mut ledger = Ledger::new();
+        let entry_id = ledger.post_deposit(&entity("I-R001"), usd(100), day(1)).unwrap().entry_id.clone();
+        let refund_id = ledger.post_refund(&entry_id, day(2)).unwrap().entry_id.clone();
+
+        assert!(ledger.balances(&Currency::usd(), day(2)).values().all(Money::is_zero));
+        assert!(matches!(ledger.post_refund(&entry_id, day(3)), Err(LedgerError::AlreadyRefunded(_))));
+        assert!(matches!(ledger.post_refund(&refund_id, day(3)), Err(LedgerError::AlreadyRefunded(_))));
+        assert!(matches!(ledger.post_refund("JE-999999", day(3)), Err(LedgerError::UnknownEntry(_))));
+    }
+}
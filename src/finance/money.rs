@@ -0,0 +1,323 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+
+/*
+Exact money and rate arithmetic.
+
+Money is a whole number of minor units (cents for USD) together with its ISO 4217 currency, so amounts never pick up
+floating-point error and amounts in different currencies cannot be mixed by accident.
+
+A Rate is a percentage between 0% and 100%, held as parts per million of the whole (1_000_000 = 100%), i.e. exact to
+four decimal places of a percent. Rates are written as decimal percentages ("2.5" is 2.5%) in JSON and are checked
+against the 0-100% range whenever they are created or deserialized. Applying a rate rounds half away from zero
+to the nearest minor unit.
+*/
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoneyError {
+    InvalidCurrency(String),
+    InvalidRate(String),
+    RateOutOfRange(String),
+    CurrencyMismatch { expected: Currency, found: Currency },
+    Overflow,
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::InvalidCurrency(code) => write!(f, "Money Error: {} is not an ISO 4217 currency code", code),
+            MoneyError::InvalidRate(value) => write!(f, "Money Error: {} is not a valid percentage (up to 4 decimal places)", value),
+            MoneyError::RateOutOfRange(value) => write!(f, "Money Error: rate {}% is outside 0-100%", value),
+            MoneyError::CurrencyMismatch { expected, found } => write!(f, "Money Error: expected an amount in {} but found {}", expected, found),
+            MoneyError::Overflow => write!(f, "Money Error: amount overflow"),
+        }
+    }
+}
+
+// An ISO 4217 alphabetic currency code, e.g. "USD".
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "String", into = "String")]
+#[schemars(with = "String")]
+pub struct Currency(String);
+
+impl Currency {
+    pub fn new(code: &str) -> Result<Self, MoneyError> {
+        if code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase()) {
+            Ok(Currency(code.to_string()))
+        } else {
+            Err(MoneyError::InvalidCurrency(code.to_string()))
+        }
+    }
+
+    pub fn usd() -> Self {
+        Currency("USD".to_string())
+    }
+
+    pub fn code(&self) -> &str {
+        &self.0
+    }
+
+    // Digits after the decimal point of the currency's minor unit.
+    pub fn minor_unit_digits(&self) -> u32 {
+        match self.0.as_str() {
+            "JPY" | "KRW" | "CLP" | "ISK" | "VND" => 0,
+            "BHD" | "KWD" | "OMR" | "JOD" | "TND" => 3,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = MoneyError;
+
+    fn try_from(code: String) -> Result<Self, Self::Error> {
+        Currency::new(&code)
+    }
+}
+
+impl From<Currency> for String {
+    fn from(currency: Currency) -> Self {
+        currency.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct Money {
+    pub amount_minor: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount_minor: i64, currency: Currency) -> Self {
+        Money { amount_minor, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Money { amount_minor: 0, currency }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.amount_minor == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.amount_minor < 0
+    }
+
+    fn check_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch { expected: self.currency.clone(), found: other.currency.clone() });
+        }
+        Ok(())
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.check_currency(other)?;
+        let amount_minor = self.amount_minor.checked_add(other.amount_minor).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount_minor, self.currency.clone()))
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.check_currency(other)?;
+        let amount_minor = self.amount_minor.checked_sub(other.amount_minor).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount_minor, self.currency.clone()))
+    }
+
+    pub fn checked_neg(&self) -> Result<Money, MoneyError> {
+        let amount_minor = self.amount_minor.checked_neg().ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(amount_minor, self.currency.clone()))
+    }
+
+    // Sums amounts that must all be in the given currency.
+    pub fn sum<'a>(currency: &Currency, amounts: impl IntoIterator<Item = &'a Money>) -> Result<Money, MoneyError> {
+        amounts.into_iter().try_fold(Money::zero(currency.clone()), |total, amount| total.checked_add(amount))
+    }
+
+    /*
+    Divides the amount into count parts that differ by at most one minor unit, larger parts first,
+    so that the parts always add back up to the amount.
+    */
+    pub fn split_evenly(&self, count: usize) -> Vec<Money> {
+        if count == 0 {
+            return Vec::new();
+        }
+        let count = count as i64;
+        let (base, remainder) = (self.amount_minor.div_euclid(count), self.amount_minor.rem_euclid(count));
+        (0..count).map(|i| Money::new(base + i64::from(i < remainder), self.currency.clone())).collect()
+    }
+
+    // The amount as a decimal number in major units without the currency code, e.g. "-12.34".
+    pub fn decimal_amount(&self) -> String {
+        let digits = self.currency.minor_unit_digits();
+        let sign = if self.amount_minor < 0 { "-" } else { "" };
+        let magnitude = self.amount_minor.unsigned_abs();
+        if digits == 0 {
+            return format!("{}{}", sign, magnitude);
+        }
+        let scale = 10u64.pow(digits);
+        format!("{}{}.{:0width$}", sign, magnitude / scale, magnitude % scale, width = digits as usize)
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.currency, self.decimal_amount())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "String", into = "String")]
+#[schemars(with = "String")]
+pub struct Rate(u32);
+
+impl Rate {
+    pub const PPM_PER_WHOLE: u32 = 1_000_000;
+    pub const ZERO: Rate = Rate(0);
+    pub const FULL: Rate = Rate(Self::PPM_PER_WHOLE);
+
+    pub fn from_ppm(ppm: u32) -> Result<Self, MoneyError> {
+        if ppm > Self::PPM_PER_WHOLE {
+            return Err(MoneyError::RateOutOfRange(Rate(ppm).to_string()));
+        }
+        Ok(Rate(ppm))
+    }
+
+    // A whole-number percentage, e.g. Rate::percent(5) is 5%.
+    pub fn percent(percent: u32) -> Result<Self, MoneyError> {
+        Self::from_ppm(percent.saturating_mul(10_000))
+    }
+
+    pub fn from_basis_points(basis_points: u32) -> Result<Self, MoneyError> {
+        Self::from_ppm(basis_points.saturating_mul(100))
+    }
+
+    pub fn ppm(&self) -> u32 {
+        self.0
+    }
+
+    // The share of an amount, rounded half away from zero to the nearest minor unit.
+    pub fn of(&self, amount: &Money) -> Money {
+        let product = amount.amount_minor as i128 * self.0 as i128;
+        let whole = Self::PPM_PER_WHOLE as i128;
+        let rounded = (product.abs() + whole / 2) / whole * product.signum();
+        Money::new(rounded as i64, amount.currency.clone())
+    }
+
+    // The sum of several rates, which unlike a single Rate may exceed 100%.
+    pub fn total_ppm(rates: impl IntoIterator<Item = Rate>) -> u64 {
+        rates.into_iter().map(|rate| rate.0 as u64).sum()
+    }
+
+    pub fn format_ppm(ppm: u64) -> String {
+        let whole = ppm / 10_000;
+        let fraction = ppm % 10_000;
+        if fraction == 0 {
+            return whole.to_string();
+        }
+        format!("{}.{:04}", whole, fraction).trim_end_matches('0').to_string()
+    }
+}
+
+// Written as a decimal percentage without the % sign, e.g. "2.5".
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Self::format_ppm(self.0 as u64))
+    }
+}
+
+impl FromStr for Rate {
+    type Err = MoneyError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || MoneyError::InvalidRate(value.to_string());
+        let trimmed = value.trim().trim_end_matches('%');
+        let (whole, fraction) = trimmed.split_once('.').unwrap_or((trimmed, ""));
+        if whole.is_empty() || fraction.len() > 4 || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let whole: u64 = whole.parse().map_err(|_| invalid())?;
+        let fraction: u64 = if fraction.is_empty() { 0 } else { format!("{:0<4}", fraction).parse().map_err(|_| invalid())? };
+        let ppm = whole.checked_mul(10_000).and_then(|w| w.checked_add(fraction)).ok_or_else(invalid)?;
+        if ppm > Self::PPM_PER_WHOLE as u64 {
+            return Err(MoneyError::RateOutOfRange(trimmed.to_string()));
+        }
+        Ok(Rate(ppm as u32))
+    }
+}
+
+impl TryFrom<String> for Rate {
+    type Error = MoneyError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Rate> for String {
+    fn from(rate: Rate) -> Self {
+        rate.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    #[test]
+    fn negating_the_smallest_amount_overflows() {
+        assert_eq!(usd(250).checked_neg(), Ok(usd(-250)));
+        assert_eq!(usd(i64::MIN + 1).checked_neg(), Ok(usd(i64::MAX)));
+        assert_eq!(usd(i64::MIN).checked_neg(), Err(MoneyError::Overflow));
+    }
+
+    #[test]
+    fn split_evenly_adds_up_to_the_amount() {
+        for amount_minor in [0, 1, 100, 1_001, -100, 999_999] {
+            for count in 1..=7 {
+                let parts = usd(amount_minor).split_evenly(count);
+                assert_eq!(parts.len(), count);
+                assert_eq!(Money::sum(&Currency::usd(), &parts).unwrap(), usd(amount_minor));
+                let largest = parts.iter().map(|p| p.amount_minor).max().unwrap();
+                let smallest = parts.iter().map(|p| p.amount_minor).min().unwrap();
+                assert!(largest - smallest <= 1);
+            }
+        }
+        assert_eq!(usd(100).split_evenly(3), vec![usd(34), usd(33), usd(33)]);
+    }
+
+    #[test]
+    fn rate_of_rounds_to_the_nearest_minor_unit() {
+        assert_eq!(Rate::percent(10).unwrap().of(&usd(1_005)), usd(101));
+        assert_eq!(Rate::from_basis_points(250).unwrap().of(&usd(10_000)), usd(250));
+        assert_eq!(Rate::ZERO.of(&usd(12_345)), usd(0));
+        assert!(Rate::percent(101).is_err());
+    }
+
+    #[test]
+    fn rates_parse_from_percentages_with_up_to_four_decimals() {
+        assert_eq!("2.5".parse::<Rate>().unwrap(), Rate::from_basis_points(250).unwrap());
+        assert_eq!("7.25%".parse::<Rate>().unwrap(), Rate::from_basis_points(725).unwrap());
+        assert!("1.23456".parse::<Rate>().is_err());
+        assert!(matches!("100.5".parse::<Rate>(), Err(MoneyError::RateOutOfRange(_))));
+    }
+
+    #[test]
+    fn arithmetic_checks_currency_and_overflow() {
+        let eur = Money::new(100, Currency::new("EUR").unwrap());
+        assert!(matches!(usd(100).checked_add(&eur), Err(MoneyError::CurrencyMismatch { .. })));
+        assert_eq!(usd(i64::MAX).checked_add(&usd(1)), Err(MoneyError::Overflow));
+        assert_eq!(usd(100).checked_sub(&usd(250)).unwrap(), usd(-150));
+    }
+}
@@ -0,0 +1,354 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::Date;
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::duration::TermLength;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::finance::money::{Currency, Money};
+
+/*
+Turns a contract's residual schedule into dated payment obligations, one per beneficiary per disbursement,
+so upcoming liabilities can be listed and each obligation marked paid once it has been disbursed.
+
+Dated schedules (OneOff, Monthly, Quarterly, Annually) are expanded up to a horizon date with schedule_through();
+expanding again later only adds the disbursements that are new. RevenueTriggered schedules create obligations
+when revenue is reported with record_revenue(). Each disbursement is shared equally among the beneficiaries.
+*/
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ObligationTrigger {
+    Scheduled,
+    Revenue { revenue: Money },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ObligationStatus {
+    Outstanding,
+    Paid { paid_on: Date },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PaymentObligation {
+    pub obligation_id: String,
+    pub contract_id: String,
+    pub beneficiary: EntityId,
+    pub due_on: Date,
+    pub amount: Money,
+    pub trigger: ObligationTrigger,
+    pub status: ObligationStatus,
+}
+
+impl PaymentObligation {
+    pub fn is_outstanding(&self) -> bool {
+        self.status == ObligationStatus::Outstanding
+    }
+}
+
+#[derive(Debug)]
+pub enum ResidualError {
+    NoResiduals(String),
+    NotRevenueTriggered(String),
+    RevenueTriggered(String),
+    OutsideSchedule { contract_id: String, on: Date },
+    UnknownObligation(String),
+    AlreadyPaid { obligation_id: String, paid_on: Date },
+}
+
+impl fmt::Display for ResidualError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResidualError::NoResiduals(contract_id) => write!(f, "Residual Error: contract {} has no residual payments", contract_id),
+            ResidualError::NotRevenueTriggered(contract_id) => write!(f, "Residual Error: residuals under contract {} are not revenue-triggered", contract_id),
+            ResidualError::RevenueTriggered(contract_id) => write!(f, "Residual Error: residuals under contract {} are only paid when revenue is reported", contract_id),
+            ResidualError::OutsideSchedule { contract_id, on } => write!(f, "Residual Error: {} is outside the residual schedule of contract {}", on, contract_id),
+            ResidualError::UnknownObligation(obligation_id) => write!(f, "Residual Error: payment obligation {} not found", obligation_id),
+            ResidualError::AlreadyPaid { obligation_id, paid_on } => write!(f, "Residual Error: payment obligation {} was already paid on {}", obligation_id, paid_on),
+        }
+    }
+}
+
+// The dates of a dated schedule's disbursements up to and including through.
+pub fn disbursement_dates(schedule: &DisbursementSchedule, through: Date) -> Vec<Date> {
+    let last = schedule.end_date.map_or(through, |end_date| end_date.min(through));
+    if schedule.start_date > last {
+        return Vec::new();
+    }
+    match schedule.frequency.months() {
+        None if schedule.frequency == DisbursementFrequency::OneOff => vec![schedule.start_date],
+        None => Vec::new(),
+        Some(months) => (0..)
+            .map(|n: u32| TermLength::Months(n * months).after(schedule.start_date))
+            .take_while(|date| *date <= last)
+            .collect(),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ResidualObligations {
+    obligations: Vec<PaymentObligation>,
+}
+
+impl ResidualObligations {
+    pub fn new() -> Self {
+        ResidualObligations {
+            obligations: Vec::new(),
+        }
+    }
+
+    fn residuals_of(contract: &HealthDataContract) -> Result<&Residuals, ResidualError> {
+        contract.get_residual_payments().ok_or_else(|| ResidualError::NoResiduals(contract.get_contract_id().to_string()))
+    }
+
+    // One obligation per beneficiary, in entity_id order, sharing the amount equally.
+    fn push_disbursement(&mut self, contract_id: &str, residuals: &Residuals, due_on: Date, amount: Money, trigger: ObligationTrigger) -> Vec<PaymentObligation> {
+        let mut beneficiaries: Vec<&EntityId> = residuals.Beneficiaries.iter().map(|b| b.entity_id()).collect();
+        beneficiaries.sort_by(|a, b| a.0.cmp(&b.0));
+        let shares = amount.split_evenly(beneficiaries.len());
+
+        let mut created = Vec::new();
+        for (beneficiary, amount) in beneficiaries.into_iter().zip(shares) {
+            let obligation = PaymentObligation {
+                obligation_id: format!("{}-R{:05}", contract_id, self.obligations.len() + 1),
+                contract_id: contract_id.to_string(),
+                beneficiary: beneficiary.clone(),
+                due_on,
+                amount,
+                trigger: trigger.clone(),
+                status: ObligationStatus::Outstanding,
+            };
+            self.obligations.push(obligation.clone());
+            created.push(obligation);
+        }
+        created
+    }
+
+    // Creates the obligations for every dated disbursement up to and including through that has not been created yet.
+    pub fn schedule_through(&mut self, contract: &HealthDataContract, through: Date) -> Result<Vec<PaymentObligation>, ResidualError> {
+        let residuals = Self::residuals_of(contract)?;
+        let contract_id = contract.get_contract_id();
+        let ResidualAmount::FixedPerDisbursement(amount) = &residuals.Amount else {
+            return Err(ResidualError::RevenueTriggered(contract_id.to_string()));
+        };
+        if residuals.DisbursementSchedule.frequency == DisbursementFrequency::RevenueTriggered {
+            return Err(ResidualError::RevenueTriggered(contract_id.to_string()));
+        }
+
+        let mut created = Vec::new();
+        for due_on in disbursement_dates(&residuals.DisbursementSchedule, through) {
+            let already_scheduled = self.obligations.iter().any(|o| {
+                o.contract_id == contract_id && o.due_on == due_on && o.trigger == ObligationTrigger::Scheduled
+            });
+            if !already_scheduled {
+                created.extend(self.push_disbursement(contract_id, residuals, due_on, amount.clone(), ObligationTrigger::Scheduled));
+            }
+        }
+        Ok(created)
+    }
+
+    // Creates the obligations owed on revenue reported under a RevenueTriggered schedule.
+    pub fn record_revenue(&mut self, contract: &HealthDataContract, revenue: Money, on: Date) -> Result<Vec<PaymentObligation>, ResidualError> {
+        let residuals = Self::residuals_of(contract)?;
+        let contract_id = contract.get_contract_id();
+        let schedule = &residuals.DisbursementSchedule;
+        let (DisbursementFrequency::RevenueTriggered, ResidualAmount::PercentOfRevenue(rate)) = (schedule.frequency, &residuals.Amount) else {
+            return Err(ResidualError::NotRevenueTriggered(contract_id.to_string()));
+        };
+        if on < schedule.start_date || schedule.end_date.is_some_and(|end_date| on > end_date) {
+            return Err(ResidualError::OutsideSchedule { contract_id: contract_id.to_string(), on });
+        }
+        let amount = rate.of(&revenue);
+        Ok(self.push_disbursement(contract_id, residuals, on, amount, ObligationTrigger::Revenue { revenue }))
+    }
+
+    pub fn mark_paid(&mut self, obligation_id: &str, paid_on: Date) -> Result<&PaymentObligation, ResidualError> {
+        let obligation = self.obligations.iter_mut().find(|o| o.obligation_id == obligation_id)
+            .ok_or_else(|| ResidualError::UnknownObligation(obligation_id.to_string()))?;
+        if let ObligationStatus::Paid { paid_on } = obligation.status {
+            return Err(ResidualError::AlreadyPaid { obligation_id: obligation_id.to_string(), paid_on });
+        }
+        obligation.status = ObligationStatus::Paid { paid_on };
+        Ok(obligation)
+    }
+
+    pub fn get(&self, obligation_id: &str) -> Option<&PaymentObligation> {
+        self.obligations.iter().find(|o| o.obligation_id == obligation_id)
+    }
+
+    pub fn all(&self) -> &[PaymentObligation] {
+        &self.obligations
+    }
+
+    pub fn for_contract<'a>(&'a self, contract_id: &'a str) -> impl Iterator<Item = &'a PaymentObligation> {
+        self.obligations.iter().filter(move |o| o.contract_id == contract_id)
+    }
+
+    pub fn for_beneficiary<'a>(&'a self, beneficiary: &'a EntityId) -> impl Iterator<Item = &'a PaymentObligation> {
+        self.obligations.iter().filter(move |o| o.beneficiary == *beneficiary)
+    }
+
+    // Outstanding obligations falling due between from and to (inclusive), earliest first.
+    pub fn upcoming(&self, from: Date, to: Date) -> Vec<&PaymentObligation> {
+        let mut upcoming: Vec<&PaymentObligation> = self.obligations.iter()
+            .filter(|o| o.is_outstanding() && o.due_on >= from && o.due_on <= to)
+            .collect();
+        upcoming.sort_by_key(|o| o.due_on);
+        upcoming
+    }
+
+    // Outstanding obligations already past due on the given date.
+    pub fn overdue(&self, on: Date) -> Vec<&PaymentObligation> {
+        self.obligations.iter().filter(|o| o.is_outstanding() && o.due_on < on).collect()
+    }
+
+    // The total of outstanding obligations in the given currency.
+    pub fn outstanding(&self, currency: &Currency) -> Money {
+        let amount_minor = self.obligations.iter()
+            .filter(|o| o.is_outstanding() && o.amount.currency == *currency)
+            .map(|o| o.amount.amount_minor)
+            .sum();
+        Money::new(amount_minor, currency.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Month;
+
+    use super::*;
+    use crate::contracts::*;
+    use crate::finance::money::Rate;
+    use crate::test_support::sale;
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    fn date(year: i32, month: Month, day: u8) -> Date {
+        Date::from_calendar_date(year, month, day).unwrap()
+    }
+
+    fn schedule(frequency: DisbursementFrequency, start_date: Date, end_date: Option<Date>) -> DisbursementSchedule {
+        DisbursementSchedule { frequency, start_date, end_date }
+    }
+
+    // A DirectSale from custodian I-C001 to recipient I-R001 paying residuals to the custodian and generator C-G001.
+    fn sale_with_residuals(schedule: DisbursementSchedule, amount: ResidualAmount) -> HealthDataContract {
+        let sale = sale();
+        let beneficiaries = vec![sale.generators[0].clone(), sale.agent_a.clone()];
+        sale.residual_payments(Residuals {
+            Beneficiaries: beneficiaries,
+            DisbursementSchedule: schedule,
+            Amount: amount,
+        })
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn disbursement_dates_follow_the_frequency_up_to_the_end_date() {
+        let start = date(2030, Month::January, 15);
+        let monthly = schedule(DisbursementFrequency::Monthly, start, Some(date(2030, Month::March, 15)));
+        assert_eq!(
+            disbursement_dates(&monthly, date(2031, Month::January, 1)),
+            vec![start, date(2030, Month::February, 15), date(2030, Month::March, 15)],
+        );
+        assert_eq!(disbursement_dates(&monthly, date(2030, Month::February, 14)), vec![start]);
+        assert!(disbursement_dates(&monthly, date(2030, Month::January, 14)).is_empty());
+
+        let one_off = schedule(DisbursementFrequency::OneOff, start, None);
+        assert_eq!(disbursement_dates(&one_off, date(2040, Month::January, 1)), vec![start]);
+        let annually = schedule(DisbursementFrequency::Annually, start, None);
+        assert_eq!(disbursement_dates(&annually, date(2032, Month::January, 15)).len(), 3);
+        let revenue = schedule(DisbursementFrequency::RevenueTriggered, start, None);
+        assert!(disbursement_dates(&revenue, date(2040, Month::January, 1)).is_empty());
+    }
+
+    #[test]
+    fn month_end_start_dates_clamp_without_drifting() {
+        let monthly = schedule(DisbursementFrequency::Monthly, date(2030, Month::January, 31), None);
+        assert_eq!(
+            disbursement_dates(&monthly, date(2030, Month::April, 30)),
+            vec![date(2030, Month::January, 31), date(2030, Month::February, 28), date(2030, Month::March, 31), date(2030, Month::April, 30)],
+        );
+
+        let quarterly = schedule(DisbursementFrequency::Quarterly, date(2031, Month::November, 30), None);
+        assert_eq!(
+            disbursement_dates(&quarterly, date(2032, Month::November, 30)),
+            vec![
+                date(2031, Month::November, 30),
+                date(2032, Month::February, 29),
+                date(2032, Month::May, 30),
+                date(2032, Month::August, 30),
+                date(2032, Month::November, 30),
+            ],
+        );
+    }
+
+    #[test]
+    fn scheduling_again_only_adds_new_disbursements() {
+        let start = date(2030, Month::January, 1);
+        let contract = sale_with_residuals(
+            schedule(DisbursementFrequency::Monthly, start, None),
+            ResidualAmount::FixedPerDisbursement(usd(101)),
+        );
+        let mut obligations = ResidualObligations::new();
+
+        let first = obligations.schedule_through(&contract, date(2030, Month::February, 1)).unwrap();
+        assert_eq!(first.len(), 4);
+        // Beneficiaries in entity_id order, the odd cent going to the first.
+        assert_eq!(first[0].beneficiary, EntityId("C-G001".to_string()));
+        assert_eq!(first[0].amount, usd(51));
+        assert_eq!(first[1].amount, usd(50));
+
+        assert!(obligations.schedule_through(&contract, date(2030, Month::February, 1)).unwrap().is_empty());
+        let second = obligations.schedule_through(&contract, date(2030, Month::March, 1)).unwrap();
+        assert_eq!(second.len(), 2);
+        assert!(second.iter().all(|o| o.due_on == date(2030, Month::March, 1)));
+        assert_eq!(obligations.all().len(), 6);
+        assert_eq!(obligations.outstanding(&Currency::usd()), usd(303));
+    }
+
+    #[test]
+    fn revenue_is_only_recorded_within_the_schedule() {
+        let start = date(2030, Month::January, 1);
+        let end = date(2030, Month::December, 31);
+        let contract = sale_with_residuals(
+            schedule(DisbursementFrequency::RevenueTriggered, start, Some(end)),
+            ResidualAmount::PercentOfRevenue(Rate::percent(10).unwrap()),
+        );
+        let mut obligations = ResidualObligations::new();
+
+        assert!(matches!(
+            obligations.record_revenue(&contract, usd(1_000), date(2029, Month::December, 31)),
+            Err(ResidualError::OutsideSchedule { .. }),
+        ));
+        assert!(matches!(
+            obligations.record_revenue(&contract, usd(1_000), date(2031, Month::January, 1)),
+            Err(ResidualError::OutsideSchedule { .. }),
+        ));
+        let created = obligations.record_revenue(&contract, usd(1_000), end).unwrap();
+        assert_eq!(created.iter().map(|o| o.amount.amount_minor).sum::<i64>(), 100);
+        assert!(created.iter().all(|o| o.trigger == ObligationTrigger::Revenue { revenue: usd(1_000) }));
+        assert!(matches!(obligations.schedule_through(&contract, end), Err(ResidualError::RevenueTriggered(_))));
+    }
+
+    #[test]
+    fn an_obligation_is_paid_once() {
+        let start = date(2030, Month::January, 1);
+        let contract = sale_with_residuals(
+            schedule(DisbursementFrequency::OneOff, start, None),
+            ResidualAmount::FixedPerDisbursement(usd(100)),
+        );
+        let mut obligations = ResidualObligations::new();
+        let obligation_id = obligations.schedule_through(&contract, start).unwrap()[0].obligation_id.clone();
+        assert!(matches!(obligations.record_revenue(&contract, usd(1_000), start), Err(ResidualError::NotRevenueTriggered(_))));
+
+        obligations.mark_paid(&obligation_id, date(2030, Month::January, 5)).unwrap();
+        let paid_again = obligations.mark_paid(&obligation_id, date(2030, Month::January, 6));
+        assert!(matches!(paid_again, Err(ResidualError::AlreadyPaid { paid_on, .. }) if paid_on == date(2030, Month::January, 5)));
+        assert!(matches!(obligations.mark_paid("C-DS-001-R99999", start), Err(ResidualError::UnknownObligation(_))));
+        assert_eq!(obligations.outstanding(&Currency::usd()), usd(50));
+        assert_eq!(obligations.overdue(date(2030, Month::February, 1)).len(), 1);
+    }
+}
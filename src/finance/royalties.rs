@@ -0,0 +1,317 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::{Date, Duration};
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::duration::{consecutive_periods, TermLength};
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::finance::money::{Currency, Money, Rate};
+
+/*
+Royalty reporting for derivative works made under a LicensingAgreement with royalty terms.
+
+The licensee (agent_b) registers each derivative work while the agreement is in force. From the day a work is
+registered, its revenue is reported once per terms.royalties.reporting_period, for each period after it has ended.
+Works keep earning after the licence ends, so reports can still be filed then. Each report computes the royalties owed:
+    - licensor_rate of the revenue to the licensor (agent_a), the DataOriginator or DataCustodian licensing the data;
+      originators behind a custodian are not parties to the licence and are not paid here (see RoyaltyTerms),
+    - generator_rate of the revenue, shared equally by the generators.
+Minor units that cannot be shared evenly go to the generators first in entity_id order.
+*/
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DerivativeWork {
+    pub work_id: String,
+    pub contract_id: String,
+    pub licensee: EntityId,
+    pub title: String,
+    pub registered_on: Date,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RoyaltyShare {
+    Licensor,
+    Generator,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoyaltyLine {
+    pub payee: EntityId,
+    pub share: RoyaltyShare,
+    pub rate: Rate,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RevenueReport {
+    pub report_id: String,
+    pub work_id: String,
+    pub contract_id: String,
+    pub licensee: EntityId,
+    pub period_start: Date,
+    pub period_end: Date,
+    pub revenue: Money,
+    pub filed_on: Date,
+    pub due_on: Date,
+    pub royalties: Vec<RoyaltyLine>,
+}
+
+impl RevenueReport {
+    pub fn total_royalties(&self) -> Money {
+        Money::new(self.royalties.iter().map(|line| line.amount.amount_minor).sum(), self.revenue.currency.clone())
+    }
+
+    pub fn owed_to(&self, payee: &EntityId) -> Money {
+        let amount_minor = self.royalties.iter().filter(|line| line.payee == *payee).map(|line| line.amount.amount_minor).sum();
+        Money::new(amount_minor, self.revenue.currency.clone())
+    }
+}
+
+#[derive(Debug)]
+pub enum RoyaltyError {
+    NotLicensing(AgreementKind),
+    NoRoyaltyTerms(String),
+    NotLicensee { contract_id: String, entity_id: EntityId },
+    NotInForce { contract_id: String, on: Date },
+    UnknownWork(String),
+    WrongContract { work_id: String, contract_id: String },
+    NotAReportingPeriod { work_id: String, period_start: Date },
+    PeriodNotEnded { work_id: String, period_end: Date },
+    AlreadyReported { work_id: String, period_start: Date, report_id: String },
+    NegativeRevenue(Money),
+    DueDateOutOfRange { work_id: String, filed_on: Date },
+}
+
+impl fmt::Display for RoyaltyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoyaltyError::NotLicensing(kind) => write!(f, "Royalty Error: derivative works are licensed under a LicensingAgreement, not a {}", kind),
+            RoyaltyError::NoRoyaltyTerms(contract_id) => write!(f, "Royalty Error: contract {} has no royalty terms", contract_id),
+            RoyaltyError::NotLicensee { contract_id, entity_id } => write!(f, "Royalty Error: {} is not the licensee of contract {}", entity_id.0, contract_id),
+            RoyaltyError::NotInForce { contract_id, on } => write!(f, "Royalty Error: contract {} is not in force on {}", contract_id, on),
+            RoyaltyError::UnknownWork(work_id) => write!(f, "Royalty Error: derivative work {} not found", work_id),
+            RoyaltyError::WrongContract { work_id, contract_id } => write!(f, "Royalty Error: derivative work {} was not registered under contract {}", work_id, contract_id),
+            RoyaltyError::NotAReportingPeriod { work_id, period_start } => write!(f, "Royalty Error: no reporting period of work {} starts on {}", work_id, period_start),
+            RoyaltyError::PeriodNotEnded { work_id, period_end } => write!(f, "Royalty Error: the reporting period of work {} only ends on {}", work_id, period_end),
+            RoyaltyError::AlreadyReported { work_id, period_start, report_id } => {
+                write!(f, "Royalty Error: revenue of work {} for the period starting {} was already reported in {}", work_id, period_start, report_id)
+            }
+            RoyaltyError::NegativeRevenue(revenue) => write!(f, "Royalty Error: reported revenue cannot be negative ({})", revenue),
+            RoyaltyError::DueDateOutOfRange { work_id, filed_on } => {
+                write!(f, "Royalty Error: royalties on work {} filed on {} would fall due beyond the last representable date", work_id, filed_on)
+            }
+        }
+    }
+}
+
+// The licensor, licensee and generators of a LicensingAgreement, with its royalty terms.
+fn licence_of(contract: &HealthDataContract) -> Result<(&Party, &Party, &[Party], &RoyaltyTerms), RoyaltyError> {
+    let ContractCategory::ThreePlusParty(TransactionLegalStructure::LicensingAgreement { agent_a, agent_b, generators, .. }) = contract.get_agreement_type() else {
+        return Err(RoyaltyError::NotLicensing(contract.get_agreement_type().kind()));
+    };
+    let terms = contract.get_terms().royalties.as_ref().ok_or_else(|| RoyaltyError::NoRoyaltyTerms(contract.get_contract_id().to_string()))?;
+    Ok((agent_a, agent_b, generators, terms))
+}
+
+// The last day of the reporting period starting on period_start, if one does.
+fn period_ending(length: TermLength, first_day: Date, period_start: Date) -> Option<Date> {
+    if length.is_zero() {
+        return None;
+    }
+    (0..)
+        .map(|n| (length.times(n).after(first_day), n))
+        .take_while(|(start, _)| *start <= period_start)
+        .find(|(start, _)| *start == period_start)
+        .map(|(_, n)| length.times(n + 1).after(first_day) - Duration::days(1))
+}
+
+#[derive(Debug, Default)]
+pub struct RoyaltyRegistry {
+    works: Vec<DerivativeWork>,
+    reports: Vec<RevenueReport>,
+}
+
+impl RoyaltyRegistry {
+    pub fn new() -> Self {
+        RoyaltyRegistry {
+            works: Vec::new(),
+            reports: Vec::new(),
+        }
+    }
+
+    pub fn register_work(&mut self, contract: &HealthDataContract, licensee: &EntityId, title: impl Into<String>, on: Date) -> Result<&DerivativeWork, RoyaltyError> {
+        let (_, agent_b, _, _) = licence_of(contract)?;
+        let contract_id = contract.get_contract_id();
+        if agent_b.entity_id() != licensee {
+            return Err(RoyaltyError::NotLicensee { contract_id: contract_id.to_string(), entity_id: licensee.clone() });
+        }
+        if !contract.is_in_force(on) {
+            return Err(RoyaltyError::NotInForce { contract_id: contract_id.to_string(), on });
+        }
+        let work = DerivativeWork {
+            work_id: format!("DW-{:05}", self.works.len() + 1),
+            contract_id: contract_id.to_string(),
+            licensee: licensee.clone(),
+            title: title.into(),
+            registered_on: on,
+        };
+        self.works.push(work);
+        Ok(self.works.last().expect("work was just registered"))
+    }
+
+    // The reporting periods of a work that have ended by through.
+    pub fn reporting_periods(&self, contract: &HealthDataContract, work_id: &str, through: Date) -> Result<Vec<(Date, Date)>, RoyaltyError> {
+        let (_, _, _, terms) = licence_of(contract)?;
+        let work = self.work_under(contract, work_id)?;
+        Ok(consecutive_periods(terms.reporting_period, work.registered_on, None, through))
+    }
+
+    // Reporting periods that have ended by on without a revenue report.
+    pub fn missing_reports(&self, contract: &HealthDataContract, work_id: &str, on: Date) -> Result<Vec<(Date, Date)>, RoyaltyError> {
+        let periods = self.reporting_periods(contract, work_id, on)?;
+        Ok(periods.into_iter()
+            .filter(|(start, _)| !self.reports.iter().any(|r| r.work_id == work_id && r.period_start == *start))
+            .collect())
+    }
+
+    // Files the revenue a work earned in the reporting period starting on period_start and computes the royalties owed on it.
+    pub fn file_report(&mut self, contract: &HealthDataContract, work_id: &str, period_start: Date, revenue: Money, filed_on: Date) -> Result<&RevenueReport, RoyaltyError> {
+        let (agent_a, _, generators, terms) = licence_of(contract)?;
+        if revenue.is_negative() {
+            return Err(RoyaltyError::NegativeRevenue(revenue));
+        }
+        let work = self.work_under(contract, work_id)?;
+        let period_end = period_ending(terms.reporting_period, work.registered_on, period_start)
+            .ok_or_else(|| RoyaltyError::NotAReportingPeriod { work_id: work_id.to_string(), period_start })?;
+        if period_end >= filed_on {
+            return Err(RoyaltyError::PeriodNotEnded { work_id: work_id.to_string(), period_end });
+        }
+        if let Some(existing) = self.reports.iter().find(|r| r.work_id == work_id && r.period_start == period_start) {
+            return Err(RoyaltyError::AlreadyReported { work_id: work_id.to_string(), period_start, report_id: existing.report_id.clone() });
+        }
+        let due_on = filed_on.checked_add(Duration::days(terms.payment_terms_days as i64))
+            .ok_or_else(|| RoyaltyError::DueDateOutOfRange { work_id: work_id.to_string(), filed_on })?;
+
+        let mut royalties = vec![RoyaltyLine {
+            payee: agent_a.entity_id().clone(),
+            share: RoyaltyShare::Licensor,
+            rate: terms.licensor_rate,
+            amount: terms.licensor_rate.of(&revenue),
+        }];
+        let mut generator_ids: Vec<&EntityId> = generators.iter().map(|g| g.entity_id()).collect();
+        generator_ids.sort_by(|a, b| a.0.cmp(&b.0));
+        let generator_shares = terms.generator_rate.of(&revenue).split_evenly(generator_ids.len());
+        royalties.extend(generator_ids.into_iter().zip(generator_shares).map(|(payee, amount)| RoyaltyLine {
+            payee: payee.clone(),
+            share: RoyaltyShare::Generator,
+            rate: terms.generator_rate,
+            amount,
+        }));
+        royalties.retain(|line| !line.amount.is_zero());
+
+        let report = RevenueReport {
+            report_id: format!("RR-{:06}", self.reports.len() + 1),
+            work_id: work_id.to_string(),
+            contract_id: work.contract_id.clone(),
+            licensee: work.licensee.clone(),
+            period_start,
+            period_end,
+            revenue,
+            filed_on,
+            due_on,
+            royalties,
+        };
+        self.reports.push(report);
+        Ok(self.reports.last().expect("report was just filed"))
+    }
+
+    fn work_under(&self, contract: &HealthDataContract, work_id: &str) -> Result<&DerivativeWork, RoyaltyError> {
+        let work = self.get_work(work_id).ok_or_else(|| RoyaltyError::UnknownWork(work_id.to_string()))?;
+        if work.contract_id != contract.get_contract_id() {
+            return Err(RoyaltyError::WrongContract { work_id: work_id.to_string(), contract_id: contract.get_contract_id().to_string() });
+        }
+        Ok(work)
+    }
+
+    pub fn get_work(&self, work_id: &str) -> Option<&DerivativeWork> {
+        self.works.iter().find(|w| w.work_id == work_id)
+    }
+
+    pub fn works_for_contract<'a>(&'a self, contract_id: &'a str) -> impl Iterator<Item = &'a DerivativeWork> {
+        self.works.iter().filter(move |w| w.contract_id == contract_id)
+    }
+
+    pub fn get_report(&self, report_id: &str) -> Option<&RevenueReport> {
+        self.reports.iter().find(|r| r.report_id == report_id)
+    }
+
+    pub fn reports_for_work<'a>(&'a self, work_id: &'a str) -> impl Iterator<Item = &'a RevenueReport> {
+        self.reports.iter().filter(move |r| r.work_id == work_id)
+    }
+
+    // Royalties owed to a payee in the given currency across all reports filed between from and to (inclusive).
+    pub fn royalties_owed_to(&self, payee: &EntityId, currency: &Currency, from: Date, to: Date) -> Money {
+        let amount_minor = self.reports.iter()
+            .filter(|r| r.filed_on >= from && r.filed_on <= to && r.revenue.currency == *currency)
+            .map(|r| r.owed_to(payee).amount_minor)
+            .sum();
+        Money::new(amount_minor, currency.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::contracts::*;
+    use crate::test_support::sale;
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    // A LicensingAgreement from licensor I-C001 to licensee I-R001 of data made by generators C-G001 and C-G002.
+    fn executed_licence(payment_terms_days: u32) -> HealthDataContract {
+        let royalties = RoyaltyTerms {
+            licensor_rate: Rate::percent(10).unwrap(),
+            generator_rate: Rate::percent(5).unwrap(),
+            reporting_period: TermLength::Days(1),
+            payment_terms_days,
+        };
+        sale()
+            .kind(AgreementKind::LicensingAgreement)
+            .contract_id("C-LA-001")
+            .generator_count(2)
+            .terms(Terms { royalties: Some(royalties), ..Terms::default() })
+            .generator_rate(GeneratorRateSpecification::KnowledgeRate(Rate::percent(5).unwrap()))
+            .executed()
+    }
+
+    #[test]
+    fn report_owes_the_licensor_and_generators_their_rates() {
+        let contract = executed_licence(30);
+        let today = OffsetDateTime::now_utc().date();
+        let mut registry = RoyaltyRegistry::new();
+        let work_id = registry.register_work(&contract, &EntityId("I-R001".to_string()), "Risk model", today).unwrap().work_id.clone();
+
+        let report = registry.file_report(&contract, &work_id, today, usd(100_001), today + Duration::days(1)).unwrap();
+        assert_eq!(report.owed_to(&EntityId("I-C001".to_string())), usd(10_000));
+        // 5% of 1000.01 is 50.00, shared by both generators.
+        assert_eq!(report.owed_to(&EntityId("C-G001".to_string())), usd(2_500));
+        assert_eq!(report.owed_to(&EntityId("C-G002".to_string())), usd(2_500));
+        assert_eq!(report.due_on, today + Duration::days(31));
+    }
+
+    #[test]
+    fn due_date_past_the_last_representable_date_is_an_error() {
+        let contract = executed_licence(u32::MAX);
+        let today = OffsetDateTime::now_utc().date();
+        let mut registry = RoyaltyRegistry::new();
+        let work_id = registry.register_work(&contract, &EntityId("I-R001".to_string()), "Risk model", today).unwrap().work_id.clone();
+
+        let err = registry.file_report(&contract, &work_id, today, usd(100_000), today + Duration::days(1)).unwrap_err();
+        assert!(matches!(err, RoyaltyError::DueDateOutOfRange { .. }));
+        assert_eq!(registry.reports_for_work(&work_id).count(), 0);
+    }
+}
@@ -0,0 +1,352 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::Date;
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::finance::transaction_registry::*;
+use crate::finance::money::{Money, Rate};
+
+/*
+Splits the gross amount paid under a transaction contract among the parties it compensates.
+
+Amounts are exact Money, so a split always adds up to the gross amount. From the gross amount:
+    - HBank takes terms.fees.hbank_fee,
+    - the generators share the contract's generator rate; a Tiered rate resolves to KnowledgeRate for the first
+      transaction of the data bundle and to UsageRate afterwards, as counted by the engine's TransactionRegistry,
+    - any consultants who are neither paying nor being paid share terms.fees.consultant,
+    - the DataOriginators who have not revoked their consent share terms.fees.originator, whether or not they are
+      also payees; originators who revoked get no share of settlements made on or after the day they revoked,
+    - the payees named by the agreement type receive whatever remains. A payee who is a DataOriginator and has
+      revoked their consent cannot be paid for their data, so nothing can be settled under the contract from then on.
+Each share is rounded to the nearest minor unit. When a share is divided among several parties, the minor units
+that cannot be divided evenly go to the parties first in entity_id order, so the same inputs always give the same split.
+
+Who pays and who is paid follows the agreement type:
+    ConsultAgreement                       agent pays, consultant is paid
+    DirectSale / Purchase / Licensing /
+    Access / Subscription                  agent_b pays, agent_a is paid
+    ParticipationAgreement                 funders pay, agents are paid
+    DataExchangeAgreement                  agents_b pay, agents_a are paid
+Two-party agreements and ConsortiumAgreement carry no compensation and cannot be settled.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ShareKind {
+    HBankFee,
+    GeneratorShare,
+    ConsultantFee,
+    OriginatorShare,
+    Proceeds,
+}
+
+// One party's part of a settlement, with the rate it was computed from (None for the remainder paid as Proceeds).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettlementLine {
+    pub entity_id: EntityId,
+    pub party_type: PartyType,
+    pub kind: ShareKind,
+    pub rate: Option<Rate>,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settlement {
+    pub contract_id: String,
+    pub revision: u32,
+    pub settled_on: Date,
+    pub gross: Money,
+    pub paid_by: Vec<EntityId>,
+    pub bundle: DataBundleId,
+    pub prior_transactions: u64,
+    pub generator_rate: Option<GeneratorRateSpecification>,
+    pub lines: Vec<SettlementLine>,
+}
+
+impl Settlement {
+    // Always equal to gross.
+    pub fn total(&self) -> Money {
+        Money::new(self.lines.iter().map(|line| line.amount.amount_minor).sum(), self.gross.currency.clone())
+    }
+
+    pub fn amount_for(&self, entity_id: &EntityId) -> Money {
+        let amount_minor = self.lines.iter().filter(|line| line.entity_id == *entity_id).map(|line| line.amount.amount_minor).sum();
+        Money::new(amount_minor, self.gross.currency.clone())
+    }
+
+    pub fn lines_of_kind(&self, kind: ShareKind) -> impl Iterator<Item = &SettlementLine> {
+        self.lines.iter().filter(move |line| line.kind == kind)
+    }
+}
+
+#[derive(Debug)]
+pub enum SettlementError {
+    NotSettleable(AgreementKind),
+    NotInForce { contract_id: String, on: Date },
+    MissingParty(PartyType),
+    NegativeAmount(Money),
+    SharesExceedGross { shares: Money, gross: Money },
+    ConsentRevoked(EntityId),
+}
+
+impl fmt::Display for SettlementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettlementError::NotSettleable(kind) => write!(f, "Settlement Error: a {} carries no compensation to settle", kind),
+            SettlementError::NotInForce { contract_id, on } => write!(f, "Settlement Error: contract {} is not in force on {}", contract_id, on),
+            SettlementError::MissingParty(party_type) => write!(f, "Settlement Error: the contract has no {:?} party to pay", party_type),
+            SettlementError::NegativeAmount(amount) => write!(f, "Settlement Error: cannot settle a negative amount ({})", amount),
+            SettlementError::SharesExceedGross { shares, gross } => {
+                write!(f, "Settlement Error: fees and shares of {} exceed the gross amount of {}", shares, gross)
+            }
+            SettlementError::ConsentRevoked(entity_id) => write!(f, "Settlement Error: data originator {} has revoked consent and cannot be paid", entity_id.0),
+        }
+    }
+}
+
+// The parties who pay and the parties who receive the remainder, as named by the agreement type.
+pub fn payers_and_payees(agreement_type: &ContractCategory) -> Option<(Vec<&Party>, Vec<&Party>)> {
+    match agreement_type {
+        ContractCategory::TwoParty(_) => None,
+        ContractCategory::ThreePlusParty(structure) => match structure {
+            TransactionLegalStructure::ConsultAgreement { agent, consultant, .. } => Some((vec![agent], vec![consultant])),
+            TransactionLegalStructure::DirectSale { agent_a, agent_b, .. } |
+            TransactionLegalStructure::PurchaseAgreement { agent_a, agent_b, .. } |
+            TransactionLegalStructure::LicensingAgreement { agent_a, agent_b, .. } |
+            TransactionLegalStructure::AccessAgreement { agent_a, agent_b, .. } |
+            TransactionLegalStructure::SubscriptionAgreement { agent_a, agent_b, .. } => Some((vec![agent_b], vec![agent_a])),
+            TransactionLegalStructure::ConsortiumAgreement { .. } => None,
+            TransactionLegalStructure::ParticipationAgreement { agents, funders, .. } => Some((funders.iter().collect(), agents.iter().collect())),
+            TransactionLegalStructure::DataExchangeAgreement { agents_a, agents_b, .. } => Some((agents_b.iter().collect(), agents_a.iter().collect())),
+        },
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SettlementEngine {
+    transactions: TransactionRegistry,
+}
+
+impl SettlementEngine {
+    pub fn new() -> Self {
+        SettlementEngine {
+            transactions: TransactionRegistry::new(),
+        }
+    }
+
+    pub fn with_registry(transactions: TransactionRegistry) -> Self {
+        SettlementEngine { transactions }
+    }
+
+    pub fn transactions(&self) -> &TransactionRegistry {
+        &self.transactions
+    }
+
+    // Settles a payment made under the contract on the given date, treating the contract's cohort (or the contract itself) as the data bundle.
+    pub fn settle(&mut self, contract: &HealthDataContract, gross: Money, on: Date) -> Result<Settlement, SettlementError> {
+        self.settle_bundle(contract, &DataBundleId::for_contract(contract), gross, on)
+    }
+
+    /*
+    Settles a payment for a specific data bundle. The generator rate is resolved from the number of earlier transactions
+    of the bundle, and a successful settlement under a counted agreement type records this contract's transaction.
+    */
+    pub fn settle_bundle(&mut self, contract: &HealthDataContract, bundle: &DataBundleId, gross: Money, on: Date) -> Result<Settlement, SettlementError> {
+        let prior_transactions = self.transactions.prior_transactions(bundle, contract.get_contract_id());
+        let generator_rate = contract.get_generator_rate().map(|rate| rate.resolve(prior_transactions));
+        let settlement = split(contract, bundle, prior_transactions, gross, on, generator_rate)?;
+        if let Some(record) = self.transactions.record(bundle, contract, on) {
+            debug_assert_eq!(record.sequence, prior_transactions + 1, "settled {} against the wrong transaction count", record.contract_id);
+        }
+        Ok(settlement)
+    }
+}
+
+fn split(
+    contract: &HealthDataContract,
+    bundle: &DataBundleId,
+    prior_transactions: u64,
+    gross: Money,
+    on: Date,
+    generator_rate: Option<GeneratorRateSpecification>,
+) -> Result<Settlement, SettlementError> {
+    let kind = contract.get_agreement_type().kind();
+    let (payers, payees) = payers_and_payees(contract.get_agreement_type()).ok_or(SettlementError::NotSettleable(kind))?;
+    if !contract.is_in_force(on) {
+        return Err(SettlementError::NotInForce { contract_id: contract.get_contract_id().to_string(), on });
+    }
+
+    if gross.is_negative() {
+        return Err(SettlementError::NegativeAmount(gross));
+    }
+    if let Some(payee) = payees.iter().find(|p| p.party_type() == PartyType::DataOriginator && contract.had_revoked_by(p.entity_id(), on)) {
+        return Err(SettlementError::ConsentRevoked(payee.entity_id().clone()));
+    }
+
+    let parties = contract.get_parties();
+    let fees = &contract.get_terms().fees;
+    let of_type = |party_type: PartyType| -> Vec<&Party> { parties.iter().filter(|p| p.party_type() == party_type).collect() };
+    let is_payer_or_payee = |party: &Party| payers.iter().chain(payees.iter()).any(|p| p.entity_id() == party.entity_id());
+
+    let mut lines = Vec::new();
+    if fees.hbank_fee > Rate::ZERO {
+        let hbank = of_type(PartyType::HBank);
+        if hbank.is_empty() {
+            return Err(SettlementError::MissingParty(PartyType::HBank));
+        }
+        lines.extend(share_equally(&hbank, ShareKind::HBankFee, Some(fees.hbank_fee), fees.hbank_fee.of(&gross)));
+    }
+    if let Some(rate) = &generator_rate {
+        let generators = of_type(PartyType::DataGenerator);
+        if generators.is_empty() {
+            return Err(SettlementError::MissingParty(PartyType::DataGenerator));
+        }
+        let rate = rate.max_rate();
+        lines.extend(share_equally(&generators, ShareKind::GeneratorShare, Some(rate), rate.of(&gross)));
+    }
+    if fees.consultant > Rate::ZERO {
+        let consultants: Vec<&Party> = of_type(PartyType::DataConsultant).into_iter().filter(|p| !is_payer_or_payee(p)).collect();
+        if consultants.is_empty() {
+            return Err(SettlementError::MissingParty(PartyType::DataConsultant));
+        }
+        lines.extend(share_equally(&consultants, ShareKind::ConsultantFee, Some(fees.consultant), fees.consultant.of(&gross)));
+    }
+    if fees.originator > Rate::ZERO {
+        let originators: Vec<&Party> = of_type(PartyType::DataOriginator).into_iter()
+            .filter(|p| !contract.had_revoked_by(p.entity_id(), on))
+            .collect();
+        if originators.is_empty() {
+            return Err(SettlementError::MissingParty(PartyType::DataOriginator));
+        }
+        lines.extend(share_equally(&originators, ShareKind::OriginatorShare, Some(fees.originator), fees.originator.of(&gross)));
+    }
+
+    let shares = Money::new(lines.iter().map(|line: &SettlementLine| line.amount.amount_minor).sum(), gross.currency.clone());
+    if shares.amount_minor > gross.amount_minor {
+        return Err(SettlementError::SharesExceedGross { shares, gross });
+    }
+    let proceeds = Money::new(gross.amount_minor - shares.amount_minor, gross.currency.clone());
+    lines.extend(share_equally(&payees, ShareKind::Proceeds, None, proceeds));
+
+    Ok(Settlement {
+        contract_id: contract.get_contract_id().to_string(),
+        revision: contract.get_revision(),
+        settled_on: on,
+        gross,
+        paid_by: payers.iter().map(|p| p.entity_id().clone()).collect(),
+        bundle: bundle.clone(),
+        prior_transactions,
+        generator_rate,
+        lines,
+    })
+}
+
+fn share_equally(parties: &[&Party], kind: ShareKind, rate: Option<Rate>, amount: Money) -> Vec<SettlementLine> {
+    let mut parties: Vec<&Party> = parties.to_vec();
+    parties.sort_by(|a, b| a.entity_id().0.cmp(&b.entity_id().0));
+    parties.iter().zip(amount.split_evenly(parties.len())).map(|(party, amount)| SettlementLine {
+        entity_id: party.entity_id().clone(),
+        party_type: party.party_type(),
+        kind,
+        rate,
+        amount,
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Duration, OffsetDateTime};
+
+    use super::*;
+    use crate::contracts::*;
+    use crate::persons::consent::Consent;
+    use crate::finance::money::Currency;
+    use crate::test_support::sale;
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    fn fees(fees: FeeTerms) -> Terms {
+        Terms { fees, ..Terms::default() }
+    }
+
+    // A DirectSale from custodian I-C001 to recipient I-R001 of data made by the given number of generators.
+    fn executed_sale(generator_count: usize, terms: Terms) -> HealthDataContract {
+        sale().generator_count(generator_count).terms(terms).executed()
+    }
+
+    #[test]
+    fn settlement_lines_add_up_to_the_gross_amount() {
+        let contract = executed_sale(3, fees(FeeTerms { hbank_fee: Rate::from_basis_points(725).unwrap(), ..FeeTerms::default() }));
+        let mut engine = SettlementEngine::new();
+
+        for gross in [usd(0), usd(1), usd(100_001), usd(7_777_777)] {
+            let settlement = engine.settle(&contract, gross.clone(), OffsetDateTime::now_utc().date()).unwrap();
+            assert_eq!(settlement.total(), gross);
+            assert_eq!(settlement.lines_of_kind(ShareKind::GeneratorShare).count(), 3);
+            assert_eq!(settlement.lines_of_kind(ShareKind::HBankFee).count(), 1);
+        }
+    }
+
+    #[test]
+    fn generator_shares_differ_by_at_most_one_minor_unit() {
+        let contract = executed_sale(3, Terms::default());
+        let settlement = SettlementEngine::new().settle(&contract, usd(100_001), OffsetDateTime::now_utc().date()).unwrap();
+
+        // 3% of 1000.01 is 30.00, shared by three generators in entity_id order.
+        let shares: Vec<i64> = settlement.lines_of_kind(ShareKind::GeneratorShare).map(|line| line.amount.amount_minor).collect();
+        assert_eq!(shares, vec![1_000, 1_000, 1_000]);
+        assert_eq!(settlement.amount_for(&EntityId("I-C001".to_string())), usd(100_001 - 3_000));
+        assert_eq!(settlement.paid_by, vec![EntityId("I-R001".to_string())]);
+    }
+
+    #[test]
+    fn settlement_requires_a_contract_in_force() {
+        let contract = sale().proposed();
+        let err = SettlementEngine::new().settle(&contract, usd(1_000), OffsetDateTime::now_utc().date()).unwrap_err();
+        assert!(matches!(err, SettlementError::NotInForce { .. }));
+    }
+
+    #[test]
+    fn originator_share_needs_a_consenting_originator() {
+        let contract = executed_sale(1, fees(FeeTerms { originator: Rate::percent(5).unwrap(), ..FeeTerms::default() }));
+        let err = SettlementEngine::new().settle(&contract, usd(1_000), OffsetDateTime::now_utc().date()).unwrap_err();
+        assert!(matches!(err, SettlementError::MissingParty(PartyType::DataOriginator)));
+    }
+
+    #[test]
+    fn negative_amounts_are_not_settled() {
+        let contract = executed_sale(1, Terms::default());
+        let err = SettlementEngine::new().settle(&contract, usd(-1), OffsetDateTime::now_utc().date()).unwrap_err();
+        assert!(matches!(err, SettlementError::NegativeAmount(_)));
+    }
+
+    #[test]
+    fn originator_who_revoked_consent_is_not_paid_from_that_day() {
+        const PSEUDONYM: &str = "PSN-0123456789abcdef0123456789abcdef";
+        let originator = EntityId(PSEUDONYM.to_string());
+        let consent = Consent::new(vec![DataCategory::Labs], vec![DataUsePurpose::Commercial], vec![PartyType::DataRecipient]);
+        let terms = Terms {
+            data_categories: vec![DataCategory::Labs],
+            fees: FeeTerms { originator: Rate::percent(10).unwrap(), ..FeeTerms::default() },
+            ..Terms::default()
+        };
+        let mut contract = sale().originator(PSEUDONYM, consent).terms(terms).executed();
+        let today = OffsetDateTime::now_utc().date();
+        let mut engine = SettlementEngine::new();
+
+        let settlement = engine.settle(&contract, usd(10_000), today).unwrap();
+        assert_eq!(settlement.lines_of_kind(ShareKind::OriginatorShare).map(|line| &line.entity_id).collect::<Vec<_>>(), vec![&originator]);
+        assert_eq!(settlement.amount_for(&originator), usd(10_000 - 300));
+
+        contract.revoke_consent_at(&originator, (today + Duration::days(2)).midnight().assume_utc()).unwrap();
+        assert!(engine.settle(&contract, usd(10_000), today + Duration::days(1)).is_ok());
+        for on in [today + Duration::days(2), today + Duration::days(3)] {
+            let err = engine.settle(&contract, usd(10_000), on).unwrap_err();
+            assert!(matches!(err, SettlementError::ConsentRevoked(ref entity_id) if *entity_id == originator));
+        }
+        // Every payment was under the same contract, so the bundle was transacted once.
+        assert_eq!(engine.transactions().transaction_count(&DataBundleId::for_contract(&contract)), 1);
+    }
+}
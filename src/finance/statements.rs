@@ -0,0 +1,460 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::{Date, Duration};
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::finance::settlement::{Settlement, ShareKind};
+use crate::finance::residuals::{ObligationStatus, ResidualObligations};
+use crate::finance::ledger::{AccountId, EntryKind, Ledger};
+use crate::finance::money::{Currency, Money};
+
+/*
+Periodic statements showing a party what it owes and is owed over a date range (inclusive), in one currency.
+
+A statement lists:
+    - the contracts the entity is a party to that were in force on any day of the period,
+    - the settlements it paid or received a share of,
+    - the residuals that fell due to it (whether paid yet or not),
+    - the fees it paid (its part of the HBank and consultant fees on settlements it paid) and the fees it earned,
+    - every ledger entry that moved its account, with the opening balance the day before the period and the
+      closing (net) balance on its last day.
+Amounts on the entity's account follow the ledger: positive is owed to the entity, negative is owed by it.
+
+Statements export to JSON with to_json() and to a single CSV table with to_csv(), one row per item with a section column.
+*/
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementContract {
+    pub contract_id: String,
+    pub revision: u32,
+    pub agreement_kind: AgreementKind,
+    pub roles: Vec<PartyType>,
+    pub effective_from: Option<Date>,
+    // None if the contract runs until it is terminated.
+    pub last_day_in_force: Option<Date>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementSettlement {
+    pub contract_id: String,
+    pub settled_on: Date,
+    pub gross: Money,
+    pub paid: Money,
+    pub received: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementResidual {
+    pub obligation_id: String,
+    pub contract_id: String,
+    pub due_on: Date,
+    pub amount: Money,
+    pub paid_on: Option<Date>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementActivity {
+    pub entry_id: String,
+    pub posted_on: Date,
+    pub kind: EntryKind,
+    pub memo: String,
+    pub amount: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementTotals {
+    pub settlements_paid: Money,
+    pub settlements_received: Money,
+    pub residuals_accrued: Money,
+    pub fees_paid: Money,
+    pub fees_earned: Money,
+    pub opening_balance: Money,
+    pub closing_balance: Money,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Statement {
+    pub entity_id: EntityId,
+    pub from: Date,
+    pub to: Date,
+    pub currency: Currency,
+    pub contracts: Vec<StatementContract>,
+    pub settlements: Vec<StatementSettlement>,
+    pub residuals: Vec<StatementResidual>,
+    pub activity: Vec<StatementActivity>,
+    pub totals: StatementTotals,
+}
+
+#[derive(Debug)]
+pub enum StatementError {
+    EmptyPeriod { from: Date, to: Date },
+    Export(String),
+}
+
+impl fmt::Display for StatementError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatementError::EmptyPeriod { from, to } => write!(f, "Statement Error: the period from {} to {} is empty", from, to),
+            StatementError::Export(e) => write!(f, "Statement Error: export failed: {}", e),
+        }
+    }
+}
+
+// The financial records a statement is drawn from.
+#[derive(Debug, Clone, Copy)]
+pub struct FinancialRecords<'a> {
+    pub settlements: &'a [Settlement],
+    pub residuals: &'a ResidualObligations,
+    pub ledger: &'a Ledger,
+}
+
+const CSV_HEADER: &str = "section,date,reference,kind,description,amount,currency";
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Amounts already filtered to the one currency.
+fn total<'a>(currency: &Currency, amounts: impl Iterator<Item = &'a Money>) -> Money {
+    Money::new(amounts.map(|m| m.amount_minor).sum(), currency.clone())
+}
+
+impl Statement {
+    pub fn generate<'a>(
+        entity_id: &EntityId,
+        from: Date,
+        to: Date,
+        currency: &Currency,
+        contracts: impl IntoIterator<Item = &'a HealthDataContract>,
+        records: FinancialRecords<'_>,
+    ) -> Result<Statement, StatementError> {
+        if to < from {
+            return Err(StatementError::EmptyPeriod { from, to });
+        }
+        let zero = || Money::zero(currency.clone());
+
+        let mut statement_contracts: Vec<StatementContract> = contracts.into_iter()
+            .filter(|contract| contract.was_in_force_between(from, to))
+            .filter_map(|contract| {
+                let roles: Vec<PartyType> = contract.get_parties().iter()
+                    .filter(|p| p.entity_id() == entity_id)
+                    .map(|p| p.party_type())
+                    .collect();
+                (!roles.is_empty()).then(|| StatementContract {
+                    contract_id: contract.get_contract_id().to_string(),
+                    revision: contract.get_revision(),
+                    agreement_kind: contract.get_agreement_type().kind(),
+                    roles,
+                    effective_from: contract.effective_from(),
+                    last_day_in_force: contract.last_day_in_force(),
+                })
+            })
+            .collect();
+        statement_contracts.sort_by(|a, b| a.contract_id.cmp(&b.contract_id));
+
+        let mut settlements = Vec::new();
+        let mut fees_paid = zero();
+        let mut fees_earned = zero();
+        for settlement in records.settlements.iter()
+            .filter(|s| s.settled_on >= from && s.settled_on <= to && s.gross.currency == *currency)
+        {
+            let is_fee = |kind: ShareKind| matches!(kind, ShareKind::HBankFee | ShareKind::ConsultantFee);
+            // A payer's part of the gross amount and of its fees, split as in the ledger.
+            let payer_index = settlement.paid_by.iter().position(|payer| payer == entity_id);
+            let paid = payer_index.map_or_else(zero, |i| settlement.gross.split_evenly(settlement.paid_by.len()).swap_remove(i));
+            if payer_index.is_none() && !settlement.lines.iter().any(|line| line.entity_id == *entity_id) {
+                continue;
+            }
+            if let Some(i) = payer_index {
+                let fees: i64 = settlement.lines.iter().filter(|line| is_fee(line.kind)).map(|line| line.amount.amount_minor).sum();
+                fees_paid.amount_minor += Money::new(fees, currency.clone()).split_evenly(settlement.paid_by.len())[i].amount_minor;
+            }
+            fees_earned.amount_minor += settlement.lines.iter()
+                .filter(|line| line.entity_id == *entity_id && is_fee(line.kind))
+                .map(|line| line.amount.amount_minor)
+                .sum::<i64>();
+            settlements.push(StatementSettlement {
+                contract_id: settlement.contract_id.clone(),
+                settled_on: settlement.settled_on,
+                gross: settlement.gross.clone(),
+                paid,
+                received: settlement.amount_for(entity_id),
+            });
+        }
+        settlements.sort_by_key(|s| s.settled_on);
+
+        let mut residuals: Vec<StatementResidual> = records.residuals.for_beneficiary(entity_id)
+            .filter(|o| o.due_on >= from && o.due_on <= to && o.amount.currency == *currency)
+            .map(|o| StatementResidual {
+                obligation_id: o.obligation_id.clone(),
+                contract_id: o.contract_id.clone(),
+                due_on: o.due_on,
+                amount: o.amount.clone(),
+                paid_on: match o.status {
+                    ObligationStatus::Paid { paid_on } => Some(paid_on),
+                    ObligationStatus::Outstanding => None,
+                },
+            })
+            .collect();
+        residuals.sort_by_key(|r| r.due_on);
+
+        let account = AccountId::Entity(entity_id.clone());
+        let activity: Vec<StatementActivity> = records.ledger.entries_for(&account, from, to)
+            .filter(|entry| entry.currency() == currency)
+            .map(|entry| StatementActivity {
+                entry_id: entry.entry_id.clone(),
+                posted_on: entry.posted_on,
+                kind: entry.kind,
+                memo: entry.memo.clone(),
+                amount: Money::new(
+                    entry.postings.iter().filter(|p| p.account == account).map(|p| p.signed_minor()).sum(),
+                    currency.clone(),
+                ),
+            })
+            .collect();
+
+        let totals = StatementTotals {
+            settlements_paid: total(currency, settlements.iter().map(|s| &s.paid)),
+            settlements_received: total(currency, settlements.iter().map(|s| &s.received)),
+            residuals_accrued: total(currency, residuals.iter().map(|r| &r.amount)),
+            fees_paid,
+            fees_earned,
+            opening_balance: records.ledger.balance(&account, currency, from - Duration::days(1)),
+            closing_balance: records.ledger.balance(&account, currency, to),
+        };
+
+        Ok(Statement {
+            entity_id: entity_id.clone(),
+            from,
+            to,
+            currency: currency.clone(),
+            contracts: statement_contracts,
+            settlements,
+            residuals,
+            activity,
+            totals,
+        })
+    }
+
+    // The net balance at the end of the period: positive if HBank owes the entity, negative if the entity owes.
+    pub fn net_balance(&self) -> &Money {
+        &self.totals.closing_balance
+    }
+
+    pub fn to_json(&self) -> Result<String, StatementError> {
+        serde_json::to_string_pretty(self).map_err(|e| StatementError::Export(e.to_string()))
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut rows: Vec<[String; 6]> = Vec::new();
+        let none = String::new;
+
+        for contract in &self.contracts {
+            let roles: Vec<String> = contract.roles.iter().map(|r| format!("{:?}", r)).collect();
+            let until = contract.last_day_in_force.map_or("until terminated".to_string(), |d| format!("until {}", d));
+            rows.push([
+                "contract".into(),
+                contract.effective_from.map_or_else(none, |d| d.to_string()),
+                contract.contract_id.clone(),
+                contract.agreement_kind.to_string(),
+                format!("Revision {} as {}, in force {}", contract.revision, roles.join("/"), until),
+                none(),
+            ]);
+        }
+        for settlement in &self.settlements {
+            let net = Money::new(settlement.received.amount_minor - settlement.paid.amount_minor, self.currency.clone());
+            rows.push([
+                "settlement".into(),
+                settlement.settled_on.to_string(),
+                settlement.contract_id.clone(),
+                if settlement.paid.is_zero() { "Received".into() } else { "Paid".into() },
+                format!("Gross {}: paid {}, received {}", settlement.gross, settlement.paid, settlement.received),
+                net.decimal_amount(),
+            ]);
+        }
+        for residual in &self.residuals {
+            rows.push([
+                "residual".into(),
+                residual.due_on.to_string(),
+                residual.obligation_id.clone(),
+                residual.paid_on.map_or("Outstanding".to_string(), |d| format!("Paid {}", d)),
+                format!("Residual under contract {}", residual.contract_id),
+                residual.amount.decimal_amount(),
+            ]);
+        }
+        for activity in &self.activity {
+            rows.push([
+                "activity".into(),
+                activity.posted_on.to_string(),
+                activity.entry_id.clone(),
+                format!("{:?}", activity.kind),
+                activity.memo.clone(),
+                activity.amount.decimal_amount(),
+            ]);
+        }
+        let totals = [
+            ("settlements_paid", &self.totals.settlements_paid, self.to),
+            ("settlements_received", &self.totals.settlements_received, self.to),
+            ("residuals_accrued", &self.totals.residuals_accrued, self.to),
+            ("fees_paid", &self.totals.fees_paid, self.to),
+            ("fees_earned", &self.totals.fees_earned, self.to),
+            ("opening_balance", &self.totals.opening_balance, self.from - Duration::days(1)),
+            ("closing_balance", &self.totals.closing_balance, self.to),
+        ];
+        for (name, amount, date) in totals {
+            rows.push(["total".into(), date.to_string(), self.entity_id.0.clone(), name.into(), none(), amount.decimal_amount()]);
+        }
+
+        let mut csv = String::from(CSV_HEADER);
+        csv.push('\n');
+        for row in rows {
+            let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+            csv.push_str(&fields.join(","));
+            csv.push(',');
+            csv.push_str(self.currency.code());
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::finance::money::Rate;
+    use crate::finance::settlement::SettlementEngine;
+    use crate::test_support::sale;
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    fn entity(id: &str) -> EntityId {
+        EntityId(id.to_string())
+    }
+
+    struct Books {
+        contract: HealthDataContract,
+        settlements: Vec<Settlement>,
+        residuals: ResidualObligations,
+        ledger: Ledger,
+    }
+
+    impl Books {
+        fn statement(&self, entity_id: &str, from: Date, to: Date) -> Statement {
+            let records = FinancialRecords { settlements: &self.settlements, residuals: &self.residuals, ledger: &self.ledger };
+            Statement::generate(&entity(entity_id), from, to, &Currency::usd(), [&self.contract], records).unwrap()
+        }
+    }
+
+    /*
+    A DirectSale from custodian I-C001 to recipient I-R001 with a 5% HBank fee and a monthly residual of 6.00 shared
+    by the custodian and generator C-G001 from day 3. The recipient deposits 2000.00 today, is settled 1000.00 on day
+    1 and 500.00 on day 10, and pays the custodian's first residual on day 4.
+    */
+    fn books(today: Date) -> Books {
+        let sale = sale().terms(Terms { fees: FeeTerms { hbank_fee: Rate::percent(5).unwrap(), ..FeeTerms::default() }, ..Terms::default() });
+        let beneficiaries = vec![sale.agent_a.clone(), sale.generators[0].clone()];
+        let contract = sale
+            .residual_payments(Residuals {
+                Beneficiaries: beneficiaries,
+                DisbursementSchedule: DisbursementSchedule { frequency: DisbursementFrequency::Monthly, start_date: today + Duration::days(3), end_date: None },
+                Amount: ResidualAmount::FixedPerDisbursement(usd(600)),
+            })
+            .executed();
+        let mut engine = SettlementEngine::new();
+        let mut ledger = Ledger::new();
+        let mut residuals = ResidualObligations::new();
+        let recipient = entity("I-R001");
+
+        ledger.post_deposit(&recipient, usd(200_000), today).unwrap();
+        let settlements = vec![
+            engine.settle(&contract, usd(100_000), today + Duration::days(1)).unwrap(),
+            engine.settle(&contract, usd(50_000), today + Duration::days(10)).unwrap(),
+        ];
+        for settlement in &settlements {
+            ledger.post_settlement(settlement).unwrap();
+        }
+        let first = residuals.schedule_through(&contract, today + Duration::days(3)).unwrap();
+        let custodians = first.iter().find(|o| o.beneficiary == entity("I-C001")).unwrap().obligation_id.clone();
+        ledger.post_residual_payout(&mut residuals, &custodians, &recipient, today + Duration::days(4)).unwrap();
+
+        Books { contract, settlements, residuals, ledger }
+    }
+
+    #[test]
+    fn totals_are_taken_over_the_period_and_balances_as_of_its_ends() {
+        let today = OffsetDateTime::now_utc().date();
+        let books = books(today);
+        let received = books.settlements[0].amount_for(&entity("I-C001"));
+
+        let custodian = books.statement("I-C001", today, today + Duration::days(5));
+        assert_eq!(custodian.contracts.len(), 1);
+        assert_eq!(custodian.contracts[0].roles, vec![PartyType::DataCustodian]);
+        assert_eq!(custodian.settlements.len(), 1);
+        assert_eq!(custodian.residuals.len(), 1);
+        assert_eq!(custodian.residuals[0].paid_on, Some(today + Duration::days(4)));
+        assert_eq!(custodian.activity.len(), 2);
+        assert_eq!(custodian.totals.settlements_paid, usd(0));
+        assert_eq!(custodian.totals.settlements_received, received);
+        assert_eq!(custodian.totals.residuals_accrued, usd(300));
+        assert_eq!(custodian.totals.opening_balance, usd(0));
+        assert_eq!(custodian.net_balance(), &usd(received.amount_minor + 300));
+
+        // 5% of the 1000.00 settled on day 1; the 500.00 on day 10 falls in the next period.
+        let recipient = books.statement("I-R001", today, today + Duration::days(5));
+        assert_eq!(recipient.totals.settlements_paid, usd(100_000));
+        assert_eq!(recipient.totals.fees_paid, usd(5_000));
+        assert_eq!(recipient.totals.closing_balance, usd(200_000 - 100_000 - 300));
+
+        let next = books.statement("I-R001", today + Duration::days(6), today + Duration::days(12));
+        assert_eq!(next.totals.opening_balance, recipient.totals.closing_balance);
+        assert_eq!(next.totals.settlements_paid, usd(50_000));
+        assert_eq!(next.totals.closing_balance, usd(200_000 - 150_000 - 300));
+
+        let hbank = books.statement("C-HB001", today, today + Duration::days(12));
+        assert_eq!(hbank.totals.fees_earned, usd(7_500));
+    }
+
+    #[test]
+    fn period_ending_before_it_starts_is_rejected() {
+        let today = OffsetDateTime::now_utc().date();
+        let books = books(today);
+        let records = FinancialRecords { settlements: &books.settlements, residuals: &books.residuals, ledger: &books.ledger };
+        let err = Statement::generate(&entity("I-C001"), today, today - Duration::days(1), &Currency::usd(), [&books.contract], records).unwrap_err();
+        assert!(matches!(err, StatementError::EmptyPeriod { .. }));
+    }
+
+    #[test]
+    fn csv_has_the_header_then_one_row_per_item_and_total() {
+        let today = OffsetDateTime::now_utc().date();
+        let to = today + Duration::days(5);
+        let statement = books(today).statement("I-R001", today, to);
+        let csv = statement.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines[0], CSV_HEADER);
+        let items = statement.contracts.len() + statement.settlements.len() + statement.residuals.len() + statement.activity.len();
+        assert_eq!(lines.len(), 1 + items + 7);
+        assert_eq!(lines[1], format!("contract,{},C-DS-001,DirectSale,\"Revision 1 as DataRecipient, in force until terminated\",,USD", today));
+        assert_eq!(lines[2], format!("settlement,{},C-DS-001,Paid,\"Gross USD 1000.00: paid USD 1000.00, received USD 0.00\",-1000.00,USD", today + Duration::days(1)));
+        assert_eq!(lines[lines.len() - 2], format!("total,{},I-R001,opening_balance,,0.00,USD", today - Duration::days(1)));
+        assert_eq!(lines[lines.len() - 1], format!("total,{},I-R001,closing_balance,,997.00,USD", to));
+        assert_eq!(csv_field("say \"hi\", twice"), "\"say \"\"hi\"\", twice\"");
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let today = OffsetDateTime::now_utc().date();
+        let statement = books(today).statement("I-C001", today, today + Duration::days(31));
+        let json = statement.to_json().unwrap();
+        assert_eq!(serde_json::from_str::<Statement>(&json).unwrap(), statement);
+    }
+}
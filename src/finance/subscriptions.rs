@@ -0,0 +1,381 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::{Date, Duration};
+
+use crate::contracts::structs_enums::*;
+use crate::contracts::lifecycle::{ContractStatus, LifecycleError};
+use crate::contracts::duration::consecutive_periods;
+use crate::contracts::health_data_contract::HealthDataContract;
+use crate::finance::settlement::payers_and_payees;
+use crate::finance::money::{Money, MoneyError};
+
+/*
+Metering and billing of SubscriptionAgreements under the plan in terms.subscription.
+
+Billing periods run back to back from the day the contract takes effect, each one plan.period long; the last period
+ends with the contract and is billed in full even if cut short. When a period ends, a BillingEvent charges the
+subscriber the plan price plus overage for the job runs and data refreshes used beyond the plan's allowance.
+The bill is issued the day after the period ends and is due plan.payment_terms_days later. A period in which the
+contract was never in force, e.g. because it stayed suspended throughout, is not billed.
+
+run_billing_cycle() is meant to be called daily (or at any interval): it bills every period that has ended and then
+suspends the contract while a bill is overdue, reinstating it once every overdue bill is paid. Usage can only be
+recorded while the contract is in force, so a suspended subscription cannot run jobs or refresh data.
+Contracts in a cohort are billed and suspended through HBankInterface, which also meters every job submitted under one.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum UsageKind {
+    JobRun,
+    DataRefresh,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub contract_id: String,
+    pub kind: UsageKind,
+    pub used_on: Date,
+    // e.g. the job_id of a job run.
+    pub reference: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BillingStatus {
+    Due,
+    Paid { paid_on: Date },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BillingEvent {
+    pub event_id: String,
+    pub contract_id: String,
+    pub subscriber: EntityId,
+    pub tier: String,
+    pub period_start: Date,
+    pub period_end: Date,
+    pub job_runs: u32,
+    pub data_refreshes: u32,
+    pub base: Money,
+    pub overage: Money,
+    pub total: Money,
+    pub issued_on: Date,
+    pub due_on: Date,
+    pub status: BillingStatus,
+}
+
+impl BillingEvent {
+    pub fn is_overdue(&self, on: Date) -> bool {
+        self.status == BillingStatus::Due && self.due_on < on
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AccessChange {
+    Unchanged,
+    Suspended { overdue_event_id: String },
+    Reinstated,
+}
+
+#[derive(Debug)]
+pub enum SubscriptionError {
+    NotSubscription(AgreementKind),
+    NoPlan(String),
+    NotExecuted(String),
+    NotInForce { contract_id: String, on: Date },
+    AccessSuspended { contract_id: String, overdue_event_id: String },
+    UnknownEvent(String),
+    AlreadyPaid { event_id: String, paid_on: Date },
+    DueDateOutOfRange { contract_id: String, period_end: Date },
+    Money(MoneyError),
+    Lifecycle(LifecycleError),
+}
+
+impl fmt::Display for SubscriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscriptionError::NotSubscription(kind) => write!(f, "Subscription Error: a {} is not a subscription", kind),
+            SubscriptionError::NoPlan(contract_id) => write!(f, "Subscription Error: contract {} has no subscription plan", contract_id),
+            SubscriptionError::NotExecuted(contract_id) => write!(f, "Subscription Error: contract {} has not taken effect", contract_id),
+            SubscriptionError::NotInForce { contract_id, on } => write!(f, "Subscription Error: contract {} is not in force on {}", contract_id, on),
+            SubscriptionError::AccessSuspended { contract_id, overdue_event_id } => {
+                write!(f, "Subscription Error: access under contract {} is suspended until bill {} is paid", contract_id, overdue_event_id)
+            }
+            SubscriptionError::UnknownEvent(event_id) => write!(f, "Subscription Error: bill {} not found", event_id),
+            SubscriptionError::AlreadyPaid { event_id, paid_on } => write!(f, "Subscription Error: bill {} was already paid on {}", event_id, paid_on),
+            SubscriptionError::DueDateOutOfRange { contract_id, period_end } => {
+                write!(f, "Subscription Error: the bill of contract {} for the period ending {} would fall due beyond the last representable date", contract_id, period_end)
+            }
+            SubscriptionError::Money(e) => write!(f, "Subscription Error: {}", e),
+            SubscriptionError::Lifecycle(e) => write!(f, "Subscription Error: {}", e),
+        }
+    }
+}
+
+impl From<MoneyError> for SubscriptionError {
+    fn from(e: MoneyError) -> Self {
+        SubscriptionError::Money(e)
+    }
+}
+
+impl From<LifecycleError> for SubscriptionError {
+    fn from(e: LifecycleError) -> Self {
+        SubscriptionError::Lifecycle(e)
+    }
+}
+
+fn plan_of(contract: &HealthDataContract) -> Result<&SubscriptionPlan, SubscriptionError> {
+    let kind = contract.get_agreement_type().kind();
+    if kind != AgreementKind::SubscriptionAgreement {
+        return Err(SubscriptionError::NotSubscription(kind));
+    }
+    contract.get_terms().subscription.as_ref().ok_or_else(|| SubscriptionError::NoPlan(contract.get_contract_id().to_string()))
+}
+
+// The charge for the units used beyond those included in the plan.
+fn overage(used: u32, included: u32, per_unit: &Money) -> Result<Money, MoneyError> {
+    let amount_minor = (used.saturating_sub(included) as i64).checked_mul(per_unit.amount_minor).ok_or(MoneyError::Overflow)?;
+    Ok(Money::new(amount_minor, per_unit.currency.clone()))
+}
+
+// The billing periods (first and last day) starting on first_day that have ended by through, cut off at last_day.
+pub fn billing_periods(plan: &SubscriptionPlan, first_day: Date, last_day: Option<Date>, through: Date) -> Vec<(Date, Date)> {
+    consecutive_periods(plan.period, first_day, last_day, through)
+}
+
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    usage: Vec<UsageRecord>,
+    events: Vec<BillingEvent>,
+    // Contracts this registry suspended for non-payment, which it may reinstate.
+    suspended_for_payment: Vec<String>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Subscriptions {
+            usage: Vec::new(),
+            events: Vec::new(),
+            suspended_for_payment: Vec::new(),
+        }
+    }
+
+    // Whether the subscriber may use the subscription on the given day: the contract must be Active and in force.
+    pub fn check_access(&self, contract: &HealthDataContract, on: Date) -> Result<(), SubscriptionError> {
+        plan_of(contract)?;
+        let contract_id = contract.get_contract_id();
+        if !contract.is_active() || !contract.is_in_force(on) {
+            if let Some(overdue) = self.unpaid_reason_for_suspension(contract_id, on) {
+                return Err(SubscriptionError::AccessSuspended { contract_id: contract_id.to_string(), overdue_event_id: overdue.event_id.clone() });
+            }
+            return Err(SubscriptionError::NotInForce { contract_id: contract_id.to_string(), on });
+        }
+        Ok(())
+    }
+
+    // Meters a job run or data refresh. Only allowed while the contract is in force.
+    pub fn record_usage(&mut self, contract: &HealthDataContract, kind: UsageKind, on: Date, reference: Option<String>) -> Result<&UsageRecord, SubscriptionError> {
+        self.check_access(contract, on)?;
+        self.usage.push(UsageRecord { contract_id: contract.get_contract_id().to_string(), kind, used_on: on, reference });
+        Ok(self.usage.last().expect("usage was just recorded"))
+    }
+
+    pub fn usage_between(&self, contract_id: &str, kind: UsageKind, from: Date, to: Date) -> u32 {
+        self.usage.iter()
+            .filter(|u| u.contract_id == contract_id && u.kind == kind && u.used_on >= from && u.used_on <= to)
+            .count() as u32
+    }
+
+    // Creates the bill for every billing period that has ended by through and has not been billed yet.
+    pub fn bill_through(&mut self, contract: &HealthDataContract, through: Date) -> Result<Vec<BillingEvent>, SubscriptionError> {
+        let plan = plan_of(contract)?;
+        let contract_id = contract.get_contract_id();
+        let first_day = contract.effective_from().ok_or_else(|| SubscriptionError::NotExecuted(contract_id.to_string()))?;
+        let (payers, _) = payers_and_payees(contract.get_agreement_type()).ok_or(SubscriptionError::NotSubscription(AgreementKind::SubscriptionAgreement))?;
+        let subscriber = payers[0].entity_id().clone();
+
+        let mut created = Vec::new();
+        for (period_start, period_end) in billing_periods(plan, first_day, contract.last_day_in_force(), through) {
+            if self.events.iter().any(|e| e.contract_id == contract_id && e.period_start == period_start) {
+                continue;
+            }
+            if !contract.was_in_force_between(period_start, period_end) {
+                continue;
+            }
+            let job_runs = self.usage_between(contract_id, UsageKind::JobRun, period_start, period_end);
+            let data_refreshes = self.usage_between(contract_id, UsageKind::DataRefresh, period_start, period_end);
+            let overage = overage(job_runs, plan.included_job_runs, &plan.overage_per_job_run)?
+                .checked_add(&overage(data_refreshes, plan.included_data_refreshes, &plan.overage_per_data_refresh)?)?;
+            let total = plan.price.checked_add(&overage)?;
+            let out_of_range = || SubscriptionError::DueDateOutOfRange { contract_id: contract_id.to_string(), period_end };
+            let issued_on = period_end.next_day().ok_or_else(out_of_range)?;
+            let due_on = issued_on.checked_add(Duration::days(plan.payment_terms_days as i64)).ok_or_else(out_of_range)?;
+
+            let event = BillingEvent {
+                event_id: format!("BILL-{:06}", self.events.len() + 1),
+                contract_id: contract_id.to_string(),
+                subscriber: subscriber.clone(),
+                tier: plan.tier.clone(),
+                period_start,
+                period_end,
+                job_runs,
+                data_refreshes,
+                base: plan.price.clone(),
+                total,
+                overage,
+                issued_on,
+                due_on,
+                status: BillingStatus::Due,
+            };
+            self.events.push(event.clone());
+            created.push(event);
+        }
+        Ok(created)
+    }
+
+    pub fn mark_paid(&mut self, event_id: &str, paid_on: Date) -> Result<&BillingEvent, SubscriptionError> {
+        let event = self.events.iter_mut().find(|e| e.event_id == event_id)
+            .ok_or_else(|| SubscriptionError::UnknownEvent(event_id.to_string()))?;
+        if let BillingStatus::Paid { paid_on } = event.status {
+            return Err(SubscriptionError::AlreadyPaid { event_id: event_id.to_string(), paid_on });
+        }
+        event.status = BillingStatus::Paid { paid_on };
+        Ok(event)
+    }
+
+    /*
+    Suspends an Active contract with an overdue bill, and reinstates a contract this registry suspended once none of
+    its bills are overdue. Reinstatement only checks that payment was the reason for the suspension; contracts
+    suspended for other reasons are left alone.
+    */
+    pub fn enforce_payment(&mut self, contract: &mut HealthDataContract, on: Date) -> Result<AccessChange, SubscriptionError> {
+        plan_of(contract)?;
+        let contract_id = contract.get_contract_id().to_string();
+        // At the start of the day, unless the status already changed later that day.
+        let at = on.midnight().assume_utc();
+        let at = contract.last_transition_at().map_or(at, |last| last.max(at));
+        let overdue_event_id = self.overdue_for(&contract_id, on).map(|e| e.event_id.clone());
+        let suspended_here = self.suspended_for_payment.contains(&contract_id);
+
+        match (overdue_event_id, contract.get_status()) {
+            (Some(overdue_event_id), ContractStatus::Active) => {
+                contract.transition_at(ContractStatus::Suspended, at)?;
+                self.suspended_for_payment.push(contract_id);
+                Ok(AccessChange::Suspended { overdue_event_id })
+            }
+            (None, ContractStatus::Suspended) if suspended_here => {
+                contract.reinstate_at(at)?;
+                self.suspended_for_payment.retain(|id| *id != contract_id);
+                Ok(AccessChange::Reinstated)
+            }
+            _ => Ok(AccessChange::Unchanged),
+        }
+    }
+
+    // Bills every period that has ended before on, then suspends or reinstates access depending on overdue bills.
+    pub fn run_billing_cycle(&mut self, contract: &mut HealthDataContract, on: Date) -> Result<(Vec<BillingEvent>, AccessChange), SubscriptionError> {
+        let billed = self.bill_through(contract, on - Duration::days(1))?;
+        let access = self.enforce_payment(contract, on)?;
+        Ok((billed, access))
+    }
+
+    fn overdue_for(&self, contract_id: &str, on: Date) -> Option<&BillingEvent> {
+        self.events.iter().find(|e| e.contract_id == contract_id && e.is_overdue(on))
+    }
+
+    // The bill keeping a contract suspended: one overdue on the day, or any unpaid one if this registry suspended it.
+    fn unpaid_reason_for_suspension(&self, contract_id: &str, on: Date) -> Option<&BillingEvent> {
+        self.overdue_for(contract_id, on).or_else(|| {
+            let suspended_here = self.suspended_for_payment.iter().any(|id| id == contract_id);
+            self.events.iter().find(|e| suspended_here && e.contract_id == contract_id && e.status == BillingStatus::Due)
+        })
+    }
+
+    pub fn get(&self, event_id: &str) -> Option<&BillingEvent> {
+        self.events.iter().find(|e| e.event_id == event_id)
+    }
+
+    pub fn events(&self) -> &[BillingEvent] {
+        &self.events
+    }
+
+    pub fn for_contract<'a>(&'a self, contract_id: &'a str) -> impl Iterator<Item = &'a BillingEvent> {
+        self.events.iter().filter(move |e| e.contract_id == contract_id)
+    }
+
+    pub fn overdue(&self, on: Date) -> Vec<&BillingEvent> {
+        self.events.iter().filter(|e| e.is_overdue(on)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::contracts::*;
+    use crate::finance::money::{Currency, Rate};
+    use crate::test_support::sale;
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    fn daily_plan(overage_per_job_run: Money, payment_terms_days: u32) -> SubscriptionPlan {
+        SubscriptionPlan {
+            tier: "daily".to_string(),
+            price: usd(1_000),
+            period: TermLength::Days(1),
+            included_job_runs: 1,
+            included_data_refreshes: 0,
+            overage_per_job_run,
+            overage_per_data_refresh: usd(0),
+            payment_terms_days,
+        }
+    }
+
+    fn executed_subscription(plan: SubscriptionPlan) -> HealthDataContract {
+        sale()
+            .kind(AgreementKind::SubscriptionAgreement)
+            .contract_id("C-SUB-001")
+            .terms(Terms { subscription: Some(plan), ..Terms::default() })
+            .generator_rate(GeneratorRateSpecification::KnowledgeRate(Rate::percent(5).unwrap()))
+            .executed()
+    }
+
+    #[test]
+    fn period_is_billed_with_overage_beyond_the_allowance() {
+        let contract = executed_subscription(daily_plan(usd(250), 10));
+        let today = OffsetDateTime::now_utc().date();
+        let mut subscriptions = Subscriptions::new();
+        for _ in 0..3 {
+            subscriptions.record_usage(&contract, UsageKind::JobRun, today, None).unwrap();
+        }
+
+        let billed = subscriptions.bill_through(&contract, today).unwrap();
+        assert_eq!(billed.len(), 1);
+        assert_eq!((billed[0].job_runs, billed[0].overage.clone(), billed[0].total.clone()), (3, usd(500), usd(1_500)));
+        assert_eq!(billed[0].due_on, today + Duration::days(11));
+        assert!(subscriptions.bill_through(&contract, today).unwrap().is_empty());
+    }
+
+    #[test]
+    fn overflowing_overage_is_an_error() {
+        let contract = executed_subscription(daily_plan(usd(i64::MAX / 2), 0));
+        let today = OffsetDateTime::now_utc().date();
+        let mut subscriptions = Subscriptions::new();
+        for _ in 0..4 {
+            subscriptions.record_usage(&contract, UsageKind::JobRun, today, None).unwrap();
+        }
+
+        assert!(matches!(subscriptions.bill_through(&contract, today), Err(SubscriptionError::Money(MoneyError::Overflow))));
+        assert!(subscriptions.events().is_empty());
+    }
+
+    #[test]
+    fn due_date_past_the_last_representable_date_is_an_error() {
+        let contract = executed_subscription(daily_plan(usd(0), u32::MAX));
+        let today = OffsetDateTime::now_utc().date();
+        let err = Subscriptions::new().bill_through(&contract, today).unwrap_err();
+        assert!(matches!(err, SubscriptionError::DueDateOutOfRange { period_end, .. } if period_end == today));
+    }
+}
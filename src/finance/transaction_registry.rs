@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use time::Date;
+
+use crate::contracts::structs_enums::AgreementKind;
+use crate::contracts::health_data_contract::HealthDataContract;
+
+/*
+Counts how often each data bundle has been transacted, so that settlement can tell a first transaction
+(KnowledgeRate) from a later one (UsageRate).
+
+A transaction is one contract transferring or granting access to the bundle; repeated payments under the same
+contract (e.g. subscription instalments) belong to the same transaction. Only the agreement types in
+COUNTED_AGREEMENTS are counted. A contract's bundle is its cohort if it has one, and otherwise the contract itself.
+*/
+pub const COUNTED_AGREEMENTS: [AgreementKind; 4] = [
+    AgreementKind::DirectSale,
+    AgreementKind::LicensingAgreement,
+    AgreementKind::AccessAgreement,
+    AgreementKind::SubscriptionAgreement,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DataBundleId {
+    Cohort(String),
+    Bundle(String),
+}
+
+impl DataBundleId {
+    pub fn for_contract(contract: &HealthDataContract) -> Self {
+        match contract.get_cohort_id() {
+            Some(cohort_id) => DataBundleId::Cohort(cohort_id.to_string()),
+            None => DataBundleId::Bundle(contract.get_contract_id().to_string()),
+        }
+    }
+}
+
+impl fmt::Display for DataBundleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataBundleId::Cohort(id) => write!(f, "cohort {}", id),
+            DataBundleId::Bundle(id) => write!(f, "bundle {}", id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub bundle: DataBundleId,
+    pub contract_id: String,
+    pub agreement_kind: AgreementKind,
+    pub transacted_on: Date,
+    // 1 for the first transaction of the bundle.
+    pub sequence: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct TransactionRegistry {
+    transactions: HashMap<DataBundleId, Vec<TransactionRecord>>,
+}
+
+impl TransactionRegistry {
+    pub fn new() -> Self {
+        TransactionRegistry {
+            transactions: HashMap::new(),
+        }
+    }
+
+    pub fn counts_towards_usage(kind: AgreementKind) -> bool {
+        COUNTED_AGREEMENTS.contains(&kind)
+    }
+
+    pub fn transaction_count(&self, bundle: &DataBundleId) -> u64 {
+        self.transactions.get(bundle).map_or(0, |records| records.len() as u64)
+    }
+
+    pub fn history(&self, bundle: &DataBundleId) -> &[TransactionRecord] {
+        self.transactions.get(bundle).map_or(&[], |records| records.as_slice())
+    }
+
+    pub fn find(&self, bundle: &DataBundleId, contract_id: &str) -> Option<&TransactionRecord> {
+        self.history(bundle).iter().find(|record| record.contract_id == contract_id)
+    }
+
+    /*
+    How many other transactions of the bundle came before the contract's own.
+    For a contract that has not transacted the bundle yet this is every transaction recorded so far.
+    */
+    pub fn prior_transactions(&self, bundle: &DataBundleId, contract_id: &str) -> u64 {
+        match self.find(bundle, contract_id) {
+            Some(record) => record.sequence - 1,
+            None => self.transaction_count(bundle),
+        }
+    }
+
+    /*
+    Records the contract as a transaction of the bundle and returns its record.
+    Recording the same contract again returns the existing record; agreement types that are not counted return None.
+    */
+    pub fn record(&mut self, bundle: &DataBundleId, contract: &HealthDataContract, on: Date) -> Option<TransactionRecord> {
+        let agreement_kind = contract.get_agreement_type().kind();
+        if !Self::counts_towards_usage(agreement_kind) {
+            return None;
+        }
+        if let Some(existing) = self.find(bundle, contract.get_contract_id()) {
+            return Some(existing.clone());
+        }
+        let records = self.transactions.entry(bundle.clone()).or_default();
+        let record = TransactionRecord {
+            bundle: bundle.clone(),
+            contract_id: contract.get_contract_id().to_string(),
+            agreement_kind,
+            transacted_on: on,
+            sequence: records.len() as u64 + 1,
+        };
+        records.push(record.clone());
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Month, OffsetDateTime};
+
+    use super::*;
+    use crate::contracts::*;
+    use crate::finance::money::{Currency, Money, Rate};
+    use crate::finance::settlement::SettlementEngine;
+    use crate::test_support::sale;
+
+    fn tiered() -> GeneratorRateSpecification {
+        GeneratorRateSpecification::Tiered { knowledge_rate: Rate::percent(5).unwrap(), usage_rate: Rate::percent(2).unwrap() }
+    }
+
+    // An executed DirectSale of custodian I-C001's data, made by generator C-G001, to the given recipient.
+    fn executed_sale(contract_id: &str, recipient_id: &str) -> HealthDataContract {
+        sale().contract_id(contract_id).recipient(recipient_id).generator_rate(tiered()).executed()
+    }
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, Currency::usd())
+    }
+
+    #[test]
+    fn tiered_rates_resolve_from_the_bundles_transaction_count() {
+        let bundle = DataBundleId::Cohort("CH-001".to_string());
+        let first = executed_sale("C-DS-001", "I-R001");
+        let second = executed_sale("C-DS-002", "I-R002");
+        let today = OffsetDateTime::now_utc().date();
+        let mut engine = SettlementEngine::new();
+
+        let settlement = engine.settle_bundle(&first, &bundle, usd(10_000), today).unwrap();
+        assert_eq!(settlement.prior_transactions, 0);
+        assert_eq!(settlement.generator_rate, Some(GeneratorRateSpecification::KnowledgeRate(Rate::percent(5).unwrap())));
+
+        let settlement = engine.settle_bundle(&second, &bundle, usd(10_000), today).unwrap();
+        assert_eq!(settlement.prior_transactions, 1);
+        assert_eq!(settlement.generator_rate, Some(GeneratorRateSpecification::UsageRate(Rate::percent(2).unwrap())));
+        assert_eq!(engine.transactions().find(&bundle, "C-DS-002").unwrap().sequence, 2);
+    }
+
+    #[test]
+    fn repeat_settlements_under_one_contract_are_one_transaction() {
+        let bundle = DataBundleId::Cohort("CH-001".to_string());
+        let contract = executed_sale("C-DS-001", "I-R001");
+        let today = OffsetDateTime::now_utc().date();
+        let mut engine = SettlementEngine::new();
+
+        for _ in 0..3 {
+            let settlement = engine.settle_bundle(&contract, &bundle, usd(10_000), today).unwrap();
+            assert_eq!(settlement.prior_transactions, 0);
+            assert_eq!(settlement.generator_rate, Some(GeneratorRateSpecification::KnowledgeRate(Rate::percent(5).unwrap())));
+        }
+        assert_eq!(engine.transactions().transaction_count(&bundle), 1);
+        assert_eq!(engine.transactions().prior_transactions(&bundle, "C-DS-001"), 0);
+        assert_eq!(engine.transactions().prior_transactions(&bundle, "C-DS-002"), 1);
+    }
+
+    #[test]
+    fn recording_a_contract_again_keeps_its_first_record() {
+        let contract = executed_sale("C-DS-001", "I-R001");
+        let bundle = DataBundleId::for_contract(&contract);
+        assert_eq!(bundle, DataBundleId::Bundle("C-DS-001".to_string()));
+        let mut registry = TransactionRegistry::new();
+        let first_day = Date::from_calendar_date(2030, Month::January, 1).unwrap();
+
+        let record = registry.record(&bundle, &contract, first_day).unwrap();
+        let again = registry.record(&bundle, &contract, Date::from_calendar_date(2030, Month::June, 1).unwrap()).unwrap();
+        assert_eq!(again, record);
+        assert_eq!(again.transacted_on, first_day);
+        assert_eq!(registry.history(&bundle).len(), 1);
+        assert!(!TransactionRegistry::counts_towards_usage(AgreementKind::PhilanthropicAgreement));
+    }
+}
@@ -2,6 +2,10 @@ pub mod api;
 pub mod contracts;
 pub mod persons;
 pub mod data_management;
+pub mod finance;
+
+#[cfg(test)]
+mod test_support;
 
 pub use api::HBankInterface;
 
@@ -4,6 +4,8 @@ Declare the submodules within the module: persons.
 
 pub mod individual;
 pub mod corporation;
+pub mod consent;
+pub mod pseudonyms;
 
 /*
 You can re-export an entire submodule in Rust using pub use. 
@@ -12,3 +14,5 @@ of your crate to access all items within that submodule through a single import.
 */
 pub use individual::*;  // Re-export all public items from health_data_contract
 pub use corporation::*;  // Re-export all public items from structs_enums
+pub use consent::*;
+pub use pseudonyms::*;
@@ -0,0 +1,108 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+use crate::contracts::structs_enums::{DataCategory, DataUsePurpose, PartyType};
+
+/*
+What an Individual has agreed their data may be used for: which categories of data may be shared, for which purposes,
+and with which types of receiving party. Consent is opt-in, so the default record allows nothing.
+
+A contract can only include an Individual as a DataOriginator if their consent covers the contract's purpose, every
+data category it covers and the type of every party receiving the data.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Consent {
+    pub categories: Vec<DataCategory>,
+    pub purposes: Vec<DataUsePurpose>,
+    pub recipient_types: Vec<PartyType>,
+}
+
+// Something a use of data needs that the consent does not give.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsentGap {
+    Category(DataCategory),
+    Purpose(DataUsePurpose),
+    RecipientType(PartyType),
+}
+
+impl fmt::Display for ConsentGap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsentGap::Category(category) => write!(f, "sharing {}", category),
+            ConsentGap::Purpose(purpose) => write!(f, "{}", purpose),
+            ConsentGap::RecipientType(party_type) => write!(f, "sharing with a {:?}", party_type),
+        }
+    }
+}
+
+impl Consent {
+    pub fn new(categories: Vec<DataCategory>, purposes: Vec<DataUsePurpose>, recipient_types: Vec<PartyType>) -> Self {
+        Consent {
+            categories,
+            purposes,
+            recipient_types,
+        }
+    }
+
+    pub fn allows_category(&self, category: DataCategory) -> bool {
+        self.categories.contains(&category)
+    }
+
+    pub fn allows_purpose(&self, purpose: DataUsePurpose) -> bool {
+        self.purposes.contains(&purpose)
+    }
+
+    pub fn allows_recipient_type(&self, party_type: PartyType) -> bool {
+        self.recipient_types.contains(&party_type)
+    }
+
+    // Everything the given use of data needs that this consent does not allow. Empty if the consent covers it.
+    pub fn gaps(&self, purpose: DataUsePurpose, categories: &[DataCategory], recipient_types: &[PartyType]) -> Vec<ConsentGap> {
+        let mut gaps = Vec::new();
+        if !self.allows_purpose(purpose) {
+            gaps.push(ConsentGap::Purpose(purpose));
+        }
+        gaps.extend(categories.iter().filter(|c| !self.allows_category(**c)).map(|c| ConsentGap::Category(*c)));
+        gaps.extend(recipient_types.iter().filter(|t| !self.allows_recipient_type(**t)).map(|t| ConsentGap::RecipientType(*t)));
+        gaps
+    }
+
+    pub fn covers(&self, purpose: DataUsePurpose, categories: &[DataCategory], recipient_types: &[PartyType]) -> bool {
+        self.gaps(purpose, categories, recipient_types).is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_consent_allows_nothing() {
+        let gaps = Consent::default().gaps(DataUsePurpose::Research, &[DataCategory::Labs], &[PartyType::DataRecipient]);
+        assert_eq!(gaps, vec![
+            ConsentGap::Purpose(DataUsePurpose::Research),
+            ConsentGap::Category(DataCategory::Labs),
+            ConsentGap::RecipientType(PartyType::DataRecipient),
+        ]);
+    }
+
+    #[test]
+    fn gaps_list_only_what_is_missing() {
+        let consent = Consent::new(
+            vec![DataCategory::Labs, DataCategory::Notes],
+            vec![DataUsePurpose::Research],
+            vec![PartyType::DataRecipient],
+        );
+
+        assert!(consent.covers(DataUsePurpose::Research, &[DataCategory::Labs], &[PartyType::DataRecipient]));
+        assert!(consent.covers(DataUsePurpose::Research, &[], &[]));
+        assert_eq!(
+            consent.gaps(DataUsePurpose::Commercial, &[DataCategory::Labs, DataCategory::Genomic], &[PartyType::DataRecipient, PartyType::Advertiser]),
+            vec![
+                ConsentGap::Purpose(DataUsePurpose::Commercial),
+                ConsentGap::Category(DataCategory::Genomic),
+                ConsentGap::RecipientType(PartyType::Advertiser),
+            ],
+        );
+    }
+}
@@ -1,8 +1,3 @@
-use std::collections::HashSet;
-use regex::Regex;
-
-use time::Date;
-
 use crate::contracts::structs_enums::EntityId;
 
 /*
@@ -1,8 +1,8 @@
-use std::collections::HashSet;
 use regex::Regex;
 
 use time::Date;
-use crate::contracts::structs_enums::EntityId;
+use crate::contracts::structs_enums::{DataUsePurpose, EntityId};
+use crate::persons::consent::Consent;
 
 /*
 An Individual is linked uniquely to their person_id. 
@@ -17,6 +17,8 @@ pub struct Individual {
     pub hla_profile: Option<String>,
     pub blood_type: Option<String>,
     pub date_of_birth: Date,
+    // What the Individual allows their data to be used for. Nothing until they give consent.
+    pub consent: Consent,
 }
 
 
@@ -28,6 +30,7 @@ impl Individual {
             hla_profile: None,
             blood_type: None,
             date_of_birth,
+            consent: Consent::default(),
         }
     }
 
@@ -50,6 +53,30 @@ impl Individual {
         self.blood_type = Some(blood_type.to_string());
     }
 
+    // Opting in to advertising is consent to the Advertising purpose; the consent record is the only source of truth.
+    pub fn opt_in_to_advertising(&mut self) {
+        if !self.consent.allows_purpose(DataUsePurpose::Advertising) {
+            self.consent.purposes.push(DataUsePurpose::Advertising);
+        }
+    }
+
+    pub fn opt_out_of_advertising(&mut self) {
+        self.consent.purposes.retain(|purpose| *purpose != DataUsePurpose::Advertising);
+    }
+
+    pub fn has_opted_in_to_advertising(&self) -> bool {
+        self.consent.allows_purpose(DataUsePurpose::Advertising)
+    }
+
+    // Replaces the Individual's consent record.
+    pub fn set_consent(&mut self, consent: Consent) {
+        self.consent = consent;
+    }
+
+    pub fn get_consent(&self) -> &Consent {
+        &self.consent
+    }
+
     pub fn create_sorted_hla_profile(alleles: &[&str]) -> String {
         let mut sorted_alleles: Vec<String> = alleles.iter().map(|&s| s.to_string()).collect();
         sorted_alleles.sort(); // Sort alphabetically
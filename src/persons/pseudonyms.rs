@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fmt;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::contracts::structs_enums::{EntityId, Party, PartyType};
+use crate::persons::individual::Individual;
+
+/*
+Contracts are public, so an Individual never appears in one under their person_id. Each Individual is instead given
+a pseudonymous entity_id by HBank, derived from (but never equal to) the person_id:
+
+    hash = SHA-256(HBank secret, person_id, time of issue, previous hash)
+    entity_id = "PSN-" + the first 16 bytes of hash in hex
+
+The first pseudonym of an Individual chains from an all-zero previous hash. HBank rotates pseudonyms from time to time;
+a rotated pseudonym is retired but keeps resolving, since contracts signed under it still name it. The secret keeps
+anyone who knows a person_id from recomputing its pseudonyms.
+
+Only HBank can resolve a pseudonym back to the Individual's person_id. HBank's entity_id is public, so it proves
+nothing; instead new() hands out a single ResolutionKey for the service, which HBank keeps to itself. resolve() and
+history() refuse every caller that cannot present that key.
+*/
+pub const PSEUDONYM_PREFIX: &str = "PSN-";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pseudonym {
+    pub entity_id: EntityId,
+    pub issued_at: OffsetDateTime,
+    pub retired_at: Option<OffsetDateTime>,
+    hash: [u8; 32],
+}
+
+impl Pseudonym {
+    pub fn is_current(&self) -> bool {
+        self.retired_at.is_none()
+    }
+}
+
+#[derive(Debug)]
+pub enum PseudonymError {
+    AlreadyIssued { person_id: EntityId, entity_id: EntityId },
+    NotIssued(EntityId),
+    RotationNotAfterIssue { entity_id: EntityId, issued_at: OffsetDateTime },
+    UnknownPseudonym(EntityId),
+    NotAuthorized,
+}
+
+impl fmt::Display for PseudonymError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PseudonymError::AlreadyIssued { person_id, entity_id } => {
+                write!(f, "Pseudonym Error: {} already has the pseudonym {}; rotate it instead", person_id.0, entity_id.0)
+            }
+            PseudonymError::NotIssued(person_id) => write!(f, "Pseudonym Error: no pseudonym has been issued to {}", person_id.0),
+            PseudonymError::RotationNotAfterIssue { entity_id, issued_at } => {
+                write!(f, "Pseudonym Error: pseudonym {} was issued at {} and can only be rotated after that", entity_id.0, issued_at)
+            }
+            PseudonymError::UnknownPseudonym(entity_id) => write!(f, "Pseudonym Error: {} is not a pseudonym issued by HBank", entity_id.0),
+            PseudonymError::NotAuthorized => write!(f, "Pseudonym Error: only the holder of this service's resolution key can resolve pseudonyms"),
+        }
+    }
+}
+
+/*
+The capability to resolve pseudonyms issued by one PseudonymService. It cannot be cloned or built outside this module,
+so only whoever received it from PseudonymService::new() holds it.
+*/
+pub struct ResolutionKey {
+    service_id: Uuid,
+}
+
+impl fmt::Debug for ResolutionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResolutionKey(<redacted>)")
+    }
+}
+
+pub struct PseudonymService {
+    h_bank: EntityId,
+    secret: [u8; 32],
+    service_id: Uuid,
+    // Every pseudonym issued to each person_id, oldest first. Only the last one can be current.
+    chains: HashMap<EntityId, Vec<Pseudonym>>,
+    owners: HashMap<EntityId, EntityId>,
+}
+
+// Written by hand so the secret never ends up in logs.
+impl fmt::Debug for PseudonymService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PseudonymService")
+            .field("h_bank", &self.h_bank)
+            .field("secret", &"<redacted>")
+            .field("pseudonyms", &self.owners.len())
+            .finish()
+    }
+}
+
+// Whether the entity_id has the form of an HBank pseudonym: the prefix followed by 16 bytes of hex.
+pub fn is_pseudonym_format(entity_id: &EntityId) -> bool {
+    entity_id.0.strip_prefix(PSEUDONYM_PREFIX)
+        .is_some_and(|hash| hash.len() == 32 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')))
+}
+
+impl PseudonymService {
+    // The service and the only key that can resolve its pseudonyms, for HBank to keep.
+    pub fn new(h_bank: EntityId, secret: [u8; 32]) -> (Self, ResolutionKey) {
+        let service_id = Uuid::new_v4();
+        let service = PseudonymService {
+            h_bank,
+            secret,
+            service_id,
+            chains: HashMap::new(),
+            owners: HashMap::new(),
+        };
+        (service, ResolutionKey { service_id })
+    }
+
+    fn authorize(&self, key: &ResolutionKey) -> Result<(), PseudonymError> {
+        if key.service_id != self.service_id {
+            return Err(PseudonymError::NotAuthorized);
+        }
+        Ok(())
+    }
+
+    // Issues the Individual's first pseudonym.
+    pub fn issue(&mut self, individual: &Individual, at: OffsetDateTime) -> Result<&Pseudonym, PseudonymError> {
+        let person_id = individual.get_person_id();
+        if let Some(current) = self.current(person_id) {
+            return Err(PseudonymError::AlreadyIssued { person_id: person_id.clone(), entity_id: current.entity_id.clone() });
+        }
+        Ok(self.chain(person_id, at, [0; 32]))
+    }
+
+    // Retires the Individual's current pseudonym and issues the next one in its chain.
+    pub fn rotate(&mut self, person_id: &EntityId, at: OffsetDateTime) -> Result<&Pseudonym, PseudonymError> {
+        let current = self.chains.get_mut(person_id)
+            .and_then(|chain| chain.last_mut())
+            .ok_or_else(|| PseudonymError::NotIssued(person_id.clone()))?;
+        if at <= current.issued_at {
+            return Err(PseudonymError::RotationNotAfterIssue { entity_id: current.entity_id.clone(), issued_at: current.issued_at });
+        }
+        current.retired_at = Some(at);
+        let previous_hash = current.hash;
+        Ok(self.chain(person_id, at, previous_hash))
+    }
+
+    fn chain(&mut self, person_id: &EntityId, at: OffsetDateTime, previous_hash: [u8; 32]) -> &Pseudonym {
+        let mut hasher = Sha256::new();
+        hasher.update(self.secret);
+        hasher.update(person_id.0.as_bytes());
+        hasher.update(at.unix_timestamp_nanos().to_be_bytes());
+        hasher.update(previous_hash);
+        let hash: [u8; 32] = hasher.finalize().into();
+
+        let entity_id = EntityId(format!("{}{}", PSEUDONYM_PREFIX, hex::encode(&hash[..16])));
+        self.owners.insert(entity_id.clone(), person_id.clone());
+        let chain = self.chains.entry(person_id.clone()).or_default();
+        chain.push(Pseudonym { entity_id, issued_at: at, retired_at: None, hash });
+        chain.last().expect("pseudonym was just issued")
+    }
+
+    // The pseudonym the Individual currently appears under in new contracts.
+    pub fn current(&self, person_id: &EntityId) -> Option<&Pseudonym> {
+        self.chains.get(person_id)?.last().filter(|p| p.is_current())
+    }
+
+    // A party for the Individual under their current pseudonym. The pseudonym also stands in for their name.
+    pub fn party(&self, person_id: &EntityId, party_type: PartyType) -> Result<Party, PseudonymError> {
+        let current = self.current(person_id).ok_or_else(|| PseudonymError::NotIssued(person_id.clone()))?;
+        Ok(Party::new(current.entity_id.0.clone(), current.entity_id.clone(), party_type))
+    }
+
+    // The person_id behind a current or retired pseudonym. Only HBank, holding the key, may ask.
+    pub fn resolve(&self, key: &ResolutionKey, entity_id: &EntityId) -> Result<&EntityId, PseudonymError> {
+        self.authorize(key)?;
+        self.owners.get(entity_id).ok_or_else(|| PseudonymError::UnknownPseudonym(entity_id.clone()))
+    }
+
+    // Every pseudonym issued to the Individual, oldest first. Only HBank, holding the key, may ask.
+    pub fn history(&self, key: &ResolutionKey, person_id: &EntityId) -> Result<&[Pseudonym], PseudonymError> {
+        self.authorize(key)?;
+        self.chains.get(person_id).map(Vec::as_slice).ok_or_else(|| PseudonymError::NotIssued(person_id.clone()))
+    }
+
+    // Whether the entity_id was issued by this service, without revealing who it belongs to.
+    pub fn is_pseudonym(&self, entity_id: &EntityId) -> bool {
+        self.owners.contains_key(entity_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Date, Duration, Month};
+
+    use super::*;
+
+    fn originator() -> Individual {
+        Individual::new("John Doe".to_string(), EntityId("I-O001".to_string()), Date::from_calendar_date(1980, Month::January, 1).unwrap())
+    }
+
+    fn hbank_service() -> (PseudonymService, ResolutionKey) {
+        PseudonymService::new(EntityId("C-HB001".to_string()), [7; 32])
+    }
+
+    #[test]
+    fn only_the_key_holder_can_resolve() {
+        let (mut service, key) = hbank_service();
+        let issued = service.issue(&originator(), OffsetDateTime::UNIX_EPOCH).unwrap().entity_id.clone();
+        assert_eq!(service.resolve(&key, &issued).unwrap(), originator().get_person_id());
+
+        // Anyone can build a service of their own, but its key does not open HBank's.
+        let (_, other_key) = PseudonymService::new(EntityId("C-HB001".to_string()), [7; 32]);
+        assert!(matches!(service.resolve(&other_key, &issued), Err(PseudonymError::NotAuthorized)));
+        assert!(matches!(service.history(&other_key, originator().get_person_id()), Err(PseudonymError::NotAuthorized)));
+    }
+
+    #[test]
+    fn rotated_pseudonyms_keep_resolving() {
+        let (mut service, key) = hbank_service();
+        let person_id = originator().get_person_id().clone();
+        let first = service.issue(&originator(), OffsetDateTime::UNIX_EPOCH).unwrap().entity_id.clone();
+        let second = service.rotate(&person_id, OffsetDateTime::UNIX_EPOCH + Duration::days(30)).unwrap().entity_id.clone();
+
+        assert_ne!(first, second);
+        assert!(is_pseudonym_format(&first) && is_pseudonym_format(&second));
+        assert_eq!(service.current(&person_id).unwrap().entity_id, second);
+        assert_eq!(service.resolve(&key, &first).unwrap(), &person_id);
+        let history = service.history(&key, &person_id).unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(history[0].retired_at.is_some() && history[1].is_current());
+    }
+
+    #[test]
+    fn pseudonyms_never_reveal_the_person_id() {
+        let (mut service, _) = hbank_service();
+        let issued = service.issue(&originator(), OffsetDateTime::UNIX_EPOCH).unwrap().entity_id.clone();
+        assert!(!issued.0.contains("I-O001"));
+        assert!(service.is_pseudonym(&issued));
+        assert!(matches!(service.issue(&originator(), OffsetDateTime::UNIX_EPOCH), Err(PseudonymError::AlreadyIssued { .. })));
+        assert!(!format!("{:?}", service).contains("7, 7"));
+    }
+}
@@ -0,0 +1,229 @@
+use time::{Date, Month};
+
+use crate::contracts::*;
+use crate::finance::money::Rate;
+use crate::persons::consent::Consent;
+use crate::persons::{Corporation, Individual};
+
+/*
+Parties, keys and contracts for the unit tests. Most modules test against an agreement between two agents, which
+SaleFixture builds; modules about another kind of agreement build it with the builder, and these helpers take care
+of the repetitive parts around it.
+*/
+
+pub(crate) fn party(party_type: PartyType, id: &str) -> Party {
+    Party::new(format!("{:?} {}", party_type, id), EntityId(id.to_string()), party_type)
+}
+
+pub(crate) fn adult(entity_id: &EntityId) -> Individual {
+    Individual::new(entity_id.0.clone(), entity_id.clone(), Date::from_calendar_date(1980, Month::January, 1).unwrap())
+}
+
+pub(crate) fn corporation(party: &Party) -> Corporation {
+    Corporation::new(party.as_party_info().name.clone(), party.entity_id().clone())
+}
+
+// One distinct demo key per party, in party order.
+pub(crate) fn signing_keys(parties: &[Party]) -> Vec<(EntityId, SigningKey)> {
+    parties.iter().enumerate()
+        .map(|(i, party)| (party.entity_id().clone(), SigningKey::from_bytes(&[i as u8 + 1; 32])))
+        .collect()
+}
+
+pub(crate) fn register_keys(mut builder: HealthDataContractBuilder, keys: &[(EntityId, SigningKey)]) -> HealthDataContractBuilder {
+    for (entity_id, signing_key) in keys {
+        builder = builder.party_key(entity_id.clone(), &signing_key.verifying_key());
+    }
+    builder
+}
+
+// Proposes the contract, has every party sign it and executes it.
+pub(crate) fn execute(contract: &mut HealthDataContract, keys: &[(EntityId, SigningKey)]) {
+    contract.propose().unwrap();
+    for (entity_id, signing_key) in keys {
+        contract.sign_as(entity_id, signing_key).unwrap();
+    }
+    contract.sign().unwrap();
+    contract.validate_and_execute_contract().unwrap();
+}
+
+/*
+A three-party agreement between agent A and agent B over data made by the generators, with HBank: by default an
+executable DirectSale C-DS-001 from custodian I-C001 to recipient I-R001 of data made by generator C-G001, under
+empty Terms, a 3% knowledge rate and SafeHarbor. Agents with an individual ("I-") id are adults, every other party
+a corporation. Tests change only the settings they are about.
+*/
+pub(crate) struct SaleFixture {
+    pub(crate) contract_id: String,
+    pub(crate) kind: AgreementKind,
+    pub(crate) agent_a: Party,
+    pub(crate) agent_b: Party,
+    pub(crate) generators: Vec<Party>,
+    pub(crate) hbank: Party,
+    originator_consent: Option<Consent>,
+    terms: Terms,
+    generator_rate: GeneratorRateSpecification,
+    residual_payments: Option<Residuals>,
+    cohort_id: Option<String>,
+}
+
+pub(crate) fn sale() -> SaleFixture {
+    SaleFixture {
+        contract_id: "C-DS-001".to_string(),
+        kind: AgreementKind::DirectSale,
+        agent_a: party(PartyType::DataCustodian, "I-C001"),
+        agent_b: party(PartyType::DataRecipient, "I-R001"),
+        generators: vec![party(PartyType::DataGenerator, "C-G001")],
+        hbank: party(PartyType::HBank, "C-HB001"),
+        originator_consent: None,
+        terms: Terms::default(),
+        generator_rate: GeneratorRateSpecification::KnowledgeRate(Rate::percent(3).unwrap()),
+        residual_payments: None,
+        cohort_id: None,
+    }
+}
+
+impl SaleFixture {
+    // One of the agreements between an agent A and an agent B: DirectSale, Purchase, Licensing, Access or Subscription.
+    pub(crate) fn kind(mut self, kind: AgreementKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub(crate) fn contract_id(mut self, contract_id: &str) -> Self {
+        self.contract_id = contract_id.to_string();
+        self
+    }
+
+    pub(crate) fn recipient(mut self, entity_id: &str) -> Self {
+        self.agent_b = party(PartyType::DataRecipient, entity_id);
+        self
+    }
+
+    // Generators C-G001, C-G002, ... up to the given count.
+    pub(crate) fn generator_count(mut self, count: usize) -> Self {
+        self.generators = (1..=count).map(|i| party(PartyType::DataGenerator, &format!("C-G{:03}", i))).collect();
+        self
+    }
+
+    // Agent A is a DataOriginator, individual I-O001 known by an HBank pseudonym, contributing their data only.
+    pub(crate) fn originator(mut self, pseudonym: &str, consent: Consent) -> Self {
+        self.agent_a = party(PartyType::DataOriginator, pseudonym);
+        self.originator_consent = Some(consent);
+        self
+    }
+
+    pub(crate) fn terms(mut self, terms: Terms) -> Self {
+        self.terms = terms;
+        self
+    }
+
+    pub(crate) fn generator_rate(mut self, generator_rate: GeneratorRateSpecification) -> Self {
+        self.generator_rate = generator_rate;
+        self
+    }
+
+    pub(crate) fn residual_payments(mut self, residual_payments: Residuals) -> Self {
+        self.residual_payments = Some(residual_payments);
+        self
+    }
+
+    pub(crate) fn cohort_id(mut self, cohort_id: &str) -> Self {
+        self.cohort_id = Some(cohort_id.to_string());
+        self
+    }
+
+    pub(crate) fn parties(&self) -> Vec<Party> {
+        [&self.agent_a, &self.agent_b].into_iter().chain(&self.generators).chain([&self.hbank]).cloned().collect()
+    }
+
+    pub(crate) fn keys(&self) -> Vec<(EntityId, SigningKey)> {
+        signing_keys(&self.parties())
+    }
+
+    pub(crate) fn key_of(&self, party: &Party) -> SigningKey {
+        self.keys().into_iter().find(|(entity_id, _)| entity_id == party.entity_id()).unwrap().1
+    }
+
+    pub(crate) fn agreement_type(&self) -> ContractCategory {
+        let (agent_a, agent_b, generators, h_bank) = (self.agent_a.clone(), self.agent_b.clone(), self.generators.clone(), self.hbank.clone());
+        let structure = match self.kind {
+            AgreementKind::DirectSale => TransactionLegalStructure::DirectSale { agent_a, agent_b, generators, h_bank },
+            AgreementKind::PurchaseAgreement => TransactionLegalStructure::PurchaseAgreement { agent_a, agent_b, generators, h_bank },
+            AgreementKind::LicensingAgreement => TransactionLegalStructure::LicensingAgreement { agent_a, agent_b, generators, h_bank },
+            AgreementKind::AccessAgreement => TransactionLegalStructure::AccessAgreement { agent_a, agent_b, generators, h_bank },
+            AgreementKind::SubscriptionAgreement => TransactionLegalStructure::SubscriptionAgreement { agent_a, agent_b, generators, h_bank },
+            other => panic!("{} is not an agreement between an agent A and an agent B", other),
+        };
+        ContractCategory::ThreePlusParty(structure)
+    }
+
+    // Every setting applied and every party's key registered, for tests that go on to change the builder.
+    pub(crate) fn builder(&self) -> HealthDataContractBuilder {
+        let mut builder = HealthDataContract::builder(self.contract_id.clone(), self.agreement_type())
+            .parties(self.parties())
+            .legal_framework(ContractLegalFramework::CommonLaw)
+            .terms(self.terms.clone())
+            .generator_rate(self.generator_rate.clone())
+            .privacy_level(DataPrivacyLevel::SafeHarbor);
+        if let Some(residual_payments) = &self.residual_payments {
+            builder = builder.residual_payments(residual_payments.clone());
+        }
+        if let Some(cohort_id) = &self.cohort_id {
+            builder = builder.cohort_id(cohort_id.clone());
+        }
+        builder = match &self.originator_consent {
+            Some(consent) => {
+                let mut individual = adult(&EntityId("I-O001".to_string()));
+                individual.set_consent(consent.clone());
+                builder
+                    .individual_contribution_level(IndividualContributionLevel::DataOnly)
+                    .individual_as(self.agent_a.entity_id().clone(), individual)
+            },
+            None => with_person(builder, &self.agent_a),
+        };
+        builder = with_person(builder, &self.agent_b).corporations(self.generators.iter().chain([&self.hbank]).map(corporation));
+        register_keys(builder, &self.keys())
+    }
+
+    pub(crate) fn build(&self) -> Result<HealthDataContract, ValidationReport> {
+        self.builder().build()
+    }
+
+    // Built and proposed, open for signature.
+    pub(crate) fn proposed(&self) -> HealthDataContract {
+        let mut contract = self.build().unwrap();
+        contract.propose().unwrap();
+        contract
+    }
+
+    pub(crate) fn executed(&self) -> HealthDataContract {
+        let mut contract = self.build().unwrap();
+        execute(&mut contract, &self.keys());
+        contract
+    }
+}
+
+fn with_person(builder: HealthDataContractBuilder, agent: &Party) -> HealthDataContractBuilder {
+    if agent.entity_id().0.starts_with("I-") {
+        builder.individual(adult(agent.entity_id()))
+    } else {
+        builder.corporation(corporation(agent))
+    }
+}
+
+// A GeneratorStorageAgreement between generator C-G001 and HBank C-HB001, which needs no individuals.
+pub(crate) fn generator_storage(contract_id: &str, privacy_level: DataPrivacyLevel) -> (HealthDataContract, Vec<(EntityId, SigningKey)>) {
+    let generator = party(PartyType::DataGenerator, "C-G001");
+    let hbank = party(PartyType::HBank, "C-HB001");
+    let keys = signing_keys(&[generator.clone(), hbank.clone()]);
+    let category = ContractCategory::TwoParty(TwoPartyLegalStructure::Storage_or_Exchange(
+        StorageExchangeLegalStructure::GeneratorStorageAgreement { generator: generator.clone(), h_bank: hbank.clone() },
+    ));
+    let builder = HealthDataContract::builder(contract_id, category)
+        .parties([generator, hbank])
+        .legal_framework(ContractLegalFramework::CommonLaw)
+        .generator_rate(GeneratorRateSpecification::KnowledgeRate(Rate::percent(5).unwrap()))
+        .privacy_level(privacy_level);
+    (register_keys(builder, &keys).build().unwrap(), keys)
+}